@@ -3,6 +3,32 @@ set -euo pipefail
 
 ELF_FILE="$1"
 QEMU_SERIAL_SOCK=$(mktemp)
+QEMU_SLIP_SOCK=$(mktemp)
+SLIP_TTY=$(mktemp -u)
+
+# The second UART carries IP packets framed with SLIP. It is attached to a
+# pseudo terminal turned into the host interface `sl<N>`, addressed
+# 192.168.77.1 while the firmware uses 192.168.77.2, so that the firmware
+# reaches a gateway listening on the host. Configuring the interface requires
+# root privileges, set WASMBED_SLIP=0 to run without a network.
+WASMBED_SLIP="${WASMBED_SLIP:-1}"
+HOST_ADDRESS=192.168.77.1
+DEVICE_ADDRESS=192.168.77.2
+
+if [ "$(id -u)" -eq 0 ]; then
+    SUDO=""
+else
+    SUDO="sudo"
+fi
+
+wait_for_socket() {
+    for i in $(seq 1 50); do
+        if socat -u /dev/null "UNIX-CONNECT:$1" 2>/dev/null; then
+            break
+        fi
+        sleep 0.05
+    done
+}
 
 qemu-system-riscv32                                \
     -nographic                                     \
@@ -10,20 +36,69 @@ qemu-system-riscv32                                \
     -machine sifive_e,revb=true                    \
     -d guest_errors                                \
     -serial unix:"$QEMU_SERIAL_SOCK",server,nowait \
+    -serial unix:"$QEMU_SLIP_SOCK",server,nowait   \
     -kernel "$ELF_FILE" &
 
 QEMU_PID=$!
+SOCAT_PID=""
+SLATTACH_PID=""
+
+cleanup() {
+    if [ -n "$SLATTACH_PID" ]; then
+        $SUDO kill "$SLATTACH_PID" 2>/dev/null || true
+    fi
+    if [ -n "$SOCAT_PID" ]; then
+        kill "$SOCAT_PID" 2>/dev/null || true
+    fi
+    kill "$QEMU_PID" 2>/dev/null || true
+    rm -f "$QEMU_SERIAL_SOCK" "$QEMU_SLIP_SOCK"
+}
+trap cleanup EXIT
 
-# Wait for socket readiness
-for i in $(seq 1 50); do
-    if socat -u /dev/null "UNIX-CONNECT:$QEMU_SERIAL_SOCK" 2>/dev/null; then
-        break
+wait_for_socket "$QEMU_SERIAL_SOCK"
+
+if [ "$WASMBED_SLIP" = 1 ]; then
+    wait_for_socket "$QEMU_SLIP_SOCK"
+
+    socat "PTY,link=$SLIP_TTY,rawer" "UNIX-CONNECT:$QEMU_SLIP_SOCK" &
+    SOCAT_PID=$!
+    for i in $(seq 1 50); do
+        if [ -e "$SLIP_TTY" ]; then
+            break
+        fi
+        sleep 0.05
+    done
+
+    INTERFACES=$(ls /sys/class/net)
+    $SUDO slattach -L -p slip -s 115200 "$(readlink -f "$SLIP_TTY")" &
+    SLATTACH_PID=$!
+
+    SLIP_INTERFACE=""
+    for i in $(seq 1 50); do
+        for interface in $(ls /sys/class/net); do
+            case "$interface" in
+                sl*)
+                    if ! echo "$INTERFACES" | grep -qx "$interface"; then
+                        SLIP_INTERFACE="$interface"
+                    fi
+                    ;;
+            esac
+        done
+        if [ -n "$SLIP_INTERFACE" ]; then
+            break
+        fi
+        sleep 0.05
+    done
+    if [ -z "$SLIP_INTERFACE" ]; then
+        echo "SLIP interface not created" >&2
+        exit 1
     fi
-    sleep 0.05
-done
+
+    $SUDO ip address add "$HOST_ADDRESS" peer "$DEVICE_ADDRESS" \
+        dev "$SLIP_INTERFACE"
+    # Matches the MTU of the firmware
+    $SUDO ip link set "$SLIP_INTERFACE" mtu 576 up
+fi
 
 # Connect to socket and read-only forward output
 socat -u "UNIX-CONNECT:$QEMU_SERIAL_SOCK" - | defmt-print -e "$ELF_FILE"
-
-kill "$QEMU_PID"
-rm -f "$QEMU_SERIAL_SOCK"
@@ -1,19 +1,40 @@
 // SPDX-License-Identifier: AGPL-3.0
 // Copyright © 2025 Wasmbed contributors
 
+//! Firmware of the HiFive1 Rev B emulated by QEMU, connecting to the gateway
+//! over a SLIP link.
+//!
+//! The firmware does not run applications. The `wasmbed-runtime`
+//! interpreter needs a heap, and the linear memory of a Wasm module is
+//! allocated in pages of 64 KiB, while the FE310 has 16 KiB of RAM in
+//! total. Devices running applications are simulated by
+//! `wasmbed-gateway-test-client`, which uses the same runtime.
+
 #![no_std]
 #![no_main]
 
-use defmt::info;
+mod net;
+mod slip;
+
+use defmt::{error, info};
 use defmt_serial as _;
 use embassy_executor::Spawner;
 use hifive1::{
     pin, hal::DeviceResources, hal::delay::Sleep, hal::e310x::Uart0,
-    hal::serial::Serial,
+    hal::e310x::Uart1, hal::serial::Serial,
 };
 use panic_halt as _;
+use smoltcp::wire::{IpAddress, IpEndpoint};
 use static_cell::StaticCell;
 
+use crate::net::{Buffers, HOST_ADDRESS, Network};
+use crate::slip::Slip;
+
+/// Gateway the firmware connects to, listening on the host end of the
+/// SLIP link.
+const GATEWAY_ENDPOINT: IpEndpoint =
+    IpEndpoint::new(IpAddress::Ipv4(HOST_ADDRESS), 4423);
+
 /// UART0 configured with IOF0 pins 17 (TX) and 16 (RX).
 type SerialPort = Serial<
     Uart0,
@@ -25,7 +46,20 @@ type SerialPort = Serial<
     >,
 >;
 
+/// UART1 configured with IOF0 pins 18 (TX) and 23 (RX), carrying SLIP.
+type NetworkPort = Serial<
+    Uart1,
+    hifive1::hal::gpio::gpio0::Pin18<
+        hifive1::hal::gpio::IOF0<hifive1::hal::gpio::NoInvert>,
+    >,
+    hifive1::hal::gpio::gpio0::Pin23<
+        hifive1::hal::gpio::IOF0<hifive1::hal::gpio::NoInvert>,
+    >,
+>;
+
 static SERIAL: StaticCell<SerialPort> = StaticCell::new();
+static BUFFERS: StaticCell<Buffers<'static>> = StaticCell::new();
+static NETWORK: StaticCell<Network<'static, NetworkPort>> = StaticCell::new();
 
 #[embassy_executor::main]
 async fn main(_spawner: Spawner) {
@@ -64,4 +98,20 @@ async fn main(_spawner: Spawner) {
 
     sleep.delay_ms(100);
     info!("Hello from RISC-V on QEMU!");
+
+    let network_port = Serial::new(
+        peripherals.UART1,
+        (pins.pin18.into_iof0(), pins.pin23.into_iof0()),
+        serial_baud_rate,
+        clocks,
+    );
+    let network = NETWORK.init(Network::new(
+        Slip::new(network_port),
+        BUFFERS.init(Buffers::new()),
+    ));
+
+    match network.connect(GATEWAY_ENDPOINT).await {
+        Ok(_stream) => info!("Connected to the gateway"),
+        Err(e) => error!("Unable to connect to the gateway: {}", e),
+    }
 }
@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! TCP/IP stack of the firmware, running over a [`Slip`] link to the host.
+
+use core::future::poll_fn;
+use core::task::Poll;
+
+use embedded_hal_nb::serial::{Read, Write};
+use embedded_io_async::{ErrorKind, ErrorType};
+use hifive1::hal::e310x::CLINT;
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet, SocketStorage};
+use smoltcp::socket::tcp;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{HardwareAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv4Cidr};
+
+use crate::slip::Slip;
+
+/// Address of the device on the SLIP link.
+pub const DEVICE_ADDRESS: Ipv4Cidr =
+    Ipv4Cidr::new(Ipv4Address::new(192, 168, 77, 2), 24);
+
+/// Host end of the SLIP link, through which every other address is routed.
+pub const HOST_ADDRESS: Ipv4Address = Ipv4Address::new(192, 168, 77, 1);
+
+/// Size of the receive and send buffers of the TCP socket.
+const TCP_BUFFER_SIZE: usize = 1024;
+
+/// How long a connection may stay silent before it is considered lost.
+const TCP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// First port of the range local ports are picked from.
+const EPHEMERAL_PORT: u16 = 49152;
+
+/// Number of ports in the range local ports are picked from.
+const EPHEMERAL_PORT_COUNT: u64 = 16384;
+
+/// Frequency of the machine timer.
+const MTIME_FREQUENCY: u64 = 32768;
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Error {
+    /// The remote endpoint cannot be reached from the device
+    Unaddressable,
+    /// The connection could not be established, or was lost
+    ConnectionReset,
+}
+
+impl embedded_io_async::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Unaddressable => ErrorKind::AddrNotAvailable,
+            Error::ConnectionReset => ErrorKind::ConnectionReset,
+        }
+    }
+}
+
+/// Current time, read from the machine timer.
+pub fn now() -> Instant {
+    let ticks = CLINT::mtimer().mtime.read();
+    let micros = ticks
+        .saturating_mul(1_000_000)
+        .checked_div(MTIME_FREQUENCY)
+        .unwrap_or_default();
+    Instant::from_micros(i64::try_from(micros).unwrap_or(i64::MAX))
+}
+
+/// Memory used by the network stack, kept out of the executor's task arena.
+pub struct Buffers<'a> {
+    sockets: [SocketStorage<'a>; 1],
+    rx: [u8; TCP_BUFFER_SIZE],
+    tx: [u8; TCP_BUFFER_SIZE],
+}
+
+impl Buffers<'_> {
+    pub const fn new() -> Self {
+        Self {
+            sockets: [SocketStorage::EMPTY],
+            rx: [0; TCP_BUFFER_SIZE],
+            tx: [0; TCP_BUFFER_SIZE],
+        }
+    }
+}
+
+/// Interface of the device on the SLIP link, with a single TCP socket.
+///
+/// The stack only makes progress while it is polled, which the streams do
+/// whenever they wait for the socket.
+pub struct Network<'a, S> {
+    device: Slip<S>,
+    interface: Interface,
+    sockets: SocketSet<'a>,
+    socket: SocketHandle,
+}
+
+impl<'a, S: Read<u8> + Write<u8>> Network<'a, S> {
+    pub fn new(mut device: Slip<S>, buffers: &'a mut Buffers<'a>) -> Self {
+        let mut config = Config::new(HardwareAddress::Ip);
+        config.random_seed = CLINT::mtimer().mtime.read();
+
+        let mut interface = Interface::new(config, &mut device, now());
+        interface.update_ip_addrs(|addresses| {
+            let _ = addresses.push(IpCidr::Ipv4(DEVICE_ADDRESS));
+        });
+        let _ = interface.routes_mut().add_default_ipv4_route(HOST_ADDRESS);
+
+        let mut socket = tcp::Socket::new(
+            tcp::SocketBuffer::new(&mut buffers.rx[..]),
+            tcp::SocketBuffer::new(&mut buffers.tx[..]),
+        );
+        socket.set_timeout(Some(TCP_TIMEOUT));
+        let mut sockets = SocketSet::new(&mut buffers.sockets[..]);
+        let socket = sockets.add(socket);
+
+        Self {
+            device,
+            interface,
+            sockets,
+            socket,
+        }
+    }
+
+    /// Processes the packets received and sends the ones queued.
+    pub fn poll(&mut self) {
+        self.interface
+            .poll(now(), &mut self.device, &mut self.sockets);
+    }
+
+    /// Opens a TCP connection to `remote`, waiting until it is established.
+    ///
+    /// The connection is aborted when the returned stream is dropped.
+    pub async fn connect(
+        &mut self,
+        remote: IpEndpoint,
+    ) -> Result<TcpStream<'_, 'a, S>, Error> {
+        let seed = CLINT::mtimer().mtime.read() % EPHEMERAL_PORT_COUNT;
+        let local_port =
+            EPHEMERAL_PORT.saturating_add(u16::try_from(seed).unwrap_or(0));
+
+        let socket = self.sockets.get_mut::<tcp::Socket>(self.socket);
+        socket
+            .connect(self.interface.context(), remote, local_port)
+            .map_err(|_| Error::Unaddressable)?;
+
+        let mut stream = TcpStream { network: self };
+        stream
+            .wait(|socket| match socket.state() {
+                tcp::State::Established => Some(Ok(())),
+                tcp::State::Closed => Some(Err(Error::ConnectionReset)),
+                _ => None,
+            })
+            .await?;
+        Ok(stream)
+    }
+}
+
+/// TCP connection opened with [`Network::connect`].
+pub struct TcpStream<'n, 'a, S: Read<u8> + Write<u8>> {
+    network: &'n mut Network<'a, S>,
+}
+
+impl<S: Read<u8> + Write<u8>> TcpStream<'_, '_, S> {
+    /// Polls the network until `f` returns a result for the socket.
+    async fn wait<T>(
+        &mut self,
+        mut f: impl FnMut(&mut tcp::Socket) -> Option<T>,
+    ) -> T {
+        poll_fn(|cx| {
+            self.network.poll();
+            let socket = self
+                .network
+                .sockets
+                .get_mut::<tcp::Socket>(self.network.socket);
+            match f(socket) {
+                Some(result) => Poll::Ready(result),
+                None => {
+                    // The serial port cannot wake the executor, poll again.
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                },
+            }
+        })
+        .await
+    }
+}
+
+impl<S: Read<u8> + Write<u8>> Drop for TcpStream<'_, '_, S> {
+    fn drop(&mut self) {
+        let network = &mut *self.network;
+        network
+            .sockets
+            .get_mut::<tcp::Socket>(network.socket)
+            .abort();
+        network.poll();
+    }
+}
+
+impl<S: Read<u8> + Write<u8>> ErrorType for TcpStream<'_, '_, S> {
+    type Error = Error;
+}
+
+impl<S: Read<u8> + Write<u8>> embedded_io_async::Read for TcpStream<'_, '_, S> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.wait(|socket| {
+            if socket.can_recv() {
+                Some(socket.recv_slice(buf).map_err(|_| Error::ConnectionReset))
+            } else if !socket.may_recv() {
+                // The peer closed the connection.
+                Some(Ok(0))
+            } else {
+                None
+            }
+        })
+        .await
+    }
+}
+
+impl<S: Read<u8> + Write<u8>> embedded_io_async::Write
+    for TcpStream<'_, '_, S>
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.wait(|socket| {
+            if socket.can_send() {
+                Some(socket.send_slice(buf).map_err(|_| Error::ConnectionReset))
+            } else if !socket.may_send() {
+                Some(Err(Error::ConnectionReset))
+            } else {
+                None
+            }
+        })
+        .await
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        self.wait(|socket| {
+            if socket.send_queue() == 0 {
+                Some(Ok(()))
+            } else if !socket.may_send() {
+                Some(Err(Error::ConnectionReset))
+            } else {
+                None
+            }
+        })
+        .await
+    }
+}
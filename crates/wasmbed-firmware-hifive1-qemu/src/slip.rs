@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! IP over a serial line, framed as described in RFC 1055.
+
+use embedded_hal_nb::serial::{Read, Write};
+use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+/// Largest IP packet exchanged over the link, in bytes.
+pub const MTU: usize = 576;
+
+const END: u8 = 0xc0;
+const ESC: u8 = 0xdb;
+const ESC_END: u8 = 0xdc;
+const ESC_ESC: u8 = 0xdd;
+
+/// Network device sending and receiving SLIP frames over a serial port.
+///
+/// The serial port is polled: bytes are only read while smoltcp polls the
+/// interface, and the port must buffer what arrives in between.
+pub struct Slip<S> {
+    serial: S,
+    rx: [u8; MTU],
+    rx_len: usize,
+    /// The previous byte received was `ESC`
+    escaped: bool,
+    /// The frame being received exceeds the MTU and is dropped
+    overflow: bool,
+    tx: [u8; MTU],
+}
+
+impl<S: Read<u8> + Write<u8>> Slip<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            rx: [0; MTU],
+            rx_len: 0,
+            escaped: false,
+            overflow: false,
+            tx: [0; MTU],
+        }
+    }
+
+    /// Reads the bytes available on the serial port, returning the length of
+    /// the frame completed by one of them, if any.
+    fn read_frame(&mut self) -> Option<usize> {
+        while let Ok(byte) = self.serial.read() {
+            let byte = match (self.escaped, byte) {
+                (false, END) => {
+                    let len = core::mem::take(&mut self.rx_len);
+                    if !core::mem::take(&mut self.overflow) && len > 0 {
+                        return Some(len);
+                    }
+                    continue;
+                },
+                (false, ESC) => {
+                    self.escaped = true;
+                    continue;
+                },
+                (true, ESC_END) => END,
+                (true, ESC_ESC) => ESC,
+                (_, byte) => byte,
+            };
+            self.escaped = false;
+            match self.rx.get_mut(self.rx_len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.rx_len = self.rx_len.saturating_add(1);
+                },
+                None => self.overflow = true,
+            }
+        }
+        None
+    }
+}
+
+impl<S: Read<u8> + Write<u8>> Device for Slip<S> {
+    type RxToken<'a>
+        = RxToken<'a>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a, S>
+    where
+        Self: 'a;
+
+    fn receive(
+        &mut self,
+        _timestamp: Instant,
+    ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let len = self.read_frame()?;
+        let rx = RxToken(self.rx.get(..len)?);
+        let tx = TxToken {
+            serial: &mut self.serial,
+            buf: &mut self.tx,
+        };
+        Some((rx, tx))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(TxToken {
+            serial: &mut self.serial,
+            buf: &mut self.tx,
+        })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.medium = Medium::Ip;
+        capabilities.max_transmission_unit = MTU;
+        capabilities
+    }
+}
+
+pub struct RxToken<'a>(&'a [u8]);
+
+impl phy::RxToken for RxToken<'_> {
+    fn consume<R, F: FnOnce(&[u8]) -> R>(self, f: F) -> R {
+        f(self.0)
+    }
+}
+
+pub struct TxToken<'a, S> {
+    serial: &'a mut S,
+    buf: &'a mut [u8; MTU],
+}
+
+impl<S: Write<u8>> phy::TxToken for TxToken<'_, S> {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+        let (packet, _) = self.buf.split_at_mut(len.min(MTU));
+        let result = f(packet);
+
+        // Errors are dropped like any lost packet, TCP retransmits.
+        let _ = nb::block!(self.serial.write(END));
+        for byte in packet.iter().copied() {
+            let _ = match byte {
+                END => nb::block!(self.serial.write(ESC))
+                    .and_then(|()| nb::block!(self.serial.write(ESC_END))),
+                ESC => nb::block!(self.serial.write(ESC))
+                    .and_then(|()| nb::block!(self.serial.write(ESC_ESC))),
+                byte => nb::block!(self.serial.write(byte)),
+            };
+        }
+        let _ = nb::block!(self.serial.write(END));
+
+        result
+    }
+}
@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use derive_more::{Display, Error};
+use reqwest::{Response, StatusCode};
+use rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use rustls::client::WebPkiServerVerifier;
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore,
+    SignatureScheme,
+};
+use rustls_pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    ConnectedDevice, DEVICES_PATH, ErrorResponse, OPERATIONS_PATH, Operation,
+    OperationId, OperationRequest,
+};
+
+#[derive(Debug, Display, Error)]
+pub enum ClientError {
+    #[display("Invalid TLS configuration: {_0}")]
+    Tls(rustls::Error),
+    #[display("HTTP error: {_0}")]
+    Http(reqwest::Error),
+    #[display("Gateway replied {status}: {message}")]
+    Api {
+        status: StatusCode,
+        message: String,
+    },
+}
+
+impl ClientError {
+    /// Whether the gateway answered that the requested resource, e.g. an
+    /// operation, does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Self::Api {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }
+        )
+    }
+}
+
+/// Client of the gateway API, authenticating with a client certificate.
+#[derive(Clone)]
+pub struct GatewayClient {
+    http: reqwest::Client,
+}
+
+impl GatewayClient {
+    /// `gateway_ca` is the authority that issued the certificates of the
+    /// gateways. Gateways are reached by address, so the names in their
+    /// certificates are not verified.
+    pub fn new(
+        gateway_ca: CertificateDer<'static>,
+        certificate: CertificateDer<'static>,
+        private_key: PrivateKeyDer<'static>,
+    ) -> Result<Self, ClientError> {
+        let mut root_store = RootCertStore::empty();
+        root_store.add(gateway_ca).map_err(ClientError::Tls)?;
+
+        let verifier = WebPkiServerVerifier::builder(Arc::new(root_store))
+            .build()
+            .map_err(|e| {
+                ClientError::Tls(rustls::Error::General(e.to_string()))
+            })?;
+
+        let mut config = ClientConfig::builder()
+            .with_root_certificates(RootCertStore::empty())
+            .with_client_auth_cert(vec![certificate], private_key)
+            .map_err(ClientError::Tls)?;
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(IgnoreServerName(verifier)));
+
+        let http = reqwest::Client::builder()
+            .use_preconfigured_tls(config)
+            .build()
+            .map_err(ClientError::Http)?;
+
+        Ok(Self { http })
+    }
+
+    pub async fn devices(
+        &self,
+        gateway: SocketAddr,
+    ) -> Result<Vec<ConnectedDevice>, ClientError> {
+        let response = self
+            .http
+            .get(format!("https://{gateway}{DEVICES_PATH}"))
+            .send()
+            .await
+            .map_err(ClientError::Http)?;
+        parse(response).await
+    }
+
+    pub async fn submit(
+        &self,
+        gateway: SocketAddr,
+        request: &OperationRequest,
+    ) -> Result<Operation, ClientError> {
+        let response = self
+            .http
+            .post(format!("https://{gateway}{OPERATIONS_PATH}"))
+            .json(request)
+            .send()
+            .await
+            .map_err(ClientError::Http)?;
+        parse(response).await
+    }
+
+    pub async fn operation(
+        &self,
+        gateway: SocketAddr,
+        id: OperationId,
+    ) -> Result<Operation, ClientError> {
+        let response = self
+            .http
+            .get(format!("https://{gateway}{OPERATIONS_PATH}/{id}"))
+            .send()
+            .await
+            .map_err(ClientError::Http)?;
+        parse(response).await
+    }
+}
+
+async fn parse<T: DeserializeOwned>(
+    response: Response,
+) -> Result<T, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return response.json().await.map_err(ClientError::Http);
+    }
+
+    let message = match response.json::<ErrorResponse>().await {
+        Ok(error) => error.message,
+        Err(_) => status.to_string(),
+    };
+    Err(ClientError::Api { status, message })
+}
+
+/// Verifies server certificates against the gateway authority, accepting
+/// any server name.
+#[derive(Debug)]
+struct IgnoreServerName(Arc<WebPkiServerVerifier>);
+
+impl ServerCertVerifier for IgnoreServerName {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.0.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp,
+            now,
+        ) {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName
+                | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.0.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.supported_verify_schemes()
+    }
+}
@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Internal API exposed by each gateway to the controller, as JSON over HTTP
+//! with mutual TLS.
+
+#[cfg(feature = "client")]
+mod client;
+
+use std::fmt;
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+use wasmbed_types::{DeviceClass, PublicKey};
+
+#[cfg(feature = "client")]
+pub use client::{ClientError, GatewayClient};
+
+/// Lists the devices connected to the gateway, as [`ConnectedDevice`]s.
+pub const DEVICES_PATH: &str = "/v0/devices";
+
+/// Submits an [`OperationRequest`], returning the [`Operation`] started.
+/// `OPERATIONS_PATH/{id}` returns the current state of an operation.
+pub const OPERATIONS_PATH: &str = "/v0/operations";
+
+/// A device connected to the gateway
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectedDevice {
+    pub public_key: PublicKey<'static>,
+    /// Identifier of the connection, unique for the lifetime of the gateway
+    pub connection_id: u64,
+    pub peer_addr: SocketAddr,
+}
+
+/// Request sent to a connected device on behalf of the controller
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum OperationRequest {
+    /// Deploy a Wasm module and start it
+    Deploy {
+        device: PublicKey<'static>,
+        /// Class of the device, bounding the modules it accepts
+        #[serde(default)]
+        device_class: DeviceClass,
+        /// Host modules advertised by the device, all of those of the
+        /// device class if unknown
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        host_modules: Option<Vec<String>>,
+        /// Largest message the device accepts, in bytes, the largest frame
+        /// of the protocol if unknown
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_message_size: Option<u32>,
+        app_id: String,
+        module: Module,
+        entry_point: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_memory_pages: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fuel: Option<u64>,
+    },
+    /// Stop a running application
+    Stop {
+        device: PublicKey<'static>,
+        app_id: String,
+    },
+}
+
+/// Wasm module of a deployment
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Module {
+    /// Module bytecode, base64 encoded
+    Bytecode(#[serde(with = "base64_bytes")] Vec<u8>),
+    /// OCI reference of an artifact holding the module, pulled by the
+    /// gateway
+    Image(String),
+}
+
+impl OperationRequest {
+    pub fn kind(&self) -> OperationKind {
+        match self {
+            Self::Deploy { .. } => OperationKind::Deploy,
+            Self::Stop { .. } => OperationKind::Stop,
+        }
+    }
+
+    pub fn device(&self) -> &PublicKey<'static> {
+        match self {
+            Self::Deploy { device, .. } | Self::Stop { device, .. } => device,
+        }
+    }
+
+    pub fn app_id(&self) -> &str {
+        match self {
+            Self::Deploy { app_id, .. } | Self::Stop { app_id, .. } => app_id,
+        }
+    }
+}
+
+/// Identifier of an operation, unique for the lifetime of the gateway
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct OperationId(pub u64);
+
+impl fmt::Display for OperationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An operation started by an [`OperationRequest`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    pub id: OperationId,
+    pub kind: OperationKind,
+    pub device: PublicKey<'static>,
+    pub app_id: String,
+    pub state: OperationState,
+    /// Why the operation failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationKind {
+    Deploy,
+    Stop,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationState {
+    /// Waiting for the device to reply
+    Pending,
+    Succeeded,
+    Failed,
+    /// The module image could not be pulled
+    PullFailed,
+}
+
+/// Body of the responses with an error status
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub message: String,
+}
+
+mod base64_bytes {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        bytes: &[u8],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn deploy_request_format() {
+        let request = OperationRequest::Deploy {
+            device: PublicKey::from(vec![1, 2, 3]),
+            device_class: DeviceClass::Medium,
+            host_modules: None,
+            max_message_size: None,
+            app_id: "app-0".into(),
+            module: Module::Bytecode(b"\0asm".to_vec()),
+            entry_point: "_start".into(),
+            max_memory_pages: Some(1),
+            fuel: None,
+        };
+        let value = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "type": "deploy",
+                "device": "AQID",
+                "deviceClass": "medium",
+                "appId": "app-0",
+                "module": { "bytecode": "AGFzbQ==" },
+                "entryPoint": "_start",
+                "maxMemoryPages": 1,
+            })
+        );
+        assert_eq!(
+            serde_json::from_str::<OperationRequest>(&value.to_string())
+                .unwrap(),
+            request
+        );
+    }
+}
@@ -4,9 +4,15 @@
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Error, Result};
 use clap::Parser;
+use embedded_io_async::{BufRead, ErrorType, Read, Write};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite,
+    AsyncWriteExt, BufReader,
+};
 use tokio::net::TcpStream;
 use rustls::{DigitallySignedStruct, RootCertStore};
 use rustls::client::{ClientConfig, WebPkiServerVerifier};
@@ -16,6 +22,23 @@ use rustls::client::danger::{
 use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
 use tokio_rustls::TlsConnector;
 
+use wasmbed_protocol::{
+    CapabilitiesRef, ClientMessageRef, HostModules, ServerMessageRef,
+};
+use wasmbed_protocol_client::{Client, Handler};
+use wasmbed_runtime::{Platform, RUNTIME_VERSION, Runtime};
+
+/// Size of the buffers messages are encoded into and decoded from.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Memory the applications deployed to the test client may use, enforced
+/// by its runtime.
+const APPLICATION_MEMORY: usize = 4 * 1024 * 1024;
+
+/// Interval between two heartbeats, well within the idle timeout of the
+/// gateway.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Args {
@@ -93,6 +116,82 @@ impl ServerCertVerifier for NoServerNameVerification {
     }
 }
 
+/// Adapts a tokio stream to the traits of `embedded-io-async`.
+struct Stream<T>(T);
+
+impl<T> ErrorType for Stream<T> {
+    type Error = std::io::Error;
+}
+
+impl<T: AsyncRead + Unpin> Read for Stream<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await
+    }
+}
+
+impl<T: AsyncBufRead + Unpin> BufRead for Stream<T> {
+    async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+        self.0.fill_buf().await
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt);
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Write for Stream<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.0.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().await
+    }
+}
+
+/// Runs the applications deployed to the test client on the host.
+struct Host {
+    booted: Instant,
+}
+
+impl Platform for Host {
+    fn log(&mut self, message: &str) {
+        println!("Application: {message}");
+    }
+
+    fn uptime_ms(&self) -> u64 {
+        u64::try_from(self.booted.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+}
+
+/// Device simulated by the test client, printing the messages it
+/// exchanges with the gateway.
+struct Device {
+    runtime: Runtime<Host>,
+}
+
+impl Handler for Device {
+    async fn handle<'a>(
+        &'a mut self,
+        message: ServerMessageRef<'a>,
+    ) -> Option<ClientMessageRef<'a>> {
+        println!("Received {message:?}");
+        let reply = self.runtime.handle(message).await;
+        if let Some(reply) = &reply {
+            println!("Replying {reply:?}");
+        }
+        reply
+    }
+}
+
+/// What the test client waits for once connected.
+enum Event {
+    Message,
+    Heartbeat,
+    Step,
+    Interrupted,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
@@ -145,12 +244,70 @@ async fn main() -> Result<()> {
         .await
         .context("failed to connect")?;
 
-    let _tls_stream = connector
+    let tls_stream = connector
         .connect("example.com".try_into()?, stream)
         .await
         .context("TLS handshake failed")?;
 
     println!("Successfully connected and verified TLS");
 
+    let mut rx = vec![0u8; BUFFER_SIZE];
+    let mut tx = vec![0u8; BUFFER_SIZE];
+    let stream = Stream(BufReader::new(tls_stream));
+    let mut client = Client::new(stream, &mut rx, &mut tx);
+    let booted = Instant::now();
+    let mut device = Device {
+        runtime: Runtime::new(Host { booted }, APPLICATION_MEMORY),
+    };
+
+    let version = client.negotiate(&mut device).await?;
+    println!("Negotiated protocol version {version:?}");
+
+    let capabilities = CapabilitiesRef {
+        firmware_version: env!("CARGO_PKG_VERSION"),
+        runtime_version: RUNTIME_VERSION,
+        free_memory: u32::try_from(device.runtime.memory())?,
+        host_modules: HostModules::Slice(&["wasmbed"]),
+        max_message_size: u32::try_from(BUFFER_SIZE)?,
+    };
+    client.hello(capabilities, &mut device).await?;
+    println!("Running the deployed applications until interrupted");
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        let wake_at = device
+            .runtime
+            .wake_at()
+            .and_then(|uptime| {
+                booted.checked_add(Duration::from_millis(uptime))
+            })
+            .map(tokio::time::Instant::from_std);
+        let event = tokio::select! {
+            readable = client.readable() => readable.map(|()| Event::Message)?,
+            _ = heartbeat.tick() => Event::Heartbeat,
+            _ = tokio::time::sleep_until(wake_at.unwrap_or_else(
+                tokio::time::Instant::now
+            )), if wake_at.is_some() => Event::Step,
+            _ = tokio::signal::ctrl_c() => Event::Interrupted,
+        };
+        match event {
+            Event::Message => client.dispatch(&mut device).await?,
+            Event::Heartbeat => {
+                client.heartbeat(&mut device).await?;
+                println!("Heartbeat acknowledged");
+            },
+            Event::Step => {
+                if let Some(message) = device.runtime.step() {
+                    println!("Sending {message:?}");
+                    client.send(message).await?;
+                }
+            },
+            Event::Interrupted => break,
+        }
+    }
+
+    client.disconnect("Test completed", &mut device).await?;
+    println!("Disconnected");
+
     Ok(())
 }
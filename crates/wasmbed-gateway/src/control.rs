@@ -0,0 +1,353 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use hyper::server::conn::http1;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use tokio::net::TcpListener;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use wasmbed_gateway_api::{
+    ConnectedDevice, DEVICES_PATH, ErrorResponse, Module as ModuleSource,
+    OPERATIONS_PATH, Operation, OperationId, OperationRequest, OperationState,
+};
+use wasmbed_oci::{Puller, Reference};
+use wasmbed_protocol::{
+    ClientMessage, MAX_FRAME_SIZE, ResourceLimits, ServerMessage,
+    ServerMessageRef, chunk_capacity, envelope_len,
+};
+use wasmbed_protocol_server::{Module, Server, ServerHandler, TransferConfig};
+use wasmbed_wasm::{Policy, validate};
+
+use crate::operations::Operations;
+
+/// How long finished operations can be queried.
+const OPERATION_RETENTION: Duration = Duration::from_secs(600);
+
+/// Once shutdown is requested, how long the connections may take to finish
+/// the requests in progress before being closed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct ControlConfig {
+    pub bind_addr: SocketAddr,
+    /// Accepts the controller certificates
+    pub acceptor: TlsAcceptor,
+    /// How long a device is given to reply to an operation
+    pub operation_timeout: Duration,
+    /// Pulls the module images of deployments
+    pub puller: Puller,
+    pub shutdown: CancellationToken,
+}
+
+/// Serves the control API, through which the controller drives the devices
+/// connected to `server`.
+pub async fn run<H: ServerHandler>(
+    config: ControlConfig,
+    server: Arc<Server<H>>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(config.bind_addr).await?;
+    let router = router(Control {
+        server,
+        operations: Operations::new(OPERATION_RETENTION),
+        operation_timeout: config.operation_timeout,
+        puller: config.puller,
+    });
+
+    info!("Control API listening on {}", config.bind_addr);
+
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            result = listener.accept() => {
+                match result {
+                    Ok((stream, peer_addr)) => {
+                        let acceptor = config.acceptor.clone();
+                        let service = TowerToHyperService::new(router.clone());
+                        let shutdown = config.shutdown.clone();
+                        connections.spawn(async move {
+                            let stream = match acceptor.accept(stream).await {
+                                Ok(stream) => stream,
+                                Err(e) => {
+                                    warn!(
+                                        "Control API handshake with \
+                                         {peer_addr} failed: {e}"
+                                    );
+                                    return;
+                                },
+                            };
+                            let connection = http1::Builder::new()
+                                .serve_connection(TokioIo::new(stream), service);
+                            let mut connection = std::pin::pin!(connection);
+                            let result = tokio::select! {
+                                result = connection.as_mut() => result,
+                                _ = shutdown.cancelled() => {
+                                    // Finish the request in progress, if any.
+                                    connection.as_mut().graceful_shutdown();
+                                    connection.await
+                                }
+                            };
+                            if let Err(e) = result {
+                                debug!("Control API connection error: {e}");
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("Failed to accept control connection: {}", e);
+                    }
+                }
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            _ = config.shutdown.cancelled() => break,
+        }
+    }
+
+    drop(listener);
+    let drained = tokio::time::timeout(SHUTDOWN_TIMEOUT, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        warn!("Closing {} control API connections", connections.len());
+        connections.shutdown().await;
+    }
+    info!("Control API stopped");
+
+    Ok(())
+}
+
+struct Control<H: ServerHandler> {
+    server: Arc<Server<H>>,
+    operations: Operations,
+    operation_timeout: Duration,
+    puller: Puller,
+}
+
+fn router<H: ServerHandler>(control: Control<H>) -> Router {
+    Router::new()
+        .route(DEVICES_PATH, get(devices::<H>))
+        .route(OPERATIONS_PATH, post(submit::<H>))
+        .route(&format!("{OPERATIONS_PATH}/{{id}}"), get(operation::<H>))
+        .with_state(Arc::new(control))
+}
+
+async fn devices<H: ServerHandler>(
+    State(control): State<Arc<Control<H>>>,
+) -> Json<Vec<ConnectedDevice>> {
+    let devices = control
+        .server
+        .clients()
+        .await
+        .into_iter()
+        .map(|connection| ConnectedDevice {
+            public_key: connection.client_key().clone(),
+            connection_id: connection.id().get(),
+            peer_addr: connection.peer_addr(),
+        })
+        .collect();
+    Json(devices)
+}
+
+async fn submit<H: ServerHandler>(
+    State(control): State<Arc<Control<H>>>,
+    Json(request): Json<OperationRequest>,
+) -> Result<(StatusCode, Json<Operation>), ApiError> {
+    let connected = control
+        .server
+        .clients()
+        .await
+        .iter()
+        .any(|connection| connection.client_key() == request.device());
+    if !connected {
+        return Err(ApiError(
+            StatusCode::NOT_FOUND,
+            format!("Device not connected: {}", request.device()),
+        ));
+    }
+
+    let operation = control.operations.start(&request);
+    info!(
+        "Starting operation {}: {:?} {} on {}",
+        operation.id, operation.kind, operation.app_id, operation.device
+    );
+
+    let id = operation.id;
+    tokio::spawn(async move {
+        let (state, reason) = match execute(&control, request).await {
+            Ok(()) => (OperationState::Succeeded, None),
+            Err(Failure::Pull(reason)) => {
+                warn!("Operation {id} failed to pull the module: {reason}");
+                (OperationState::PullFailed, Some(reason))
+            },
+            Err(Failure::Rejected(reason)) => {
+                warn!("Operation {id} rejected the module: {reason}");
+                (OperationState::Failed, Some(reason))
+            },
+            Err(Failure::Device(reason)) => {
+                warn!("Operation {id} failed: {reason}");
+                (OperationState::Failed, Some(reason))
+            },
+        };
+        control.operations.finish(id, state, reason);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+async fn operation<H: ServerHandler>(
+    State(control): State<Arc<Control<H>>>,
+    Path(id): Path<u64>,
+) -> Result<Json<Operation>, ApiError> {
+    control
+        .operations
+        .get(OperationId(id))
+        .map(Json)
+        .ok_or_else(|| {
+            ApiError(StatusCode::NOT_FOUND, format!("Unknown operation: {id}"))
+        })
+}
+
+/// Why an operation failed
+enum Failure {
+    /// The module image could not be pulled
+    Pull(String),
+    /// The module is not accepted by the device policy
+    Rejected(String),
+    /// The device could not be reached or reported a failure
+    Device(String),
+}
+
+/// Sends `request` to its device and waits for the outcome.
+async fn execute<H: ServerHandler>(
+    control: &Control<H>,
+    request: OperationRequest,
+) -> Result<(), Failure> {
+    let server = &control.server;
+    let timeout = control.operation_timeout;
+
+    match request {
+        OperationRequest::Deploy {
+            device,
+            device_class,
+            host_modules,
+            max_message_size,
+            app_id,
+            module,
+            entry_point,
+            max_memory_pages,
+            fuel,
+        } => {
+            let limits = ResourceLimits {
+                max_memory_pages,
+                fuel,
+            };
+            let bytecode = match module {
+                ModuleSource::Bytecode(bytecode) => bytecode,
+                ModuleSource::Image(image) => pull(&control.puller, &image)
+                    .await
+                    .map_err(Failure::Pull)?,
+            };
+
+            let policy = Policy::for_class(device_class)
+                .limit_memory_pages(max_memory_pages)
+                .restrict_host_modules(host_modules.as_deref());
+            validate(&bytecode, &entry_point, &policy)
+                .map_err(|e| Failure::Rejected(e.to_string()))?;
+
+            // Modules that do not fit in a single message are transferred in
+            // chunks.
+            let max_message_size = max_message_size
+                .and_then(|size| usize::try_from(size).ok())
+                .map_or(MAX_FRAME_SIZE, |size| size.min(MAX_FRAME_SIZE));
+            let inline_size =
+                envelope_len(&ServerMessageRef::DeployApplication {
+                    app_id: &app_id,
+                    bytecode: &bytecode,
+                    entry_point: &entry_point,
+                    limits,
+                });
+            if inline_size > max_message_size {
+                let chunk_size = chunk_capacity(&app_id, max_message_size)
+                    .ok_or_else(|| {
+                        Failure::Rejected(format!(
+                            "Device accepts messages of at most \
+                             {max_message_size} bytes"
+                        ))
+                    })?;
+                let config = TransferConfig {
+                    chunk_size,
+                    ..TransferConfig::default()
+                };
+                let module = Module {
+                    app_id,
+                    bytecode,
+                    entry_point,
+                    limits,
+                };
+                return server
+                    .transfer(&device, &module, &config)
+                    .await
+                    .map_err(|e| Failure::Device(e.to_string()));
+            }
+
+            let message = ServerMessage::DeployApplication {
+                app_id,
+                bytecode,
+                entry_point,
+                limits,
+            };
+            match server.request(&device, message, timeout).await {
+                Ok(ClientMessage::ApplicationDeployed { .. }) => Ok(()),
+                Ok(reply) => Err(failure(reply)),
+                Err(e) => Err(Failure::Device(e.to_string())),
+            }
+        },
+        OperationRequest::Stop { device, app_id } => {
+            let message = ServerMessage::StopApplication { app_id };
+            match server.request(&device, message, timeout).await {
+                Ok(ClientMessage::ApplicationStopped { .. }) => Ok(()),
+                Ok(reply) => Err(failure(reply)),
+                Err(e) => Err(Failure::Device(e.to_string())),
+            }
+        },
+    }
+}
+
+async fn pull(puller: &Puller, image: &str) -> Result<Vec<u8>, String> {
+    let reference: Reference = image.parse().map_err(|e| format!("{e}"))?;
+    let pulled = puller
+        .pull(&reference)
+        .await
+        .map_err(|e| format!("Unable to pull {reference}: {e}"))?;
+    info!("Pulled module {} of {reference}", pulled.digest);
+    Ok(pulled.bytecode)
+}
+
+fn failure(reply: ClientMessage) -> Failure {
+    Failure::Device(match reply {
+        ClientMessage::ApplicationFailed { reason, .. } => reason,
+        ClientMessage::Error { code, detail, .. } => {
+            format!("{code}: {detail}")
+        },
+        reply => format!("Unexpected reply: {reply:?}"),
+    })
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorResponse { message: self.1 })).into_response()
+    }
+}
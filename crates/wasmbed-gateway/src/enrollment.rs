@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::Utc;
+use kube::{Api, ResourceExt};
+use kube::api::DeleteParams;
+use tokio::sync::Mutex;
+
+use wasmbed_k8s_resource::{Device, DevicePhase, DeviceStatusUpdate, Enrollment};
+use wasmbed_types::{GatewayReference, PublicKey};
+
+/// Unregistered devices connected to this gateway while pairing is enabled.
+///
+/// Such devices are authorized only to send `Enroll`. Enrolling creates a
+/// Device for the public key in the `Enrolling` phase, which becomes
+/// `Enrolled` once the acknowledgment has been sent. The device is then
+/// expected to reconnect as a registered device. A Device still `Enrolling`
+/// when its device disconnects is deleted, the device having possibly
+/// never learned it was enrolled.
+#[derive(Clone)]
+pub struct Enrollments {
+    devices: Api<Device>,
+    enrollments: Api<Enrollment>,
+    gateway_reference: GatewayReference,
+    /// Admitted devices, with the name of their Device once enrolling
+    pending: Arc<Mutex<HashMap<PublicKey<'static>, Option<String>>>>,
+}
+
+impl Enrollments {
+    pub fn new(
+        devices: Api<Device>,
+        enrollments: Api<Enrollment>,
+        gateway_reference: GatewayReference,
+    ) -> Self {
+        Self {
+            devices,
+            enrollments,
+            gateway_reference,
+            pending: Default::default(),
+        }
+    }
+
+    /// Accepts the connection of an unregistered device if an Enrollment is
+    /// open. Returns whether the device may enroll.
+    pub async fn admit(
+        &self,
+        public_key: PublicKey<'static>,
+    ) -> Result<bool, kube::Error> {
+        let open =
+            Enrollment::find_open(self.enrollments.clone(), Utc::now()).await?;
+        if open.is_some() {
+            self.pending.lock().await.insert(public_key, None);
+        }
+        Ok(open.is_some())
+    }
+
+    /// Creates the Device of an admitted device, in the `Enrolling` phase.
+    /// Returns `None` if the device was not admitted or if the enrollment
+    /// closed in the meantime.
+    pub async fn enroll(
+        &self,
+        public_key: &PublicKey<'static>,
+    ) -> Result<Option<Device>, kube::Error> {
+        if !self.pending.lock().await.contains_key(public_key) {
+            return Ok(None);
+        }
+
+        let Some(enrollment) =
+            Enrollment::find_open(self.enrollments.clone(), Utc::now()).await?
+        else {
+            return Ok(None);
+        };
+
+        // Another connection with the same key may have enrolled it
+        // already.
+        let existing =
+            Device::find(self.devices.clone(), public_key.clone()).await?;
+        let device = match existing {
+            Some(device) => device,
+            None => {
+                Device::enroll(
+                    self.devices.clone(),
+                    public_key.clone(),
+                    enrollment.spec.device_labels,
+                )
+                .await?
+            },
+        };
+
+        let device = DeviceStatusUpdate::default()
+            .mark_enrolling(self.gateway_reference.clone())
+            .apply(self.devices.clone(), device)
+            .await?;
+
+        if let Some(name) = self.pending.lock().await.get_mut(public_key) {
+            *name = Some(device.name_any());
+        }
+
+        Ok(Some(device))
+    }
+
+    /// Marks an enrolled Device as `Enrolled`, once the device has been
+    /// notified.
+    pub async fn complete(
+        &self,
+        public_key: &PublicKey<'static>,
+        device: Device,
+    ) -> Result<(), kube::Error> {
+        self.pending.lock().await.remove(public_key);
+        DeviceStatusUpdate::default()
+            .mark_enrolled()
+            .apply(self.devices.clone(), device)
+            .await?;
+        Ok(())
+    }
+
+    /// Forgets an admitted device that disconnected, deleting its Device if
+    /// the enrollment was not completed.
+    pub async fn disconnect(
+        &self,
+        public_key: &PublicKey<'static>,
+    ) -> Result<(), kube::Error> {
+        let Some(Some(name)) = self.pending.lock().await.remove(public_key)
+        else {
+            return Ok(());
+        };
+
+        // Another connection with the same key may have completed it.
+        let enrolling =
+            self.devices.get_opt(&name).await?.is_some_and(|device| {
+                device.status.is_some_and(|status| {
+                    status.phase() == DevicePhase::Enrolling
+                })
+            });
+        if enrolling {
+            self.devices.delete(&name, &DeleteParams::default()).await?;
+        }
+        Ok(())
+    }
+}
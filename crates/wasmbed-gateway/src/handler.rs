@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::collections::HashSet;
+
+use kube::ResourceExt;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use wasmbed_k8s_resource::{Device, DeviceCapabilities};
+use wasmbed_protocol::{ClientMessage, ErrorCode, ServerMessage};
+use wasmbed_protocol_server::{
+    AuthorizationResult, ConnectionContext, ConnectionId, MessageContext,
+    ServerHandler,
+};
+
+use crate::enrollment::Enrollments;
+use crate::sessions::Sessions;
+
+pub struct Handler {
+    pub sessions: Sessions,
+    pub enrollments: Enrollments,
+    /// Connections of unregistered devices, only allowed to enroll
+    pub enrolling: Mutex<HashSet<ConnectionId>>,
+}
+
+impl ServerHandler for Handler {
+    async fn on_connect(
+        &self,
+        connection: &ConnectionContext,
+    ) -> AuthorizationResult {
+        let public_key = connection.client_key().clone();
+        let api = self.sessions.api().clone();
+        match Device::find(api, public_key.clone()).await {
+            Ok(Some(device)) => {
+                if let Err(e) = self.sessions.connect(public_key, device).await
+                {
+                    error!("Error updating DeviceStatus: {e}");
+                }
+                AuthorizationResult::Authorized
+            },
+            Ok(None) => match self.enrollments.admit(public_key).await {
+                Ok(true) => {
+                    self.enrolling.lock().await.insert(connection.id());
+                    AuthorizationResult::EnrollOnly
+                },
+                Ok(false) => AuthorizationResult::Unauthorized,
+                Err(e) => {
+                    error!("Unable to find Enrollment: {e}");
+                    AuthorizationResult::Unauthorized
+                },
+            },
+            Err(e) => {
+                error!("Unable to find Device: {e}");
+                AuthorizationResult::Unauthorized
+            },
+        }
+    }
+
+    async fn on_message(&self, ctx: MessageContext) {
+        match ctx.message() {
+            ClientMessage::Heartbeat => {
+                let _ = ctx.reply(ServerMessage::HeartbeatAck);
+                if let Err(e) = self.sessions.heartbeat(ctx.client_key()).await
+                {
+                    error!("Error persisting heartbeat: {e}");
+                }
+            },
+            ClientMessage::ApplicationDeployed { app_id } => {
+                info!("Application {app_id} deployed");
+            },
+            ClientMessage::ApplicationFailed { app_id, reason } => {
+                warn!("Application {app_id} failed: {reason}");
+            },
+            ClientMessage::ApplicationStopped { app_id } => {
+                info!("Application {app_id} stopped");
+            },
+            ClientMessage::TransferReady { app_id, .. }
+            | ClientMessage::ChunkAck { app_id, .. } => {
+                warn!("Unexpected transfer reply for {app_id}");
+            },
+            ClientMessage::Enroll => self.enroll(&ctx).await,
+            ClientMessage::Hello { capabilities } => {
+                let _ = ctx.reply(ServerMessage::HelloAck);
+                let capabilities = DeviceCapabilities {
+                    firmware_version: capabilities.firmware_version.clone(),
+                    runtime_version: capabilities.runtime_version.clone(),
+                    free_memory: capabilities.free_memory,
+                    host_modules: capabilities.host_modules.clone(),
+                    max_message_size: capabilities.max_message_size,
+                };
+                if let Err(e) =
+                    self.sessions.hello(ctx.client_key(), capabilities).await
+                {
+                    error!("Error persisting device capabilities: {e}");
+                }
+            },
+            ClientMessage::Error { code, detail, .. } => {
+                warn!("Device reported an error: {code}: {detail}");
+            },
+            // Handled by the protocol server
+            ClientMessage::Negotiate { .. }
+            | ClientMessage::Disconnect { .. }
+            | ClientMessage::Unknown { .. } => {},
+        }
+    }
+
+    async fn on_disconnect(&self, connection: &ConnectionContext) {
+        let public_key = connection.client_key();
+        // The session of the device, if any, belongs to a connection made
+        // once enrolled, which may still be open.
+        if self.enrolling.lock().await.remove(&connection.id()) {
+            if let Err(e) = self.enrollments.disconnect(public_key).await {
+                error!("Unable to clean up interrupted enrollment: {e}");
+            }
+        } else if let Err(e) = self.sessions.disconnect(public_key).await {
+            error!("Error updating DeviceStatus: {e}");
+        }
+    }
+}
+
+impl Handler {
+    async fn enroll(&self, ctx: &MessageContext) {
+        let public_key = ctx.client_key();
+        let device = match self.enrollments.enroll(public_key).await {
+            Ok(Some(device)) => device,
+            Ok(None) => {
+                warn!("Rejected enrollment of a device outside pairing mode");
+                let _ = ctx.reply(ServerMessage::Error {
+                    code: ErrorCode::Unauthorized,
+                    detail: "Pairing mode is disabled".into(),
+                    in_reply_to: ctx.message_id(),
+                });
+                return;
+            },
+            Err(e) => {
+                error!("Unable to enroll device: {e}");
+                let _ = ctx.reply(ServerMessage::Error {
+                    code: ErrorCode::Internal,
+                    detail: "Unable to enroll device".into(),
+                    in_reply_to: ctx.message_id(),
+                });
+                return;
+            },
+        };
+
+        let name = device.name_any();
+        if ctx
+            .reply(ServerMessage::EnrollAck {
+                device: name.clone(),
+            })
+            .is_err()
+        {
+            warn!("Device {name} disconnected during enrollment");
+            return;
+        }
+
+        match self.enrollments.complete(public_key, device).await {
+            Ok(()) => info!("Device {name} enrolled"),
+            Err(e) => error!("Error updating DeviceStatus: {e}"),
+        }
+    }
+}
@@ -1,25 +1,41 @@
 // SPDX-License-Identifier: AGPL-3.0
 // Copyright © 2025 Wasmbed contributors
 
+mod control;
+mod enrollment;
+mod handler;
+mod operations;
+mod sessions;
+
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use kube::{Api, Client};
+use tokio::signal::unix::{SignalKind, signal};
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, error, info};
 use tracing_subscriber::FmtSubscriber;
 
 use wasmbed_cert::ServerIdentity;
-use wasmbed_k8s_resource::{Device, DeviceStatusUpdate};
-use wasmbed_protocol::{ClientMessage, ServerMessage};
+use wasmbed_k8s_resource::{Device, Enrollment};
+use wasmbed_oci::{PullConfig, Puller};
 use wasmbed_protocol_server::{
-    AuthorizationResult, MessageContext, OnClientConnect, OnClientDisconnect,
-    OnClientMessage, Server, ServerConfig,
+    DuplicatePolicy, Server, ServerConfig, build_tls_acceptor,
 };
-use wasmbed_types::{GatewayReference, PublicKey};
+use wasmbed_types::GatewayReference;
+
+use crate::control::ControlConfig;
+use crate::enrollment::Enrollments;
+use crate::handler::Handler;
+use crate::sessions::Sessions;
+
+/// Largest module pulled from a registry, in bytes.
+const MAX_MODULE_SIZE: u64 = 16 * 1024 * 1024;
 
 #[derive(Parser)]
 #[command(disable_help_subcommand = true)]
@@ -32,63 +48,73 @@ struct Args {
     certificate: PathBuf,
     #[arg(long, env = "WASMBED_GATEWAY_CLIENT_CA")]
     client_ca: PathBuf,
+    /// Address of the control API used by the controller.
+    #[arg(long, env = "WASMBED_GATEWAY_CONTROL_BIND_ADDR")]
+    control_bind_addr: SocketAddr,
+    /// Authority issuing the certificates of the controller.
+    #[arg(long, env = "WASMBED_GATEWAY_CONTROL_CLIENT_CA")]
+    control_client_ca: PathBuf,
     #[arg(long, env = "WASMBED_GATEWAY_NAMESPACE")]
     namespace: String,
     #[arg(long, env = "WASMBED_GATEWAY_POD_NAMESPACE")]
     pod_namespace: String,
     #[arg(long, env = "WASMBED_GATEWAY_POD_NAME")]
     pod_name: String,
-}
-
-struct Callbacks {
-    api: Api<Device>,
-    gateway_reference: GatewayReference,
-}
-
-impl Callbacks {
-    fn on_connect(&self) -> Box<OnClientConnect> {
-        let api = self.api.clone();
-        let gateway_reference = self.gateway_reference.clone();
-        Box::new(move |public_key: PublicKey<'static>| {
-            let api = api.clone();
-            let gateway_reference = gateway_reference.clone();
-            Box::pin(async move {
-                match Device::find(api.clone(), public_key).await {
-                    Ok(Some(device)) => {
-                        if let Err(e) = DeviceStatusUpdate::default()
-                            .mark_connected(gateway_reference)
-                            .apply(api.clone(), device)
-                            .await
-                        {
-                            error!("Error updating DeviceStatus: {e}");
-                        }
-                        AuthorizationResult::Authorized
-                    },
-                    Ok(None) => AuthorizationResult::Unauthorized,
-                    Err(e) => {
-                        error!("Unable to find Device: {e}");
-                        AuthorizationResult::Unauthorized
-                    },
-                }
-            })
-        })
-    }
-
-    fn on_disconnect(&self) -> Box<OnClientDisconnect> {
-        Box::new(move |_public_key: PublicKey<'static>| Box::pin(async move {}))
-    }
-
-    fn on_message(&self) -> Box<OnClientMessage> {
-        Box::new(move |ctx: MessageContext| {
-            Box::pin(async move {
-                match ctx.message() {
-                    ClientMessage::Heartbeat => {
-                        let _ = ctx.reply(ServerMessage::HeartbeatAck);
-                    },
-                }
-            })
-        })
-    }
+    /// UID of the Pod, recorded in the status of the connected devices.
+    #[arg(long, env = "WASMBED_GATEWAY_POD_UID")]
+    pod_uid: String,
+    /// Seconds without messages after which a device connection is closed.
+    #[arg(long, env = "WASMBED_GATEWAY_IDLE_TIMEOUT", default_value_t = 90)]
+    idle_timeout: u64,
+    /// Minimum number of seconds between two heartbeats of the same device
+    /// being persisted in its status.
+    #[arg(
+        long,
+        env = "WASMBED_GATEWAY_HEARTBEAT_PERSIST_INTERVAL",
+        default_value_t = 120
+    )]
+    heartbeat_persist_interval: u64,
+    /// Maximum number of messages queued for a device.
+    #[arg(long, env = "WASMBED_GATEWAY_QUEUE_DEPTH", default_value = "64")]
+    queue_depth: NonZeroUsize,
+    /// Seconds a device queue may stay full before the device is
+    /// disconnected. 0 keeps saturated devices connected.
+    #[arg(
+        long,
+        env = "WASMBED_GATEWAY_SATURATION_TIMEOUT",
+        default_value_t = 30
+    )]
+    saturation_timeout: u64,
+    /// Refuse connections of devices that are already connected, instead
+    /// of closing their existing connection.
+    #[arg(long, env = "WASMBED_GATEWAY_REJECT_DUPLICATE_CONNECTIONS")]
+    reject_duplicate_connections: bool,
+    /// Seconds devices are given to disconnect when the gateway shuts down,
+    /// before their connections are closed.
+    #[arg(long, env = "WASMBED_GATEWAY_DRAIN_TIMEOUT", default_value_t = 20)]
+    drain_timeout: u64,
+    /// Seconds a device is given to reply to an operation of the
+    /// controller.
+    #[arg(
+        long,
+        env = "WASMBED_GATEWAY_OPERATION_TIMEOUT",
+        default_value_t = 60
+    )]
+    operation_timeout: u64,
+    /// Directory where the module images pulled are cached.
+    #[arg(
+        long,
+        env = "WASMBED_GATEWAY_MODULE_CACHE",
+        default_value = "/var/cache/wasmbed-gateway/modules"
+    )]
+    module_cache: PathBuf,
+    /// Registries reached over plain HTTP, e.g. `localhost:5000`.
+    #[arg(
+        long,
+        env = "WASMBED_GATEWAY_INSECURE_REGISTRIES",
+        value_delimiter = ','
+    )]
+    insecure_registries: Vec<String>,
 }
 
 #[tokio::main]
@@ -122,22 +148,34 @@ async fn main() -> Result<()> {
             )
         })?;
 
+    let control_client_ca_bytes = std::fs::read(&args.control_client_ca)
+        .with_context(|| {
+            format!(
+                "Failed to read control client CA certificate from {}",
+                args.control_client_ca.display()
+            )
+        })?;
+
     let identity = ServerIdentity::from_parts(
         private_key_bytes.into(),
         certificate_bytes.into(),
     );
     let client_ca = client_ca_bytes.into();
+    let control_acceptor =
+        build_tls_acceptor(&identity, &control_client_ca_bytes.into())
+            .context("Invalid control API TLS configuration")?;
 
     let gateway_reference =
-        GatewayReference::new(&args.pod_namespace, &args.pod_name);
+        GatewayReference::new(&args.pod_namespace, &args.pod_name)
+            .with_uid(&args.pod_uid);
 
     let shutdown = CancellationToken::new();
     let shutdown_clone = shutdown.clone();
 
     tokio::spawn(async move {
-        match tokio::signal::ctrl_c().await {
-            Ok(()) => {
-                info!("Received Ctrl+C, shutting down...");
+        match shutdown_signal().await {
+            Ok(signal) => {
+                info!("Received {signal}, shutting down...");
                 shutdown_clone.cancel();
             },
             Err(err) => {
@@ -149,26 +187,74 @@ async fn main() -> Result<()> {
     let client = Client::try_default().await?;
     let api: Api<Device> = Api::namespaced(client.clone(), &args.namespace);
 
-    let callbacks = Callbacks {
-        api: api.clone(),
-        gateway_reference: gateway_reference.clone(),
+    let enrollment_api: Api<Enrollment> =
+        Api::namespaced(client.clone(), &args.namespace);
+
+    let handler = Handler {
+        sessions: Sessions::new(
+            api.clone(),
+            gateway_reference.clone(),
+            Duration::from_secs(args.heartbeat_persist_interval),
+        ),
+        enrollments: Enrollments::new(
+            api.clone(),
+            enrollment_api,
+            gateway_reference.clone(),
+        ),
+        enrolling: Default::default(),
     };
 
     let config = ServerConfig {
         bind_addr: args.bind_addr,
         identity,
         client_ca,
-        on_client_connect: Arc::from(callbacks.on_connect()),
-        on_client_disconnect: Arc::from(callbacks.on_disconnect()),
-        on_client_message: Arc::from(callbacks.on_message()),
+        idle_timeout: Duration::from_secs(args.idle_timeout),
+        queue_depth: args.queue_depth,
+        saturation_timeout: (args.saturation_timeout > 0)
+            .then(|| Duration::from_secs(args.saturation_timeout)),
+        duplicate_policy: if args.reject_duplicate_connections {
+            DuplicatePolicy::RejectNew
+        } else {
+            DuplicatePolicy::ReplaceExisting
+        },
+        drain_timeout: Duration::from_secs(args.drain_timeout),
+        shutdown: shutdown.clone(),
+    };
+    let puller = Puller::new(PullConfig {
+        cache_dir: args.module_cache,
+        insecure_registries: args.insecure_registries,
+        max_module_size: MAX_MODULE_SIZE,
+    })?;
+
+    let control_config = ControlConfig {
+        bind_addr: args.control_bind_addr,
+        acceptor: control_acceptor,
+        operation_timeout: Duration::from_secs(args.operation_timeout),
+        puller,
         shutdown,
     };
 
-    let server = Server::new(config);
+    let server = Arc::new(Server::new(config, handler));
     info!("Starting server on {}", args.bind_addr);
-    if let Err(e) = server.run().await {
+    let (server_result, control_result) = tokio::join!(
+        server.run(),
+        control::run(control_config, Arc::clone(&server)),
+    );
+    if let Err(e) = server_result {
         error!("Server error: {}", e);
     }
+    if let Err(e) = control_result {
+        error!("Control API error: {}", e);
+    }
 
     Ok(())
 }
+
+/// Waits for Ctrl+C or SIGTERM, which Kubernetes sends to stop the Pod.
+async fn shutdown_signal() -> std::io::Result<&'static str> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.map(|()| "Ctrl+C"),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use wasmbed_gateway_api::{
+    Operation, OperationId, OperationRequest, OperationState,
+};
+
+/// Operations submitted through the control API.
+///
+/// Finished operations are kept for `retention`, giving the controller time
+/// to observe their outcome, and are then forgotten.
+///
+/// Identifiers start from a random value, so that a restarted gateway does
+/// not reuse the identifiers of the operations the controller still follows.
+#[derive(Clone)]
+pub struct Operations {
+    retention: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    last_id: u64,
+    entries: HashMap<OperationId, Entry>,
+}
+
+struct Entry {
+    operation: Operation,
+    finished_at: Option<Instant>,
+}
+
+impl Operations {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            state: Arc::new(Mutex::new(State {
+                last_id: RandomState::new().hash_one(()),
+                entries: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Records a new pending operation for `request`.
+    pub fn start(&self, request: &OperationRequest) -> Operation {
+        let mut state = self.lock();
+        let retention = self.retention;
+        state.entries.retain(|_, entry| {
+            entry
+                .finished_at
+                .is_none_or(|finished_at| finished_at.elapsed() < retention)
+        });
+
+        state.last_id = state.last_id.wrapping_add(1);
+        let operation = Operation {
+            id: OperationId(state.last_id),
+            kind: request.kind(),
+            device: request.device().clone(),
+            app_id: request.app_id().to_string(),
+            state: OperationState::Pending,
+            reason: None,
+        };
+        state.entries.insert(
+            operation.id,
+            Entry {
+                operation: operation.clone(),
+                finished_at: None,
+            },
+        );
+        operation
+    }
+
+    /// Records the outcome of an operation.
+    pub fn finish(
+        &self,
+        id: OperationId,
+        state: OperationState,
+        reason: Option<String>,
+    ) {
+        if let Some(entry) = self.lock().entries.get_mut(&id) {
+            entry.operation.state = state;
+            entry.operation.reason = reason;
+            entry.finished_at = Some(Instant::now());
+        }
+    }
+
+    pub fn get(&self, id: OperationId) -> Option<Operation> {
+        self.lock()
+            .entries
+            .get(&id)
+            .map(|entry| entry.operation.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmbed_types::PublicKey;
+
+    fn stop() -> OperationRequest {
+        OperationRequest::Stop {
+            device: PublicKey::from(vec![1; 32]),
+            app_id: "app-0".into(),
+        }
+    }
+
+    #[test]
+    fn finished_operations_expire() {
+        let operations = Operations::new(Duration::ZERO);
+
+        let first = operations.start(&stop());
+        let second = operations.start(&stop());
+        assert_ne!(first.id, second.id);
+
+        // A restarted gateway does not start over from the same identifier.
+        let restarted = Operations::new(Duration::ZERO);
+        assert_ne!(restarted.start(&stop()).id, first.id);
+
+        operations.finish(
+            first.id,
+            OperationState::Failed,
+            Some("Application not found".into()),
+        );
+        let failed = operations.get(first.id).unwrap();
+        assert_eq!(failed.state, OperationState::Failed);
+        assert_eq!(failed.reason.as_deref(), Some("Application not found"));
+
+        // Starting an operation forgets the expired ones, but not those
+        // still pending.
+        operations.start(&stop());
+        assert!(operations.get(first.id).is_none());
+        assert!(operations.get(second.id).is_some());
+    }
+}
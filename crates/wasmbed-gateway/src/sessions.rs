@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use kube::Api;
+use tokio::sync::Mutex;
+
+use wasmbed_k8s_resource::{Device, DeviceCapabilities, DeviceStatusUpdate};
+use wasmbed_types::{GatewayReference, PublicKey};
+
+/// Devices connected to this gateway.
+///
+/// Heartbeats are persisted in the Device status at most once every
+/// `heartbeat_interval` per device, to avoid patching the API server on
+/// every heartbeat. The most recent heartbeat not yet persisted is written
+/// when the device disconnects.
+#[derive(Clone)]
+pub struct Sessions {
+    api: Api<Device>,
+    gateway_reference: GatewayReference,
+    heartbeat_interval: Duration,
+    sessions: Arc<Mutex<HashMap<PublicKey<'static>, Session>>>,
+}
+
+struct Session {
+    device: Device,
+    heartbeat_persisted_at: Instant,
+    pending_heartbeat: Option<DateTime<Utc>>,
+}
+
+impl Sessions {
+    pub fn new(
+        api: Api<Device>,
+        gateway_reference: GatewayReference,
+        heartbeat_interval: Duration,
+    ) -> Self {
+        Self {
+            api,
+            gateway_reference,
+            heartbeat_interval,
+            sessions: Default::default(),
+        }
+    }
+
+    pub fn api(&self) -> &Api<Device> {
+        &self.api
+    }
+
+    /// Marks `device` as connected to this gateway.
+    pub async fn connect(
+        &self,
+        public_key: PublicKey<'static>,
+        device: Device,
+    ) -> Result<(), kube::Error> {
+        let device = DeviceStatusUpdate::default()
+            .mark_connected(self.gateway_reference.clone())
+            .update_heartbeat()
+            .apply(self.api.clone(), device)
+            .await?;
+
+        self.sessions.lock().await.insert(
+            public_key,
+            Session {
+                device,
+                heartbeat_persisted_at: Instant::now(),
+                pending_heartbeat: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Records a heartbeat, persisting it if the last persisted one is older
+    /// than the heartbeat interval.
+    pub async fn heartbeat(
+        &self,
+        public_key: &PublicKey<'static>,
+    ) -> Result<(), kube::Error> {
+        let now = Utc::now();
+        let device = {
+            let mut sessions = self.sessions.lock().await;
+            let Some(session) = sessions.get_mut(public_key) else {
+                return Ok(());
+            };
+
+            if session.heartbeat_persisted_at.elapsed()
+                < self.heartbeat_interval
+            {
+                session.pending_heartbeat = Some(now);
+                return Ok(());
+            }
+
+            session.heartbeat_persisted_at = Instant::now();
+            session.pending_heartbeat = None;
+            session.device.clone()
+        };
+
+        let device = DeviceStatusUpdate::default()
+            .last_heartbeat(Some(now))
+            .apply(self.api.clone(), device)
+            .await?;
+
+        if let Some(session) = self.sessions.lock().await.get_mut(public_key) {
+            session.device = device;
+        }
+
+        Ok(())
+    }
+
+    /// Persists the capabilities the device advertised in its `Hello`.
+    pub async fn hello(
+        &self,
+        public_key: &PublicKey<'static>,
+        capabilities: DeviceCapabilities,
+    ) -> Result<(), kube::Error> {
+        let Some(device) = self
+            .sessions
+            .lock()
+            .await
+            .get(public_key)
+            .map(|session| session.device.clone())
+        else {
+            return Ok(());
+        };
+
+        let device = DeviceStatusUpdate::default()
+            .capabilities(capabilities)
+            .apply(self.api.clone(), device)
+            .await?;
+
+        if let Some(session) = self.sessions.lock().await.get_mut(public_key) {
+            session.device = device;
+        }
+
+        Ok(())
+    }
+
+    /// Marks the device as disconnected, flushing any pending heartbeat.
+    pub async fn disconnect(
+        &self,
+        public_key: &PublicKey<'static>,
+    ) -> Result<(), kube::Error> {
+        let Some(session) = self.sessions.lock().await.remove(public_key)
+        else {
+            return Ok(());
+        };
+
+        let mut update = DeviceStatusUpdate::default().mark_disconnected();
+        if let Some(heartbeat) = session.pending_heartbeat {
+            update = update.last_heartbeat(Some(heartbeat));
+        }
+
+        update.apply(self.api.clone(), session.device).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,838 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use derive_more::{Display, Error};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client, ResourceExt};
+use kube::api::ListParams;
+use kube::core::{Selector, SelectorExt};
+use kube::runtime::controller::Action;
+use kube::runtime::finalizer::{self, Event, finalizer};
+use kube::runtime::reflector::{ObjectRef, Store};
+use tracing::{info, warn};
+
+use wasmbed_gateway_api::{Module, OperationId, OperationRequest, OperationState};
+use wasmbed_k8s_resource::{
+    Application, ApplicationPhase, ApplicationStatusUpdate, DEVICE_CLASS_LABEL,
+    DeploymentPhase, Device, DeviceDeployment, DevicePhase, ModuleSource,
+};
+
+use wasmbed_protocol::{
+    ResourceLimits, ServerMessageRef, chunk_capacity, envelope_len,
+};
+
+use crate::gateway::{GatewayError, Gateways};
+
+/// Interval after which every Application is reconciled again, so that
+/// failed deployments are retried even if no related resource changes.
+const REQUEUE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Interval after which an Application is reconciled again after an error.
+const ERROR_REQUEUE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval at which the operations of the deployments in progress are
+/// polled.
+const OPERATION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Minimum time a deployment stays failed before being retried.
+const FAILED_RETRY_INTERVAL: TimeDelta = TimeDelta::seconds(60);
+
+/// Size of a Wasm linear memory page, in bytes
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// Finalizer keeping deleted Applications until they are stopped on their
+/// devices.
+const FINALIZER: &str = "wasmbed.github.io/application-controller";
+
+pub struct Context {
+    pub client: Client,
+    pub applications: Api<Application>,
+    pub devices: Api<Device>,
+    pub gateways: Gateways,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum ReconcileError {
+    #[display("Kubernetes API error: {_0}")]
+    Kube(kube::Error),
+    #[display("Gateway error: {_0}")]
+    Gateway(GatewayError),
+    #[display("{_0}")]
+    Finalizer(Box<finalizer::Error<ReconcileError>>),
+}
+
+pub async fn reconcile(
+    application: Arc<Application>,
+    ctx: Arc<Context>,
+) -> Result<Action, ReconcileError> {
+    finalizer(&ctx.applications, FINALIZER, application, |event| async {
+        match event {
+            Event::Apply(application) => apply(application, &ctx).await,
+            Event::Cleanup(application) => cleanup(application, &ctx).await,
+        }
+    })
+    .await
+    .map_err(|e| ReconcileError::Finalizer(Box::new(e)))
+}
+
+async fn apply(
+    application: Arc<Application>,
+    ctx: &Context,
+) -> Result<Action, ReconcileError> {
+    let status = application.status.clone().unwrap_or_default();
+
+    let (phase, reason, deployments) = match device_selector(&application) {
+        Ok(selector) => {
+            let module = load_module(ctx, &application).await?;
+            // Unknown for images, which only the gateways pull.
+            let host_modules = match &module {
+                Ok(Module::Bytecode(bytecode)) => {
+                    wasmbed_wasm::host_modules(bytecode).ok()
+                },
+                _ => None,
+            };
+
+            let params = ListParams::default().labels_from(&selector);
+            let devices = ctx
+                .devices
+                .list(&params)
+                .await
+                .map_err(ReconcileError::Kube)?;
+
+            let devices: Vec<Device> = devices
+                .items
+                .into_iter()
+                .filter(|device| {
+                    capable(&application, host_modules.as_deref(), device)
+                })
+                .collect();
+
+            let deployments = plan(status.deployments(), &devices, Utc::now());
+            stop_removed(ctx, &application, status.deployments(), &deployments)
+                .await;
+            let deployments =
+                advance(ctx, &application, deployments, &devices, &module)
+                    .await;
+            let (phase, reason) = aggregate(&deployments);
+            (phase, reason, deployments)
+        },
+        Err(e) => (
+            ApplicationPhase::Failed,
+            Some(format!("Invalid device selector: {e}")),
+            Vec::new(),
+        ),
+    };
+
+    let action = if deployments.iter().any(|d| d.operation.is_some()) {
+        Action::requeue(OPERATION_POLL_INTERVAL)
+    } else {
+        Action::requeue(REQUEUE_INTERVAL)
+    };
+
+    if status.phase() == phase
+        && status.reason() == reason.as_deref()
+        && status.deployments() == deployments.as_slice()
+    {
+        return Ok(action);
+    }
+
+    for deployment in &deployments {
+        if deployment.phase == DeploymentPhase::Deploying
+            && status.deployment(&deployment.device) != Some(deployment)
+        {
+            info!(
+                "Deploying Application {} to Device {}",
+                application.name_any(),
+                deployment.device,
+            );
+        }
+    }
+
+    ApplicationStatusUpdate::default()
+        .phase(phase)
+        .reason(reason)
+        .deployments(deployments)
+        .apply(ctx.applications.clone(), (*application).clone())
+        .await
+        .map_err(ReconcileError::Kube)?;
+
+    Ok(action)
+}
+
+/// Stops a deleted Application on every device it was deployed to, before
+/// its finalizer is removed.
+async fn cleanup(
+    application: Arc<Application>,
+    ctx: &Context,
+) -> Result<Action, ReconcileError> {
+    let deployments = application
+        .status
+        .as_ref()
+        .map(|status| status.deployments())
+        .unwrap_or_default();
+    for deployment in deployments {
+        stop(ctx, &application, deployment).await?;
+    }
+    Ok(Action::await_change())
+}
+
+pub fn error_policy(
+    application: Arc<Application>,
+    error: &ReconcileError,
+    _ctx: Arc<Context>,
+) -> Action {
+    warn!(
+        "Failed to reconcile Application {}: {error}",
+        application.name_any()
+    );
+    Action::requeue(ERROR_REQUEUE_INTERVAL)
+}
+
+/// Returns the Applications that have to be reconciled when `device`
+/// changes, i.e. those selecting it and those deployed to it.
+pub fn applications_for_device(
+    store: &Store<Application>,
+    device: &Device,
+) -> Vec<ObjectRef<Application>> {
+    store
+        .state()
+        .iter()
+        .filter(|application| targets(application, device))
+        .map(|application| ObjectRef::from_obj(application.as_ref()))
+        .collect()
+}
+
+fn device_selector(
+    application: &Application,
+) -> Result<Selector, kube::core::ParseExpressionError> {
+    application.spec.device_selector.clone().try_into()
+}
+
+/// Whether changes to `device` are relevant to `application`.
+fn targets(application: &Application, device: &Device) -> bool {
+    if application.namespace() != device.namespace() {
+        return false;
+    }
+
+    let deployed_to = application
+        .status
+        .as_ref()
+        .zip(device.metadata.name.as_deref())
+        .is_some_and(|(status, name)| status.deployment(name).is_some());
+
+    deployed_to
+        || device_selector(application)
+            .is_ok_and(|selector| selector.matches(device.labels()))
+}
+
+/// Whether `device` can run `application`, importing from `host_modules`
+/// if they are known, according to the capabilities it advertised. Devices
+/// that have not advertised any are assumed capable, their gateway still
+/// validates the module before deploying it.
+fn capable(
+    application: &Application,
+    host_modules: Option<&[String]>,
+    device: &Device,
+) -> bool {
+    let Some(capabilities) = device
+        .status
+        .as_ref()
+        .and_then(|status| status.capabilities())
+    else {
+        return true;
+    };
+
+    let memory = application
+        .spec
+        .resources
+        .max_memory_pages
+        .and_then(|pages| u64::from(pages).checked_mul(WASM_PAGE_SIZE))
+        .is_none_or(|memory| memory <= u64::from(capabilities.free_memory));
+    let host_modules = host_modules.is_none_or(|modules| {
+        modules
+            .iter()
+            .all(|module| capabilities.host_modules.contains(module))
+    });
+
+    memory
+        && host_modules
+        && deliverable(application, capabilities.max_message_size)
+}
+
+/// Whether `application` can be transferred to a device accepting messages
+/// of at most `max_message_size` bytes, in chunks if need be.
+fn deliverable(application: &Application, max_message_size: u32) -> bool {
+    let app_id = application.name_any();
+    let max_message_size =
+        usize::try_from(max_message_size).unwrap_or(usize::MAX);
+
+    let begin = envelope_len(&ServerMessageRef::BeginTransfer {
+        app_id: &app_id,
+        size: u32::MAX,
+        entry_point: &application.spec.entry_point,
+        limits: ResourceLimits {
+            max_memory_pages: application.spec.resources.max_memory_pages,
+            fuel: application.spec.resources.fuel,
+        },
+    });
+    let commit = envelope_len(&ServerMessageRef::CommitTransfer {
+        app_id: &app_id,
+        digest: [0; 32],
+    });
+
+    begin.max(commit) <= max_message_size
+        && chunk_capacity(&app_id, max_message_size).is_some()
+}
+
+/// Computes the deployments an Application should have, given its current
+/// deployments and the devices currently matching its selector.
+///
+/// Every connected device gets a deployment through the gateway it is
+/// connected to. Deployments on devices that are no longer reachable are
+/// marked as failed, and those on devices that are no longer selected are
+/// dropped.
+fn plan(
+    current: &[DeviceDeployment],
+    devices: &[Device],
+    now: DateTime<Utc>,
+) -> Vec<DeviceDeployment> {
+    let mut deployments: Vec<DeviceDeployment> = devices
+        .iter()
+        .filter_map(|device| {
+            let name = device.metadata.name.clone()?;
+            let existing = current.iter().find(|d| d.device == name).cloned();
+            let gateway = device
+                .status
+                .as_ref()
+                .filter(|status| status.phase() == DevicePhase::Connected)
+                .and_then(|status| status.gateway())
+                .cloned();
+
+            match (existing, gateway) {
+                (None, None) => None,
+                (None, Some(gateway)) => {
+                    Some(DeviceDeployment::new(name, Some(gateway)))
+                },
+                (Some(existing), None) => Some(match existing.phase {
+                    DeploymentPhase::Failed | DeploymentPhase::PullFailed => {
+                        existing
+                    },
+                    DeploymentPhase::Deploying => existing.transition(
+                        DeploymentPhase::Failed,
+                        Some("Gateway unreachable".to_string()),
+                    ),
+                    DeploymentPhase::Running => existing.transition(
+                        DeploymentPhase::Failed,
+                        Some("Device disconnected".to_string()),
+                    ),
+                }),
+                (Some(existing), Some(gateway)) => {
+                    let moved = existing.gateway.as_ref() != Some(&gateway);
+                    let retry = existing.phase.is_failed()
+                        && existing.last_transition_time.is_none_or(|t| {
+                            now.signed_duration_since(t)
+                                >= FAILED_RETRY_INTERVAL
+                        });
+
+                    if moved || retry {
+                        Some(DeviceDeployment::new(name, Some(gateway)))
+                    } else {
+                        Some(existing)
+                    }
+                },
+            }
+        })
+        .collect();
+
+    deployments.sort_by(|a, b| a.device.cmp(&b.device));
+    deployments
+}
+
+/// Starts the planned deployments through the gateways of their devices,
+/// and moves those already started according to the outcome of their
+/// operation.
+async fn advance(
+    ctx: &Context,
+    application: &Application,
+    deployments: Vec<DeviceDeployment>,
+    devices: &[Device],
+    module: &Result<Module, String>,
+) -> Vec<DeviceDeployment> {
+    let mut advanced = Vec::with_capacity(deployments.len());
+    for deployment in deployments {
+        let deployment = match (deployment.phase, deployment.operation) {
+            (DeploymentPhase::Deploying, Some(id)) => {
+                follow(ctx, deployment, OperationId(id)).await
+            },
+            (DeploymentPhase::Deploying, None) => match module {
+                Ok(module) => {
+                    start(ctx, application, deployment, devices, module).await
+                },
+                Err(reason) => deployment
+                    .transition(DeploymentPhase::Failed, Some(reason.clone())),
+            },
+            _ => deployment,
+        };
+        advanced.push(deployment);
+    }
+
+    advanced
+}
+
+async fn start(
+    ctx: &Context,
+    application: &Application,
+    deployment: DeviceDeployment,
+    devices: &[Device],
+    module: &Module,
+) -> DeviceDeployment {
+    let Some(gateway) = deployment.gateway.clone() else {
+        return deployment;
+    };
+    let Some(device) = devices
+        .iter()
+        .find(|d| d.metadata.name.as_ref() == Some(&deployment.device))
+    else {
+        return deployment;
+    };
+
+    let device_class = match device.class() {
+        Ok(class) => class,
+        Err(e) => {
+            return deployment.transition(
+                DeploymentPhase::Failed,
+                Some(format!("Invalid {DEVICE_CLASS_LABEL} label: {e}")),
+            );
+        },
+    };
+
+    let request = OperationRequest::Deploy {
+        device: device.spec.public_key.clone(),
+        device_class,
+        host_modules: device
+            .status
+            .as_ref()
+            .and_then(|status| status.capabilities())
+            .map(|capabilities| capabilities.host_modules.clone()),
+        max_message_size: device
+            .status
+            .as_ref()
+            .and_then(|status| status.capabilities())
+            .map(|capabilities| capabilities.max_message_size),
+        app_id: application.name_any(),
+        module: module.clone(),
+        entry_point: application.spec.entry_point.clone(),
+        max_memory_pages: application.spec.resources.max_memory_pages,
+        fuel: application.spec.resources.fuel,
+    };
+
+    match ctx.gateways.submit(&gateway, &request).await {
+        Ok(operation) => DeviceDeployment {
+            operation: Some(operation.id.0),
+            ..deployment
+        },
+        Err(e) => deployment.transition(
+            DeploymentPhase::Failed,
+            Some(format!("Unable to start deployment: {e}")),
+        ),
+    }
+}
+
+async fn follow(
+    ctx: &Context,
+    deployment: DeviceDeployment,
+    id: OperationId,
+) -> DeviceDeployment {
+    let Some(gateway) = deployment.gateway.clone() else {
+        return deployment;
+    };
+
+    match ctx.gateways.operation(&gateway, id).await {
+        Ok(operation) => match operation.state {
+            OperationState::Pending => deployment,
+            OperationState::Succeeded => {
+                deployment.transition(DeploymentPhase::Running, None)
+            },
+            OperationState::Failed => {
+                deployment.transition(DeploymentPhase::Failed, operation.reason)
+            },
+            OperationState::PullFailed => deployment
+                .transition(DeploymentPhase::PullFailed, operation.reason),
+        },
+        // Forgotten by the gateway, e.g. restarted since it was started.
+        Err(GatewayError::Api(e)) if e.is_not_found() => deployment.transition(
+            DeploymentPhase::Failed,
+            Some(format!("Lost track of deployment: {e}")),
+        ),
+        // Polled again with the other deployments in progress.
+        Err(e) => {
+            warn!(
+                "Unable to follow the deployment to Device {}: {e}",
+                deployment.device,
+            );
+            deployment
+        },
+    }
+}
+
+/// Whether the application runs on the device of `deployment`, or will once
+/// the operation deploying it succeeds.
+fn active(deployment: &DeviceDeployment) -> bool {
+    match deployment.phase {
+        DeploymentPhase::Running => true,
+        DeploymentPhase::Deploying => deployment.operation.is_some(),
+        DeploymentPhase::Failed | DeploymentPhase::PullFailed => false,
+    }
+}
+
+/// Stops the application on the devices that are still connected but no
+/// longer selected or capable.
+async fn stop_removed(
+    ctx: &Context,
+    application: &Application,
+    current: &[DeviceDeployment],
+    planned: &[DeviceDeployment],
+) {
+    let removed = current
+        .iter()
+        .filter(|d| active(d) && !planned.iter().any(|p| p.device == d.device));
+
+    for deployment in removed {
+        if let Err(e) = stop(ctx, application, deployment).await {
+            warn!(
+                "Unable to stop Application {} on Device {}: {e}",
+                application.name_any(),
+                deployment.device,
+            );
+        }
+    }
+}
+
+/// Stops the application on the device of `deployment`, through the gateway
+/// it was deployed by. Devices that no longer exist, or are no longer
+/// connected to that gateway, cannot be reached and are skipped.
+async fn stop(
+    ctx: &Context,
+    application: &Application,
+    deployment: &DeviceDeployment,
+) -> Result<(), ReconcileError> {
+    let Some(gateway) = &deployment.gateway else {
+        return Ok(());
+    };
+    let Some(device) = ctx
+        .devices
+        .get_opt(&deployment.device)
+        .await
+        .map_err(ReconcileError::Kube)?
+    else {
+        return Ok(());
+    };
+
+    let request = OperationRequest::Stop {
+        device: device.spec.public_key,
+        app_id: application.name_any(),
+    };
+    match ctx.gateways.submit(gateway, &request).await {
+        Ok(_) => {
+            info!(
+                "Stopping Application {} on Device {}",
+                application.name_any(),
+                deployment.device,
+            );
+            Ok(())
+        },
+        Err(GatewayError::NotFound(_)) => Ok(()),
+        Err(GatewayError::Api(e)) if e.is_not_found() => Ok(()),
+        Err(e) => Err(ReconcileError::Gateway(e)),
+    }
+}
+
+/// Loads the Wasm module of an application, or the reference of its image
+/// for the gateways to pull it. Returns the reason why the module is
+/// unavailable, if it is.
+async fn load_module(
+    ctx: &Context,
+    application: &Application,
+) -> Result<Result<Module, String>, ReconcileError> {
+    let selector = match &application.spec.module {
+        ModuleSource::Inline(bytecode) => {
+            return Ok(Ok(Module::Bytecode(bytecode.0.clone())));
+        },
+        ModuleSource::Image(image) => {
+            return Ok(Ok(Module::Image(image.clone())));
+        },
+        ModuleSource::ConfigMapKeyRef(selector) => selector,
+    };
+
+    let config_maps: Api<ConfigMap> = match application.namespace() {
+        Some(namespace) => Api::namespaced(ctx.client.clone(), &namespace),
+        None => Api::default_namespaced(ctx.client.clone()),
+    };
+    let Some(config_map) = config_maps
+        .get_opt(&selector.name)
+        .await
+        .map_err(ReconcileError::Kube)?
+    else {
+        return Ok(Err(format!("ConfigMap {} not found", selector.name)));
+    };
+
+    let ConfigMap {
+        binary_data, data, ..
+    } = config_map;
+    let bytecode = binary_data
+        .and_then(|mut binary_data| binary_data.remove(&selector.key))
+        .map(|bytecode| bytecode.0)
+        .or_else(|| {
+            data.and_then(|mut data| data.remove(&selector.key))
+                .map(String::into_bytes)
+        });
+
+    Ok(bytecode.map(Module::Bytecode).ok_or_else(|| {
+        format!(
+            "Key {} not found in ConfigMap {}",
+            selector.key, selector.name
+        )
+    }))
+}
+
+/// Derives the Application phase from the phases of its deployments.
+fn aggregate(
+    deployments: &[DeviceDeployment],
+) -> (ApplicationPhase, Option<String>) {
+    let count = |phase| deployments.iter().filter(|d| d.phase == phase).count();
+    let total = deployments.len();
+    let failed = deployments.iter().filter(|d| d.phase.is_failed()).count();
+
+    // Matching devices may still connect.
+    if total == 0 {
+        return (
+            ApplicationPhase::Pending,
+            Some("No matching device".to_string()),
+        );
+    }
+
+    let reason =
+        (failed > 0).then(|| format!("{failed} of {total} deployments failed"));
+
+    if failed == total {
+        (ApplicationPhase::Failed, reason)
+    } else if count(DeploymentPhase::Deploying) > 0 {
+        (ApplicationPhase::Deploying, reason)
+    } else {
+        (ApplicationPhase::Running, reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wasmbed_types::GatewayReference;
+
+    fn device(name: &str, phase: &str, gateway: &str) -> Device {
+        serde_json::from_str(
+            &json!({
+                "apiVersion": "wasmbed.github.io/v0",
+                "kind": "Device",
+                "metadata": {
+                    "name": name,
+                    "namespace": "wasmbed",
+                    "labels": { "class": "sensor" },
+                },
+                "spec": { "publicKey": "AAAA" },
+                "status": {
+                    "phase": phase,
+                    "gateway": {
+                        "kind": "Pod",
+                        "name": gateway,
+                        "namespace": "wasmbed",
+                    },
+                },
+            })
+            .to_string(),
+        )
+        .unwrap()
+    }
+
+    fn application(selector: serde_json::Value) -> Application {
+        serde_json::from_str(
+            &json!({
+                "apiVersion": "wasmbed.github.io/v0",
+                "kind": "Application",
+                "metadata": { "name": "app", "namespace": "wasmbed" },
+                "spec": {
+                    "module": { "image": "registry.example.com/app:latest" },
+                    "deviceSelector": selector,
+                },
+            })
+            .to_string(),
+        )
+        .unwrap()
+    }
+
+    fn gateway(name: &str) -> Option<GatewayReference> {
+        Some(GatewayReference::new("wasmbed", name))
+    }
+
+    #[test]
+    fn test_plan_deploys_to_connected_devices() {
+        let devices = [
+            device("device-1", "Connected", "gateway-0"),
+            device("device-0", "Disconnected", "gateway-0"),
+            device("device-2", "Connected", "gateway-1"),
+        ];
+
+        let deployments = plan(&[], &devices, Utc::now());
+
+        assert_eq!(deployments.len(), 2);
+        assert_eq!(deployments[0].device, "device-1");
+        assert_eq!(deployments[0].gateway, gateway("gateway-0"));
+        assert_eq!(deployments[0].phase, DeploymentPhase::Deploying);
+        assert_eq!(deployments[1].device, "device-2");
+        assert_eq!(deployments[1].gateway, gateway("gateway-1"));
+    }
+
+    #[test]
+    fn test_plan_fails_unreachable_deployments() {
+        let current = [
+            DeviceDeployment::new("device-0".into(), gateway("gateway-0")),
+            DeviceDeployment::new("device-1".into(), gateway("gateway-0")),
+        ];
+        let devices = [device("device-0", "Disconnected", "gateway-0")];
+
+        let deployments = plan(&current, &devices, Utc::now());
+
+        assert_eq!(deployments.len(), 1);
+        assert_eq!(deployments[0].phase, DeploymentPhase::Failed);
+        assert_eq!(
+            deployments[0].reason.as_deref(),
+            Some("Gateway unreachable")
+        );
+    }
+
+    #[test]
+    fn test_plan_retries_failed_deployments() {
+        let failed =
+            DeviceDeployment::new("device-0".into(), gateway("gateway-0"))
+                .transition(DeploymentPhase::Failed, None);
+        let devices = [device("device-0", "Connected", "gateway-0")];
+
+        let now = Utc::now();
+        let kept = plan(&[failed.clone()], &devices, now);
+        assert_eq!(kept[0].phase, DeploymentPhase::Failed);
+
+        let later = now + FAILED_RETRY_INTERVAL;
+        let retried = plan(&[failed], &devices, later);
+        assert_eq!(retried[0].phase, DeploymentPhase::Deploying);
+    }
+
+    #[test]
+    fn test_active() {
+        let pending =
+            DeviceDeployment::new("device-0".into(), gateway("gateway-0"));
+        let deploying = DeviceDeployment {
+            operation: Some(1),
+            ..pending.clone()
+        };
+        let running =
+            deploying.clone().transition(DeploymentPhase::Running, None);
+        let failed =
+            deploying.clone().transition(DeploymentPhase::Failed, None);
+
+        assert!(!active(&pending));
+        assert!(active(&deploying));
+        assert!(active(&running));
+        assert!(!active(&failed));
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let deploying =
+            DeviceDeployment::new("device-0".into(), gateway("gateway-0"));
+        let running =
+            deploying.clone().transition(DeploymentPhase::Running, None);
+        let failed =
+            deploying.clone().transition(DeploymentPhase::Failed, None);
+
+        assert_eq!(aggregate(&[]).0, ApplicationPhase::Pending);
+        assert_eq!(
+            aggregate(&[running.clone(), deploying]).0,
+            ApplicationPhase::Deploying
+        );
+        assert_eq!(
+            aggregate(&[running.clone(), failed.clone()]),
+            (
+                ApplicationPhase::Running,
+                Some("1 of 2 deployments failed".to_string())
+            )
+        );
+        assert_eq!(aggregate(&[failed]).0, ApplicationPhase::Failed);
+        assert_eq!(aggregate(&[running]), (ApplicationPhase::Running, None));
+    }
+
+    #[test]
+    fn test_targets_matching_labels() {
+        let device = device("device-0", "Connected", "gateway-0");
+
+        let matching = application(json!({
+            "matchLabels": { "class": "sensor" },
+        }));
+        let other = application(json!({
+            "matchLabels": { "class": "actuator" },
+        }));
+
+        assert!(targets(&matching, &device));
+        assert!(!targets(&other, &device));
+    }
+
+    #[test]
+    fn test_capable_devices() {
+        let mut application = application(json!({}));
+        application.spec.resources.max_memory_pages = Some(2);
+
+        let unknown = device("device-0", "Connected", "gateway-0");
+        assert!(capable(&application, None, &unknown));
+
+        let with_capabilities = |max_message_size: u32| -> Device {
+            serde_json::from_str(
+                &json!({
+                    "apiVersion": "wasmbed.github.io/v0",
+                    "kind": "Device",
+                    "metadata": { "name": "device-1", "namespace": "wasmbed" },
+                    "spec": { "publicKey": "AAAA" },
+                    "status": {
+                        "phase": "Connected",
+                        "capabilities": {
+                            "firmwareVersion": "0.0.1",
+                            "runtimeVersion": "wasmi 0.32.3",
+                            "freeMemory": 65536,
+                            "hostModules": ["wasmbed"],
+                            "maxMessageSize": max_message_size,
+                        },
+                    },
+                })
+                .to_string(),
+            )
+            .unwrap()
+        };
+        let mut small = with_capabilities(4096);
+        assert!(!capable(&application, None, &small));
+
+        application.spec.resources.max_memory_pages = Some(1);
+        assert!(capable(&application, None, &small));
+
+        let wasmbed = ["wasmbed".to_string()];
+        let env = ["env".to_string()];
+        assert!(capable(&application, Some(&wasmbed), &small));
+        assert!(!capable(&application, Some(&env), &small));
+
+        assert!(!capable(&application, None, &with_capabilities(32)));
+
+        small.status = None;
+        assert!(capable(&application, None, &small));
+    }
+}
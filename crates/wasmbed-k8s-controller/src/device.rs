@@ -0,0 +1,255 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use derive_more::{Display, Error};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{Api, Client, ResourceExt};
+use kube::runtime::controller::Action;
+use tracing::{info, warn};
+
+use wasmbed_k8s_resource::{Device, DevicePhase, DeviceStatus, DeviceStatusUpdate};
+use wasmbed_types::GatewayReference;
+
+/// Interval after which every Device is reconciled again. Heartbeats and
+/// gateway Pods are not watched, so this bounds how late a stale device is
+/// noticed.
+const REQUEUE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Interval after which a Device is reconciled again after an error.
+const ERROR_REQUEUE_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct Context {
+    pub client: Client,
+    pub devices: Api<Device>,
+    /// Time without a persisted heartbeat after which a connected device is
+    /// considered unreachable.
+    pub heartbeat_timeout: TimeDelta,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum ReconcileError {
+    #[display("Kubernetes API error: {_0}")]
+    Kube(kube::Error),
+}
+
+/// Change to apply to the status of a Device.
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    /// The gateway the device was connected to no longer exists.
+    Orphaned,
+    /// Heartbeats stopped arriving.
+    Unreachable,
+    /// Heartbeats resumed after the device was marked unreachable.
+    Reachable,
+}
+
+pub async fn reconcile(
+    device: Arc<Device>,
+    ctx: Arc<Context>,
+) -> Result<Action, ReconcileError> {
+    let Some(status) = device.status.as_ref() else {
+        return Ok(Action::requeue(REQUEUE_INTERVAL));
+    };
+
+    let gateway_uid = match status.gateway() {
+        Some(gateway) => Some(gateway_uid(&ctx.client, gateway).await?),
+        None => None,
+    };
+
+    let Some(verdict) = assess(
+        status,
+        gateway_uid.as_ref().map(Option::as_deref),
+        Utc::now(),
+        ctx.heartbeat_timeout,
+    ) else {
+        return Ok(Action::requeue(REQUEUE_INTERVAL));
+    };
+
+    let update = match verdict {
+        Verdict::Orphaned => {
+            info!(
+                "Gateway of Device {} no longer exists, marking it \
+                 disconnected",
+                device.name_any()
+            );
+            DeviceStatusUpdate::default().mark_disconnected()
+        },
+        Verdict::Unreachable => {
+            warn!(
+                "Device {} stopped sending heartbeats, marking it unreachable",
+                device.name_any()
+            );
+            DeviceStatusUpdate::default().mark_unreachable()
+        },
+        Verdict::Reachable => {
+            info!("Device {} is reachable again", device.name_any());
+            DeviceStatusUpdate::default().phase(DevicePhase::Connected)
+        },
+    };
+
+    update
+        .apply(ctx.devices.clone(), (*device).clone())
+        .await
+        .map_err(ReconcileError::Kube)?;
+
+    Ok(Action::requeue(REQUEUE_INTERVAL))
+}
+
+pub fn error_policy(
+    device: Arc<Device>,
+    error: &ReconcileError,
+    _ctx: Arc<Context>,
+) -> Action {
+    warn!("Failed to reconcile Device {}: {error}", device.name_any());
+    Action::requeue(ERROR_REQUEUE_INTERVAL)
+}
+
+/// Returns the UID of the gateway Pod, or `None` if it does not exist.
+async fn gateway_uid(
+    client: &Client,
+    gateway: &GatewayReference,
+) -> Result<Option<String>, ReconcileError> {
+    let pods: Api<Pod> = match gateway.pod.namespace.as_deref() {
+        Some(namespace) => Api::namespaced(client.clone(), namespace),
+        None => Api::default_namespaced(client.clone()),
+    };
+    let pod = pods
+        .get_opt(&gateway.pod.name)
+        .await
+        .map_err(ReconcileError::Kube)?;
+    Ok(pod.map(|pod| pod.metadata.uid.unwrap_or_default()))
+}
+
+/// Decides whether the status of a Device has gone stale.
+///
+/// `gateway_uid` is `None` if the device has no gateway, and `Some(None)`
+/// if its gateway Pod no longer exists. A gateway Pod with another UID than
+/// the one recorded by the gateway is a replacement with the same name,
+/// which does not hold the connection either. Gateways that did not record
+/// their UID are only checked for existence.
+fn assess(
+    status: &DeviceStatus,
+    gateway_uid: Option<Option<&str>>,
+    now: DateTime<Utc>,
+    heartbeat_timeout: TimeDelta,
+) -> Option<Verdict> {
+    let phase = status.phase();
+    if !matches!(phase, DevicePhase::Connected | DevicePhase::Unreachable) {
+        return None;
+    }
+
+    if let Some(uid) = gateway_uid {
+        let recorded = status.gateway().and_then(|g| g.uid.as_deref());
+        let replaced = uid
+            .is_none_or(|uid| recorded.is_some_and(|recorded| recorded != uid));
+        if replaced {
+            return Some(Verdict::Orphaned);
+        }
+    }
+
+    let stale = status
+        .last_heartbeat()
+        .or(status.connected_since())
+        .is_some_and(|t| now.signed_duration_since(t) > heartbeat_timeout);
+
+    match (phase, stale) {
+        (DevicePhase::Connected, true) => Some(Verdict::Unreachable),
+        (DevicePhase::Unreachable, false) => Some(Verdict::Reachable),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(seconds, 0).unwrap()
+    }
+
+    fn status(phase: &str, connected: i64, heartbeat: i64) -> DeviceStatus {
+        serde_json::from_value(json!({
+            "phase": phase,
+            "gateway": {
+                "kind": "Pod",
+                "name": "gateway-0",
+                "namespace": "wasmbed",
+                "uid": "uid-0",
+            },
+            "connectedSince": at(connected),
+            "lastHeartbeat": at(heartbeat),
+        }))
+        .unwrap()
+    }
+
+    const TIMEOUT: TimeDelta = TimeDelta::seconds(300);
+
+    #[test]
+    fn fresh_heartbeat_keeps_device_connected() {
+        let status = status("Connected", 100, 500);
+        assert_eq!(
+            assess(&status, Some(Some("uid-0")), at(700), TIMEOUT),
+            None
+        );
+    }
+
+    #[test]
+    fn stale_heartbeat_marks_device_unreachable() {
+        let status = status("Connected", 100, 500);
+        assert_eq!(
+            assess(&status, Some(Some("uid-0")), at(900), TIMEOUT),
+            Some(Verdict::Unreachable)
+        );
+    }
+
+    #[test]
+    fn resumed_heartbeat_marks_device_reachable() {
+        let status = status("Unreachable", 100, 500);
+        assert_eq!(
+            assess(&status, Some(Some("uid-0")), at(700), TIMEOUT),
+            Some(Verdict::Reachable)
+        );
+    }
+
+    #[test]
+    fn missing_or_replaced_gateway_orphans_device() {
+        let status = status("Connected", 100, 500);
+        assert_eq!(
+            assess(&status, Some(None), at(600), TIMEOUT),
+            Some(Verdict::Orphaned)
+        );
+        assert_eq!(
+            assess(&status, Some(Some("uid-1")), at(600), TIMEOUT),
+            Some(Verdict::Orphaned)
+        );
+    }
+
+    #[test]
+    fn gateway_without_recorded_uid_is_checked_for_existence() {
+        let status: DeviceStatus = serde_json::from_value(json!({
+            "phase": "Connected",
+            "gateway": GatewayReference::new("wasmbed", "gateway-0"),
+            "lastHeartbeat": at(500),
+        }))
+        .unwrap();
+        assert_eq!(
+            assess(&status, Some(Some("uid-1")), at(600), TIMEOUT),
+            None
+        );
+        assert_eq!(
+            assess(&status, Some(None), at(600), TIMEOUT),
+            Some(Verdict::Orphaned)
+        );
+    }
+
+    #[test]
+    fn disconnected_device_is_left_alone() {
+        let status = status("Disconnected", 100, 500);
+        assert_eq!(assess(&status, Some(None), at(900), TIMEOUT), None);
+    }
+}
@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::net::{IpAddr, SocketAddr};
+
+use derive_more::{Display, Error};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{Api, Client};
+
+use wasmbed_gateway_api::{
+    ClientError, GatewayClient, Operation, OperationId, OperationRequest,
+};
+use wasmbed_types::GatewayReference;
+
+/// Control APIs of the gateway Pods, reached through the Pod IP addresses.
+pub struct Gateways {
+    client: Client,
+    api: GatewayClient,
+    port: u16,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum GatewayError {
+    #[display("Kubernetes API error: {_0}")]
+    Kube(kube::Error),
+    #[display("Gateway Pod {_0} not found")]
+    NotFound(#[error(not(source))] String),
+    #[display("Gateway Pod {_0} has no IP address")]
+    NoAddress(#[error(not(source))] String),
+    #[display("{_0}")]
+    Api(ClientError),
+}
+
+impl Gateways {
+    pub fn new(client: Client, api: GatewayClient, port: u16) -> Self {
+        Self { client, api, port }
+    }
+
+    pub async fn submit(
+        &self,
+        gateway: &GatewayReference,
+        request: &OperationRequest,
+    ) -> Result<Operation, GatewayError> {
+        let address = self.address(gateway).await?;
+        self.api
+            .submit(address, request)
+            .await
+            .map_err(GatewayError::Api)
+    }
+
+    pub async fn operation(
+        &self,
+        gateway: &GatewayReference,
+        id: OperationId,
+    ) -> Result<Operation, GatewayError> {
+        let address = self.address(gateway).await?;
+        self.api
+            .operation(address, id)
+            .await
+            .map_err(GatewayError::Api)
+    }
+
+    async fn address(
+        &self,
+        gateway: &GatewayReference,
+    ) -> Result<SocketAddr, GatewayError> {
+        let name = &gateway.pod.name;
+        let pods: Api<Pod> = match &gateway.pod.namespace {
+            Some(namespace) => Api::namespaced(self.client.clone(), namespace),
+            None => Api::default_namespaced(self.client.clone()),
+        };
+
+        let pod = pods
+            .get_opt(name)
+            .await
+            .map_err(GatewayError::Kube)?
+            .ok_or_else(|| GatewayError::NotFound(name.clone()))?;
+
+        let ip = pod
+            .status
+            .and_then(|status| status.pod_ip)
+            .and_then(|ip| ip.parse::<IpAddr>().ok())
+            .ok_or_else(|| GatewayError::NoAddress(name.clone()))?;
+
+        Ok(SocketAddr::new(ip, self.port))
+    }
+}
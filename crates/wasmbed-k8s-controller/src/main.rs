@@ -1,4 +1,163 @@
 // SPDX-License-Identifier: AGPL-3.0
 // Copyright © 2025 Wasmbed contributors
 
-pub fn main() {}
+mod application;
+mod device;
+mod gateway;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, bail};
+use chrono::TimeDelta;
+use clap::Parser;
+use futures::StreamExt;
+use kube::{Api, Client};
+use kube::runtime::{Controller, watcher};
+use tracing::{Level, info, warn};
+use rustls_pki_types::PrivatePkcs8KeyDer;
+use tracing_subscriber::FmtSubscriber;
+
+use wasmbed_gateway_api::GatewayClient;
+use wasmbed_k8s_resource::{Application, Device};
+
+use crate::gateway::Gateways;
+
+#[derive(Parser)]
+#[command(disable_help_subcommand = true)]
+struct Args {
+    #[arg(long, env = "WASMBED_CONTROLLER_NAMESPACE")]
+    namespace: String,
+    /// Seconds without a persisted heartbeat after which a connected device
+    /// is marked unreachable. Must exceed the heartbeat persist interval of
+    /// the gateways.
+    #[arg(
+        long,
+        env = "WASMBED_CONTROLLER_HEARTBEAT_TIMEOUT",
+        default_value_t = 300
+    )]
+    heartbeat_timeout: i64,
+    /// Heartbeat persist interval of the gateways, in seconds, see
+    /// `WASMBED_GATEWAY_HEARTBEAT_PERSIST_INTERVAL`.
+    #[arg(
+        long,
+        env = "WASMBED_CONTROLLER_HEARTBEAT_PERSIST_INTERVAL",
+        default_value_t = 120
+    )]
+    heartbeat_persist_interval: i64,
+    /// Authority issuing the certificates of the gateways.
+    #[arg(long, env = "WASMBED_CONTROLLER_GATEWAY_CA")]
+    gateway_ca: PathBuf,
+    /// Certificate presented to the control API of the gateways.
+    #[arg(long, env = "WASMBED_CONTROLLER_CERTIFICATE")]
+    certificate: PathBuf,
+    #[arg(long, env = "WASMBED_CONTROLLER_PRIVATE_KEY")]
+    private_key: PathBuf,
+    /// Port of the control API of the gateways.
+    #[arg(
+        long,
+        env = "WASMBED_CONTROLLER_GATEWAY_CONTROL_PORT",
+        default_value_t = 4424
+    )]
+    gateway_control_port: u16,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let args = Args::parse();
+
+    let Some(heartbeat_timeout) =
+        TimeDelta::try_seconds(args.heartbeat_timeout)
+            .filter(|timeout| *timeout > TimeDelta::zero())
+    else {
+        bail!("Invalid heartbeat timeout: {}", args.heartbeat_timeout);
+    };
+    // Devices would be marked unreachable between two persisted heartbeats.
+    if args.heartbeat_timeout <= args.heartbeat_persist_interval {
+        bail!(
+            "Heartbeat timeout ({}s) must exceed the heartbeat persist \
+             interval of the gateways ({}s)",
+            args.heartbeat_timeout,
+            args.heartbeat_persist_interval,
+        );
+    }
+
+    let read = |path: &PathBuf, what: &str| {
+        std::fs::read(path).with_context(|| {
+            format!("Failed to read {what} from {}", path.display())
+        })
+    };
+    let gateway_client = GatewayClient::new(
+        read(&args.gateway_ca, "gateway CA certificate")?.into(),
+        read(&args.certificate, "certificate")?.into(),
+        PrivatePkcs8KeyDer::from(read(&args.private_key, "private key")?)
+            .into(),
+    )?;
+
+    let client = Client::try_default().await?;
+    let applications: Api<Application> =
+        Api::namespaced(client.clone(), &args.namespace);
+    let devices: Api<Device> = Api::namespaced(client.clone(), &args.namespace);
+
+    let application_context = Arc::new(application::Context {
+        client: client.clone(),
+        applications: applications.clone(),
+        devices: devices.clone(),
+        gateways: Gateways::new(
+            client.clone(),
+            gateway_client,
+            args.gateway_control_port,
+        ),
+    });
+    let device_context = Arc::new(device::Context {
+        client: client.clone(),
+        devices: devices.clone(),
+        heartbeat_timeout,
+    });
+
+    let application_controller =
+        Controller::new(applications, watcher::Config::default());
+    let store = application_controller.store();
+
+    info!("Starting controllers in namespace {}", args.namespace);
+
+    let application_controller = application_controller
+        .watches(devices.clone(), watcher::Config::default(), move |device| {
+            application::applications_for_device(&store, &device)
+        })
+        .shutdown_on_signal()
+        .run(
+            application::reconcile,
+            application::error_policy,
+            application_context,
+        )
+        .for_each(|result| async move {
+            match result {
+                Ok((object, _)) => {
+                    info!("Reconciled Application {}", object.name)
+                },
+                Err(e) => warn!("Application controller error: {e}"),
+            }
+        });
+
+    let device_controller =
+        Controller::new(devices, watcher::Config::default())
+            .shutdown_on_signal()
+            .run(device::reconcile, device::error_policy, device_context)
+            .for_each(|result| async move {
+                if let Err(e) = result {
+                    warn!("Device controller error: {e}");
+                }
+            });
+
+    futures::join!(application_controller, device_controller);
+
+    info!("Controllers stopped");
+
+    Ok(())
+}
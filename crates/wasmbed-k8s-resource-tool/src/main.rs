@@ -1,14 +1,22 @@
 // SPDX-License-Identifier: AGPL-3.0
 // Copyright © 2025 Wasmbed contributors
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result, anyhow};
+use chrono::{TimeDelta, Utc};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use k8s_openapi::ByteString;
+use k8s_openapi::api::core::v1::ConfigMapKeySelector;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
 use kube::CustomResourceExt;
 use rustls_pki_types::CertificateDer;
 
-use wasmbed_k8s_resource::{Device, DeviceSpec};
+use wasmbed_k8s_resource::{
+    Application, ApplicationSpec, Device, DeviceSpec, Enrollment,
+    EnrollmentSpec, ModuleSource, ResourceLimits,
+};
 use wasmbed_types::PublicKey;
 
 #[derive(Parser)]
@@ -30,12 +38,36 @@ enum Command {
 
 #[derive(Subcommand)]
 enum Resource {
+    /// Generate the CRD YAML for the "Application" resource.
+    Application,
     /// Generate the CRD YAML for the "Device" resource.
     Device,
+    /// Generate the CRD YAML for the "Enrollment" resource.
+    Enrollment,
 }
 
 #[derive(Subcommand)]
 enum ManifestResource {
+    /// Generate a manifest for the "Application" resource.
+    Application {
+        /// Metadata.name of the resource.
+        #[arg(long)]
+        name: String,
+        #[command(flatten)]
+        module: ModuleArgs,
+        /// Label the target devices must have, as KEY=VALUE. Can be repeated.
+        #[arg(long = "selector", value_name = "KEY=VALUE")]
+        selector: Vec<String>,
+        /// Name of the exported function invoked to start the application.
+        #[arg(long, default_value = "_start")]
+        entry_point: String,
+        /// Maximum number of 64 KiB linear memory pages.
+        #[arg(long)]
+        max_memory_pages: Option<u32>,
+        /// Amount of fuel the entry point may consume.
+        #[arg(long)]
+        fuel: Option<u64>,
+    },
     /// Generate a manifest for the "Device" resource.
     Device {
         /// Metadata.name of the resource.
@@ -45,6 +77,82 @@ enum ManifestResource {
         #[arg(long = "cert", value_name = "FILE")]
         certificate: PathBuf,
     },
+    /// Generate a manifest for the "Enrollment" resource.
+    Enrollment {
+        /// Metadata.name of the resource.
+        #[arg(long)]
+        name: String,
+        /// Number of seconds from now during which devices can enroll.
+        #[arg(long, value_name = "SECONDS")]
+        duration: u32,
+        /// Label assigned to the enrolled devices, as KEY=VALUE. Can be
+        /// repeated.
+        #[arg(long = "label", value_name = "KEY=VALUE")]
+        labels: Vec<String>,
+    },
+}
+
+#[derive(ClapArgs)]
+#[group(required = true, multiple = false)]
+struct ModuleArgs {
+    /// OCI image reference of the Wasm module.
+    #[arg(long)]
+    image: Option<String>,
+    /// Path to a Wasm module to embed in the manifest.
+    #[arg(long = "inline", value_name = "FILE")]
+    inline: Option<PathBuf>,
+    /// ConfigMap holding the Wasm module, as NAME/KEY.
+    #[arg(long = "config-map", value_name = "NAME/KEY")]
+    config_map: Option<String>,
+}
+
+impl ModuleArgs {
+    fn into_source(self) -> Result<ModuleSource> {
+        if let Some(image) = self.image {
+            return Ok(ModuleSource::Image(image));
+        }
+
+        if let Some(path) = self.inline {
+            let bytes = std::fs::read(&path).with_context(|| {
+                format!("Failed to read Wasm module from {}", path.display())
+            })?;
+            return Ok(ModuleSource::Inline(ByteString(bytes)));
+        }
+
+        if let Some(config_map) = self.config_map {
+            let (name, key) = config_map.split_once('/').ok_or_else(|| {
+                anyhow!("Invalid ConfigMap reference: {config_map}")
+            })?;
+            return Ok(ModuleSource::ConfigMapKeyRef(ConfigMapKeySelector {
+                name: name.to_string(),
+                key: key.to_string(),
+                optional: None,
+            }));
+        }
+
+        Err(anyhow!("No Wasm module source specified"))
+    }
+}
+
+fn parse_labels(labels: &[String]) -> Result<BTreeMap<String, String>> {
+    labels
+        .iter()
+        .map(|label| {
+            label
+                .split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .ok_or_else(|| anyhow!("Invalid label: {label}"))
+        })
+        .collect()
+}
+
+fn parse_selector(labels: &[String]) -> Result<LabelSelector> {
+    let match_labels = parse_labels(labels)?;
+
+    Ok(LabelSelector {
+        match_labels: (!match_labels.is_empty()).then_some(match_labels),
+        match_expressions: None,
+    })
 }
 
 pub fn main() -> Result<()> {
@@ -53,14 +161,49 @@ pub fn main() -> Result<()> {
     let args = Args::parse();
     match args.command {
         Command::GenerateCrd(resource) => match resource {
+            Resource::Application => {
+                std::io::stdout().write_all(
+                    &serde_yaml::to_string(&Application::crd())?.into_bytes(),
+                )?;
+            },
             Resource::Device => {
                 std::io::stdout().write_all(
                     &serde_yaml::to_string(&Device::crd())?.into_bytes(),
                 )?;
             },
+            Resource::Enrollment => {
+                std::io::stdout().write_all(
+                    &serde_yaml::to_string(&Enrollment::crd())?.into_bytes(),
+                )?;
+            },
         },
 
         Command::GenerateManifest(resource) => match resource {
+            ManifestResource::Application {
+                name,
+                module,
+                selector,
+                entry_point,
+                max_memory_pages,
+                fuel,
+            } => {
+                let application = Application::new(
+                    &name,
+                    ApplicationSpec {
+                        module: module.into_source()?,
+                        device_selector: parse_selector(&selector)?,
+                        entry_point,
+                        resources: ResourceLimits {
+                            max_memory_pages,
+                            fuel,
+                        },
+                    },
+                );
+
+                std::io::stdout().write_all(
+                    &serde_yaml::to_string(&application)?.into_bytes(),
+                )?;
+            },
             ManifestResource::Device { name, certificate } => {
                 let cert_bytes =
                     std::fs::read(&certificate).with_context(|| {
@@ -82,6 +225,26 @@ pub fn main() -> Result<()> {
                 std::io::stdout()
                     .write_all(&serde_yaml::to_string(&device)?.into_bytes())?;
             },
+            ManifestResource::Enrollment {
+                name,
+                duration,
+                labels,
+            } => {
+                let expires_at = Utc::now()
+                    .checked_add_signed(TimeDelta::seconds(duration.into()))
+                    .ok_or_else(|| anyhow!("Invalid duration: {duration}"))?;
+                let enrollment = Enrollment::new(
+                    &name,
+                    EnrollmentSpec {
+                        expires_at,
+                        device_labels: parse_labels(&labels)?,
+                    },
+                );
+
+                std::io::stdout().write_all(
+                    &serde_yaml::to_string(&enrollment)?.into_bytes(),
+                )?;
+            },
         },
     };
 
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::ByteString;
+use k8s_openapi::api::core::v1::ConfigMapKeySelector;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use wasmbed_types::GatewayReference;
+
+#[derive(
+    Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema, CustomResource,
+)]
+#[kube(
+    namespaced,
+    group = "wasmbed.github.io",
+    version = "v0",
+    kind = "Application",
+    status = "ApplicationStatus",
+    printcolumn = r#"{"name":"Phase","type":"string","jsonPath":".status.phase"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationSpec {
+    /// Where to obtain the Wasm module from
+    pub module: ModuleSource,
+
+    /// Selects the devices the application is deployed to
+    #[serde(default)]
+    pub device_selector: LabelSelector,
+
+    /// Name of the exported function invoked to start the application
+    #[serde(default = "default_entry_point")]
+    pub entry_point: String,
+
+    /// Resources the application is allowed to consume on a device
+    #[serde(default)]
+    pub resources: ResourceLimits,
+}
+
+fn default_entry_point() -> String {
+    "_start".to_string()
+}
+
+/// Source of the Wasm module of an application. Exactly one source must be
+/// specified.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ModuleSource {
+    /// OCI image reference of an artifact containing the module
+    Image(String),
+    /// Module bytecode embedded in the resource
+    Inline(#[schemars(with = "String")] ByteString),
+    /// Key of a ConfigMap whose binary data holds the module bytecode
+    ConfigMapKeyRef(ConfigMapKeySelector),
+}
+
+#[derive(
+    Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceLimits {
+    /// Maximum number of 64 KiB linear memory pages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_memory_pages: Option<u32>,
+
+    /// Amount of fuel the entry point may consume before being interrupted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fuel: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationStatus {
+    /// Current application phase, aggregated from all deployments
+    #[serde(default)]
+    phase: ApplicationPhase,
+
+    /// Human-readable explanation of the current phase
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+
+    /// Deployment state on each selected device
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deployments: Vec<DeviceDeployment>,
+}
+
+impl ApplicationStatus {
+    pub fn phase(&self) -> ApplicationPhase {
+        self.phase
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    pub fn deployments(&self) -> &[DeviceDeployment] {
+        &self.deployments
+    }
+
+    pub fn deployment(&self, device: &str) -> Option<&DeviceDeployment> {
+        self.deployments.iter().find(|d| d.device == device)
+    }
+}
+
+#[derive(
+    Deserialize,
+    Serialize,
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    PartialEq,
+    JsonSchema,
+    Default,
+)]
+#[serde(rename_all = "PascalCase")]
+pub enum ApplicationPhase {
+    #[default]
+    Pending,
+    Deploying,
+    Running,
+    Failed,
+}
+
+/// State of an application on a single device.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceDeployment {
+    /// Name of the device
+    pub device: String,
+
+    /// Gateway pod the device was connected to when the deployment started
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<GatewayReference>,
+
+    /// Current deployment phase
+    #[serde(default)]
+    pub phase: DeploymentPhase,
+
+    /// Human-readable explanation of the current phase
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    /// Timestamp of the last phase change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_transition_time: Option<DateTime<Utc>>,
+
+    /// Identifier of the gateway operation deploying the application, while
+    /// the deployment is in progress
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operation: Option<u64>,
+}
+
+impl DeviceDeployment {
+    pub fn new(device: String, gateway: Option<GatewayReference>) -> Self {
+        Self {
+            device,
+            gateway,
+            phase: DeploymentPhase::default(),
+            reason: None,
+            last_transition_time: Some(Utc::now()),
+            operation: None,
+        }
+    }
+
+    /// Moves the deployment to `phase`, recording when it happened. Leaving
+    /// a phase ends the operation started in it.
+    pub fn transition(
+        mut self,
+        phase: DeploymentPhase,
+        reason: Option<String>,
+    ) -> Self {
+        if self.phase != phase {
+            self.last_transition_time = Some(Utc::now());
+            self.operation = None;
+        }
+        self.phase = phase;
+        self.reason = reason;
+        self
+    }
+}
+
+#[derive(
+    Deserialize,
+    Serialize,
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    PartialEq,
+    JsonSchema,
+    Default,
+)]
+#[serde(rename_all = "PascalCase")]
+pub enum DeploymentPhase {
+    #[default]
+    Deploying,
+    Running,
+    Failed,
+    /// The gateway could not pull the module image
+    PullFailed,
+}
+
+impl DeploymentPhase {
+    pub fn is_failed(self) -> bool {
+        matches!(self, Self::Failed | Self::PullFailed)
+    }
+}
@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use kube::{Api, Error, ResourceExt};
+use kube::api::{Patch, PatchParams};
+use serde_json::json;
+
+use crate::application::{Application, ApplicationPhase, DeviceDeployment};
+
+// This builder uses Option<Option<T>> to distinguish between "don't update"
+// (None) and "set to None" (Some(None))
+#[derive(Default)]
+pub struct ApplicationStatusUpdate {
+    phase: Option<ApplicationPhase>,
+    reason: Option<Option<String>>,
+    deployments: Option<Vec<DeviceDeployment>>,
+}
+
+impl ApplicationStatusUpdate {
+    pub fn phase(mut self, phase: ApplicationPhase) -> Self {
+        self.phase = Some(phase);
+        self
+    }
+
+    pub fn reason(mut self, reason: Option<String>) -> Self {
+        self.reason = Some(reason);
+        self
+    }
+
+    pub fn deployments(mut self, deployments: Vec<DeviceDeployment>) -> Self {
+        self.deployments = Some(deployments);
+        self
+    }
+
+    pub async fn apply(
+        self,
+        api: Api<Application>,
+        application: Application,
+    ) -> Result<Application, Error> {
+        let name = application.metadata.name.as_ref().ok_or_else(|| {
+            Error::Service(
+                format!(
+                    "Application {:?} has no name",
+                    application.uid().unwrap_or_default()
+                )
+                .into(),
+            )
+        })?;
+
+        let mut status_patch = json!({});
+
+        if let Some(map) = status_patch.as_object_mut() {
+            if let Some(phase) = self.phase {
+                map.insert("phase".to_string(), json!(phase));
+            }
+            if let Some(reason) = self.reason {
+                map.insert("reason".to_string(), json!(reason));
+            }
+            if let Some(deployments) = self.deployments {
+                map.insert("deployments".to_string(), json!(deployments));
+            }
+        } else {
+            return Err(Error::Service(
+                "status_patch is not a JSON object".into(),
+            ));
+        }
+
+        let patch = json!({
+            "status": status_patch
+        });
+
+        api.patch_status(name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await
+    }
+}
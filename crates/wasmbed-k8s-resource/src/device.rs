@@ -2,11 +2,14 @@
 // Copyright © 2025 Wasmbed contributors
 
 use chrono::{DateTime, Utc};
-use kube::CustomResource;
+use kube::{CustomResource, ResourceExt};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use wasmbed_types::{GatewayReference, PublicKey};
+use wasmbed_types::{DeviceClass, GatewayReference, PublicKey, UnknownDeviceClass};
+
+/// Label holding the [`DeviceClass`] of a device, `small` when missing.
+pub const DEVICE_CLASS_LABEL: &str = "wasmbed.github.io/device-class";
 
 #[derive(
     Clone,
@@ -31,7 +34,17 @@ pub struct DeviceSpec {
     pub public_key: PublicKey<'static>,
 }
 
+impl Device {
+    /// Class of the device, from its [`DEVICE_CLASS_LABEL`].
+    pub fn class(&self) -> Result<DeviceClass, UnknownDeviceClass> {
+        self.labels()
+            .get(DEVICE_CLASS_LABEL)
+            .map_or(Ok(DeviceClass::default()), |class| class.parse())
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct DeviceStatus {
     /// Current device phase
     #[serde(default)]
@@ -48,13 +61,72 @@ pub struct DeviceStatus {
     /// Last heartbeat timestamp
     #[serde(skip_serializing_if = "Option::is_none")]
     last_heartbeat: Option<DateTime<Utc>>,
+
+    /// What the device advertised it is able to run when it last connected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    capabilities: Option<DeviceCapabilities>,
+}
+
+impl DeviceStatus {
+    pub fn phase(&self) -> DevicePhase {
+        self.phase
+    }
+
+    pub fn gateway(&self) -> Option<&GatewayReference> {
+        self.gateway.as_ref()
+    }
+
+    pub fn connected_since(&self) -> Option<DateTime<Utc>> {
+        self.connected_since
+    }
+
+    pub fn last_heartbeat(&self) -> Option<DateTime<Utc>> {
+        self.last_heartbeat
+    }
+
+    pub fn capabilities(&self) -> Option<&DeviceCapabilities> {
+        self.capabilities.as_ref()
+    }
+}
+
+#[derive(
+    Deserialize, Serialize, Clone, Debug, Default, PartialEq, JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceCapabilities {
+    pub firmware_version: String,
+    /// Name and version of the Wasm runtime
+    pub runtime_version: String,
+    /// Memory available to applications, in bytes
+    pub free_memory: u32,
+    /// Modules from which applications can import host functions
+    #[serde(default)]
+    pub host_modules: Vec<String>,
+    /// Largest message the device accepts, in bytes
+    pub max_message_size: u32,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema, Default)]
+#[derive(
+    Deserialize,
+    Serialize,
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    PartialEq,
+    JsonSchema,
+    Default,
+)]
 #[serde(rename_all = "PascalCase")]
 pub enum DevicePhase {
     #[default]
     Pending,
+    /// The device is being enrolled through a gateway in pairing mode
+    Enrolling,
+    /// The device has been enrolled and has not reconnected yet
+    Enrolled,
     Connected,
     Disconnected,
+    /// The device is connected but its heartbeats stopped arriving
+    Unreachable,
 }
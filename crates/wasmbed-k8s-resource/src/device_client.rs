@@ -1,13 +1,15 @@
 // SPDX-License-Identifier: AGPL-3.0
 // Copyright © 2025 Wasmbed contributors
 
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use kube::{Api, Error};
-use kube::api::{ListParams, Patch, PatchParams};
+use kube::api::{ListParams, ObjectMeta, Patch, PatchParams, PostParams};
 use kube::core::Expression;
 use serde_json::json;
 
-use crate::device::{Device, DevicePhase};
+use crate::device::{Device, DeviceCapabilities, DevicePhase, DeviceSpec};
 use wasmbed_types::{GatewayReference, PublicKey};
 
 impl Device {
@@ -21,6 +23,25 @@ impl Device {
         let devices = api.list(&params).await?;
         Ok(devices.iter().next().cloned())
     }
+
+    /// Creates a Device for `public_key`, with a name generated by the API
+    /// server.
+    pub async fn enroll(
+        api: Api<Device>,
+        public_key: PublicKey<'static>,
+        labels: BTreeMap<String, String>,
+    ) -> Result<Self, Error> {
+        let device = Device {
+            metadata: ObjectMeta {
+                generate_name: Some("device-".to_string()),
+                labels: Some(labels),
+                ..ObjectMeta::default()
+            },
+            spec: DeviceSpec { public_key },
+            status: None,
+        };
+        api.create(&PostParams::default(), &device).await
+    }
 }
 
 // This builder uses Option<Option<T>> to distinguish between "don't update"
@@ -31,6 +52,7 @@ pub struct DeviceStatusUpdate {
     gateway: Option<Option<GatewayReference>>,
     connected_since: Option<Option<DateTime<Utc>>>,
     last_heartbeat: Option<Option<DateTime<Utc>>>,
+    capabilities: Option<DeviceCapabilities>,
 }
 
 impl DeviceStatusUpdate {
@@ -54,6 +76,11 @@ impl DeviceStatusUpdate {
         self
     }
 
+    pub fn capabilities(mut self, capabilities: DeviceCapabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
     pub fn mark_connected(self, gateway: GatewayReference) -> Self {
         self.phase(DevicePhase::Connected)
             .gateway(Some(gateway))
@@ -66,6 +93,18 @@ impl DeviceStatusUpdate {
             .connected_since(None)
     }
 
+    pub fn mark_enrolling(self, gateway: GatewayReference) -> Self {
+        self.phase(DevicePhase::Enrolling).gateway(Some(gateway))
+    }
+
+    pub fn mark_enrolled(self) -> Self {
+        self.phase(DevicePhase::Enrolled).gateway(None)
+    }
+
+    pub fn mark_unreachable(self) -> Self {
+        self.phase(DevicePhase::Unreachable)
+    }
+
     pub fn update_heartbeat(self) -> Self {
         self.last_heartbeat(Some(Utc::now()))
     }
@@ -102,6 +141,9 @@ impl DeviceStatusUpdate {
             if let Some(last_heartbeat) = self.last_heartbeat {
                 map.insert("lastHeartbeat".to_string(), json!(last_heartbeat));
             }
+            if let Some(capabilities) = self.capabilities {
+                map.insert("capabilities".to_string(), json!(capabilities));
+            }
         } else {
             return Err(Error::Service(
                 "status_patch is not a JSON object".into(),
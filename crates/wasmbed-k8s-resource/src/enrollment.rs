@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Enables pairing mode: while an Enrollment is open, gateways accept
+/// devices whose public key is not registered yet and create a Device for
+/// each of them.
+#[derive(
+    Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema, CustomResource,
+)]
+#[kube(
+    namespaced,
+    group = "wasmbed.github.io",
+    version = "v0",
+    kind = "Enrollment",
+    printcolumn = r#"{"name":"Expires","type":"date","jsonPath":".spec.expiresAt"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrollmentSpec {
+    /// Time after which devices are no longer enrolled
+    pub expires_at: DateTime<Utc>,
+
+    /// Labels assigned to the Devices created during the enrollment
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub device_labels: BTreeMap<String, String>,
+}
+
+impl Enrollment {
+    /// Whether devices can still be enrolled at `now`.
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        now < self.spec.expires_at
+    }
+}
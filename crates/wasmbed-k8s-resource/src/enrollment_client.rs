@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use chrono::{DateTime, Utc};
+use kube::{Api, Error};
+use kube::api::ListParams;
+
+use crate::enrollment::Enrollment;
+
+impl Enrollment {
+    /// Returns an Enrollment that is open at `now`, if any. When several
+    /// are open, the one expiring last is returned.
+    pub async fn find_open(
+        api: Api<Enrollment>,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Self>, Error> {
+        let enrollments = api.list(&ListParams::default()).await?;
+        Ok(enrollments
+            .into_iter()
+            .filter(|enrollment| enrollment.is_open(now))
+            .max_by_key(|enrollment| enrollment.spec.expires_at))
+    }
+}
@@ -1,12 +1,28 @@
 // SPDX-License-Identifier: AGPL-3.0
 // Copyright © 2025 Wasmbed contributors
 
+mod application;
 mod device;
+mod enrollment;
 
+#[cfg(feature = "client")]
+mod application_client;
 #[cfg(feature = "client")]
 mod device_client;
+#[cfg(feature = "client")]
+mod enrollment_client;
 
-pub use device::{Device, DeviceSpec, DevicePhase};
+pub use application::{
+    Application, ApplicationPhase, ApplicationSpec, ApplicationStatus,
+    DeploymentPhase, DeviceDeployment, ModuleSource, ResourceLimits,
+};
+pub use device::{
+    DEVICE_CLASS_LABEL, Device, DeviceCapabilities, DevicePhase, DeviceSpec,
+    DeviceStatus,
+};
+pub use enrollment::{Enrollment, EnrollmentSpec};
 
+#[cfg(feature = "client")]
+pub use application_client::ApplicationStatusUpdate;
 #[cfg(feature = "client")]
 pub use device_client::DeviceStatusUpdate;
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::path::PathBuf;
+
+use tokio::fs;
+use tracing::warn;
+
+use crate::digest::Digest;
+
+/// Modules already pulled, stored on disk under their digest.
+///
+/// Content is verified against its digest when read, a corrupted entry is
+/// treated as missing.
+#[derive(Clone, Debug)]
+pub(crate) struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub async fn get(&self, digest: &Digest) -> Option<Vec<u8>> {
+        let path = self.path(digest);
+        let content = match fs::read(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Unable to read cached module {}: {e}", path.display());
+                return None;
+            },
+        };
+
+        if Digest::of(&content) != *digest {
+            warn!("Discarding corrupted cached module {}", path.display());
+            return None;
+        }
+        Some(content)
+    }
+
+    pub async fn put(
+        &self,
+        digest: &Digest,
+        content: &[u8],
+    ) -> std::io::Result<()> {
+        let dir = self.dir.join("sha256");
+        fs::create_dir_all(&dir).await?;
+
+        // Written aside and renamed, so that readers never see a partial
+        // module.
+        let partial = dir.join(format!("{}.partial", digest.hex()));
+        fs::write(&partial, content).await?;
+        fs::rename(&partial, self.path(digest)).await
+    }
+
+    fn path(&self, digest: &Digest) -> PathBuf {
+        self.dir.join("sha256").join(digest.hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn corrupted_entries_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf());
+        let digest = Digest::of(b"\0asm");
+
+        assert_eq!(cache.get(&digest).await, None);
+        cache.put(&digest, b"\0asm").await.unwrap();
+        assert_eq!(cache.get(&digest).await.as_deref(), Some(&b"\0asm"[..]));
+
+        std::fs::write(dir.path().join("sha256").join(digest.hex()), b"")
+            .unwrap();
+        assert_eq!(cache.get(&digest).await, None);
+    }
+}
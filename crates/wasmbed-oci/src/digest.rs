@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::fmt;
+use std::str::FromStr;
+
+use derive_more::{Display, Error};
+use sha2::{Digest as _, Sha256};
+
+/// SHA-256 content digest, written `sha256:<hex>` in OCI documents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Digest([u8; 32]);
+
+#[derive(Debug, Display, Error)]
+pub enum DigestError {
+    #[display("Unsupported digest algorithm in {_0}")]
+    UnsupportedAlgorithm(#[error(not(source))] String),
+    #[display("Invalid digest {_0}")]
+    Invalid(#[error(not(source))] String),
+}
+
+impl Digest {
+    /// Computes the digest of `content`.
+    pub fn of(content: &[u8]) -> Self {
+        Self(Sha256::digest(content).into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// The hexadecimal encoding of the digest, without algorithm.
+    pub fn hex(&self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+impl FromStr for Digest {
+    type Err = DigestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(encoded) = s.strip_prefix("sha256:") else {
+            return Err(DigestError::UnsupportedAlgorithm(s.to_string()));
+        };
+        // Uppercase hexadecimal is not allowed by the OCI specification.
+        if encoded.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(DigestError::Invalid(s.to_string()));
+        }
+
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(encoded, &mut bytes)
+            .map_err(|_| DigestError::Invalid(s.to_string()))?;
+        Ok(Self(bytes))
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sha256:{}", self.hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_round_trip() {
+        let digest = Digest::of(b"");
+        let encoded = digest.to_string();
+        assert_eq!(
+            encoded,
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(encoded.parse::<Digest>().unwrap(), digest);
+    }
+
+    #[test]
+    fn invalid_digests() {
+        assert!(matches!(
+            "sha512:00".parse::<Digest>(),
+            Err(DigestError::UnsupportedAlgorithm(_))
+        ));
+        assert!(matches!(
+            "sha256:00".parse::<Digest>(),
+            Err(DigestError::Invalid(_))
+        ));
+        assert!(matches!(
+            format!("sha256:{}", "A".repeat(64)).parse::<Digest>(),
+            Err(DigestError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Client pulling Wasm modules published as OCI artifacts.
+//!
+//! An artifact is an image manifest whose layer of media type
+//! [`WASM_MEDIA_TYPE`] holds the module bytecode.
+
+mod cache;
+mod digest;
+mod manifest;
+mod puller;
+mod reference;
+
+pub use digest::{Digest, DigestError};
+pub use puller::{PullConfig, PullError, Pulled, Puller};
+pub use reference::{Reference, ReferenceError};
+
+/// Media type of the layer holding the module bytecode
+pub const WASM_MEDIA_TYPE: &str = "application/wasm";
+
+/// Media type of the module layer of artifacts following the CNCF Wasm OCI
+/// artifact layout, also accepted
+pub const WASM_CONTENT_LAYER_MEDIA_TYPE: &str =
+    "application/vnd.wasm.content.layer.v1+wasm";
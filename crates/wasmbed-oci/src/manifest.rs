@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use serde::Deserialize;
+
+use crate::{WASM_CONTENT_LAYER_MEDIA_TYPE, WASM_MEDIA_TYPE};
+
+/// Media types of the manifests requested from registries
+pub(crate) const ACCEPTED_MANIFEST_TYPES: &str = "application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+
+/// The parts of an image manifest needed to find the module layer.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Manifest {
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+}
+
+impl Manifest {
+    /// Whether the manifest is an image manifest rather than an index.
+    pub fn is_image_manifest(&self) -> bool {
+        self.media_type.as_deref().is_none_or(|media_type| {
+            ACCEPTED_MANIFEST_TYPES
+                .split(", ")
+                .any(|accepted| accepted == media_type)
+        })
+    }
+
+    /// The layer holding the module bytecode.
+    pub fn wasm_layer(&self) -> Option<&Descriptor> {
+        self.layers.iter().find(|layer| {
+            layer.media_type == WASM_MEDIA_TYPE
+                || layer.media_type == WASM_CONTENT_LAYER_MEDIA_TYPE
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_wasm_layer() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": {
+                    "mediaType": "application/vnd.oci.empty.v1+json",
+                    "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a",
+                    "size": 2
+                },
+                "layers": [
+                    {
+                        "mediaType": "text/plain",
+                        "digest": "sha256:00",
+                        "size": 1
+                    },
+                    {
+                        "mediaType": "application/wasm",
+                        "digest": "sha256:01",
+                        "size": 8
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert!(manifest.is_image_manifest());
+        assert_eq!(manifest.wasm_layer().unwrap().digest, "sha256:01");
+    }
+}
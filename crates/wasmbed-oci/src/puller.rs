@@ -0,0 +1,493 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use derive_more::{Display, Error};
+use reqwest::header::{ACCEPT, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Response, StatusCode};
+use rustls::{ClientConfig, RootCertStore};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::cache::Cache;
+use crate::digest::{Digest, DigestError};
+use crate::manifest::{ACCEPTED_MANIFEST_TYPES, Manifest};
+use crate::reference::Reference;
+
+/// Largest manifest accepted, in bytes
+const MAX_MANIFEST_SIZE: u64 = 4 * 1024 * 1024;
+
+pub struct PullConfig {
+    /// Directory where pulled modules are cached
+    pub cache_dir: PathBuf,
+    /// Registries reached over plain HTTP instead of HTTPS, as they appear
+    /// in references, e.g. `localhost:5000`
+    pub insecure_registries: Vec<String>,
+    /// Largest module accepted, in bytes
+    pub max_module_size: u64,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum PullError {
+    #[display("Unable to create HTTP client: {_0}")]
+    Client(reqwest::Error),
+    #[display("Registry request failed: {_0}")]
+    Http(reqwest::Error),
+    #[display("Registry replied {status} to {url}")]
+    Status {
+        url: String,
+        status: StatusCode,
+    },
+    #[display("Registry authentication failed: {_0}")]
+    Authentication(#[error(not(source))] String),
+    #[display("Invalid manifest: {_0}")]
+    Manifest(serde_json::Error),
+    #[display("Unsupported manifest type {_0}")]
+    UnsupportedManifest(#[error(not(source))] String),
+    #[display("Manifest has no Wasm layer")]
+    NoWasmLayer,
+    #[display("Invalid layer digest: {_0}")]
+    InvalidDigest(DigestError),
+    #[display("Content exceeds the limit of {limit} bytes")]
+    TooLarge {
+        limit: u64,
+    },
+    #[display("Digest mismatch: expected {expected}, got {actual}")]
+    DigestMismatch {
+        expected: Digest,
+        actual: Digest,
+    },
+}
+
+/// A module pulled from a registry or from the cache.
+#[derive(Debug)]
+pub struct Pulled {
+    pub digest: Digest,
+    pub bytecode: Vec<u8>,
+}
+
+/// Pulls Wasm modules from OCI registries, anonymously or with the bearer
+/// tokens registries grant to anonymous clients.
+#[derive(Clone)]
+pub struct Puller {
+    http: reqwest::Client,
+    cache: Cache,
+    insecure_registries: Vec<String>,
+    max_module_size: u64,
+}
+
+impl Puller {
+    pub fn new(config: PullConfig) -> Result<Self, PullError> {
+        let root_store = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let tls = ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+
+        let http = reqwest::Client::builder()
+            .use_preconfigured_tls(tls)
+            .build()
+            .map_err(PullError::Client)?;
+
+        Ok(Self {
+            http,
+            cache: Cache::new(config.cache_dir),
+            insecure_registries: config.insecure_registries,
+            max_module_size: config.max_module_size,
+        })
+    }
+
+    /// Pulls the module of the artifact `reference` points to.
+    ///
+    /// The manifest is always fetched, and verified if the reference pins
+    /// its digest. The module itself is only downloaded if it is not cached
+    /// yet, and is verified against the digest of its layer.
+    pub async fn pull(
+        &self,
+        reference: &Reference,
+    ) -> Result<Pulled, PullError> {
+        let scheme = if self
+            .insecure_registries
+            .iter()
+            .any(|registry| registry == reference.registry())
+        {
+            "http"
+        } else {
+            "https"
+        };
+        let mut session = Session {
+            http: &self.http,
+            reference,
+            token: None,
+        };
+        let base = format!(
+            "{scheme}://{}/v2/{}",
+            reference.registry_host(),
+            reference.repository()
+        );
+
+        let manifest_url =
+            format!("{base}/manifests/{}", reference.manifest_reference());
+        let response = session
+            .get(&manifest_url, Some(ACCEPTED_MANIFEST_TYPES))
+            .await?;
+        let manifest = read_limited(response, MAX_MANIFEST_SIZE).await?;
+        if let Some(expected) = reference.digest() {
+            verify(expected, &manifest)?;
+        }
+
+        let manifest: Manifest =
+            serde_json::from_slice(&manifest).map_err(PullError::Manifest)?;
+        if !manifest.is_image_manifest() {
+            return Err(PullError::UnsupportedManifest(
+                manifest.media_type.unwrap_or_default(),
+            ));
+        }
+        let layer = manifest.wasm_layer().ok_or(PullError::NoWasmLayer)?;
+        let digest: Digest =
+            layer.digest.parse().map_err(PullError::InvalidDigest)?;
+        if layer.size > self.max_module_size {
+            return Err(PullError::TooLarge {
+                limit: self.max_module_size,
+            });
+        }
+
+        if let Some(bytecode) = self.cache.get(&digest).await {
+            debug!("Module {digest} of {reference} found in cache");
+            return Ok(Pulled { digest, bytecode });
+        }
+
+        let response =
+            session.get(&format!("{base}/blobs/{digest}"), None).await?;
+        let bytecode = read_limited(response, self.max_module_size).await?;
+        verify(&digest, &bytecode)?;
+
+        if let Err(e) = self.cache.put(&digest, &bytecode).await {
+            warn!("Unable to cache module {digest} of {reference}: {e}");
+        }
+        Ok(Pulled { digest, bytecode })
+    }
+}
+
+/// Requests made to a registry for a single pull, sharing the token
+/// obtained on the first authentication challenge.
+struct Session<'a> {
+    http: &'a reqwest::Client,
+    reference: &'a Reference,
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+impl Session<'_> {
+    async fn get(
+        &mut self,
+        url: &str,
+        accept: Option<&str>,
+    ) -> Result<Response, PullError> {
+        let mut response = self.send(url, accept).await?;
+
+        if response.status() == StatusCode::UNAUTHORIZED && self.token.is_none()
+        {
+            let challenge = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_bearer_challenge)
+                .ok_or_else(|| {
+                    PullError::Authentication(format!(
+                        "Unsupported challenge from {url}"
+                    ))
+                })?;
+            self.token = Some(self.authenticate(challenge).await?);
+            response = self.send(url, accept).await?;
+        }
+
+        if !response.status().is_success() {
+            return Err(PullError::Status {
+                url: url.to_string(),
+                status: response.status(),
+            });
+        }
+        Ok(response)
+    }
+
+    async fn send(
+        &self,
+        url: &str,
+        accept: Option<&str>,
+    ) -> Result<Response, PullError> {
+        let mut request = self.http.get(url);
+        if let Some(accept) = accept {
+            request = request.header(ACCEPT, accept);
+        }
+        if let Some(token) = &self.token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        request.send().await.map_err(PullError::Http)
+    }
+
+    /// Requests an anonymous token from the authorization service named in
+    /// the challenge.
+    async fn authenticate(
+        &self,
+        mut challenge: HashMap<String, String>,
+    ) -> Result<String, PullError> {
+        let realm = challenge.remove("realm").ok_or_else(|| {
+            PullError::Authentication("Challenge without realm".to_string())
+        })?;
+        let scope = challenge.remove("scope").unwrap_or_else(|| {
+            format!("repository:{}:pull", self.reference.repository())
+        });
+        let mut query = vec![("scope", scope)];
+        if let Some(service) = challenge.remove("service") {
+            query.push(("service", service));
+        }
+
+        let response = self
+            .http
+            .get(&realm)
+            .query(&query)
+            .send()
+            .await
+            .map_err(PullError::Http)?;
+        if !response.status().is_success() {
+            return Err(PullError::Authentication(format!(
+                "{realm} replied {}",
+                response.status()
+            )));
+        }
+
+        let token: TokenResponse =
+            response.json().await.map_err(PullError::Http)?;
+        token.token.or(token.access_token).ok_or_else(|| {
+            PullError::Authentication(format!("No token in reply of {realm}"))
+        })
+    }
+}
+
+/// Parses the parameters of a `Bearer` challenge, e.g.
+/// `Bearer realm="https://auth.example.com/token",service="registry"`.
+fn parse_bearer_challenge(header: &str) -> Option<HashMap<String, String>> {
+    let (scheme, mut rest) = header.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    let mut parameters = HashMap::new();
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let value = value.trim_start();
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"')?,
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        parameters.insert(key.trim().to_ascii_lowercase(), value.to_string());
+        rest = remaining.trim_start_matches([',', ' ']);
+    }
+    Some(parameters)
+}
+
+/// Reads the body of `response`, failing as soon as it exceeds `limit`.
+async fn read_limited(
+    mut response: Response,
+    limit: u64,
+) -> Result<Vec<u8>, PullError> {
+    let mut content = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(PullError::Http)? {
+        content.extend_from_slice(&chunk);
+        if u64::try_from(content.len()).unwrap_or(u64::MAX) > limit {
+            return Err(PullError::TooLarge { limit });
+        }
+    }
+    Ok(content)
+}
+
+fn verify(expected: &Digest, content: &[u8]) -> Result<(), PullError> {
+    let actual = Digest::of(content);
+    if actual != *expected {
+        return Err(PullError::DigestMismatch {
+            expected: *expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::Router;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use tokio::net::TcpListener;
+
+    const MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    /// Registry stand-in serving a single artifact `app:1.0`.
+    struct Registry {
+        manifest: String,
+        blob: Vec<u8>,
+        /// Token required in requests, if any
+        token: Option<&'static str>,
+        blob_requests: AtomicUsize,
+        addr: SocketAddr,
+    }
+
+    fn manifest(layer_digest: &Digest) -> String {
+        format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "layers": [{{
+                    "mediaType": "application/wasm",
+                    "digest": "{layer_digest}",
+                    "size": {}
+                }}]
+            }}"#,
+            MODULE.len()
+        )
+    }
+
+    async fn serve(
+        manifest: String,
+        blob: Vec<u8>,
+        token: Option<&'static str>,
+    ) -> Arc<Registry> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let registry = Arc::new(Registry {
+            manifest,
+            blob,
+            token,
+            blob_requests: AtomicUsize::new(0),
+            addr: listener.local_addr().unwrap(),
+        });
+
+        let router = Router::new()
+            .route("/v2/app/manifests/1.0", get(manifest_handler))
+            .route("/v2/app/blobs/{digest}", get(blob_handler))
+            .route("/token", get(async || r#"{"token":"secret"}"#))
+            .with_state(Arc::clone(&registry));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        registry
+    }
+
+    fn authorized(registry: &Registry, headers: &HeaderMap) -> bool {
+        registry.token.is_none_or(|token| {
+            headers
+                .get(AUTHORIZATION)
+                .is_some_and(|value| *value == format!("Bearer {token}"))
+        })
+    }
+
+    fn challenge(registry: &Registry) -> axum::response::Response {
+        let challenge = format!(
+            r#"Bearer realm="http://{}/token",service="stand-in",scope="repository:app:pull""#,
+            registry.addr
+        );
+        (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, challenge)])
+            .into_response()
+    }
+
+    async fn manifest_handler(
+        State(registry): State<Arc<Registry>>,
+        headers: HeaderMap,
+    ) -> axum::response::Response {
+        if !authorized(&registry, &headers) {
+            return challenge(&registry);
+        }
+        registry.manifest.clone().into_response()
+    }
+
+    async fn blob_handler(
+        State(registry): State<Arc<Registry>>,
+        headers: HeaderMap,
+    ) -> axum::response::Response {
+        if !authorized(&registry, &headers) {
+            return challenge(&registry);
+        }
+        registry.blob_requests.fetch_add(1, Ordering::Relaxed);
+        registry.blob.clone().into_response()
+    }
+
+    fn puller(registry: &Registry, cache_dir: PathBuf) -> Puller {
+        Puller::new(PullConfig {
+            cache_dir,
+            insecure_registries: vec![registry.addr.to_string()],
+            max_module_size: 1024,
+        })
+        .unwrap()
+    }
+
+    fn reference(registry: &Registry) -> Reference {
+        format!("{}/app:1.0", registry.addr).parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn pulled_modules_are_cached() {
+        let digest = Digest::of(MODULE);
+        let registry = serve(manifest(&digest), MODULE.to_vec(), None).await;
+        let cache = tempfile::tempdir().unwrap();
+        let puller = puller(&registry, cache.path().to_path_buf());
+
+        for _ in 0..2 {
+            let pulled = puller.pull(&reference(&registry)).await.unwrap();
+            assert_eq!(pulled.digest, digest);
+            assert_eq!(pulled.bytecode, MODULE);
+        }
+        assert_eq!(registry.blob_requests.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn tampered_modules_are_rejected() {
+        let digest = Digest::of(MODULE);
+        let registry = serve(manifest(&digest), b"\0asm".to_vec(), None).await;
+        let cache = tempfile::tempdir().unwrap();
+        let puller = puller(&registry, cache.path().to_path_buf());
+
+        let result = puller.pull(&reference(&registry)).await;
+        assert!(matches!(
+            result,
+            Err(PullError::DigestMismatch { expected, .. }) if expected == digest
+        ));
+        assert!(!cache.path().join("sha256").join(digest.hex()).exists());
+    }
+
+    #[tokio::test]
+    async fn anonymous_token_is_requested() {
+        let digest = Digest::of(MODULE);
+        let registry =
+            serve(manifest(&digest), MODULE.to_vec(), Some("secret")).await;
+        let cache = tempfile::tempdir().unwrap();
+        let puller = puller(&registry, cache.path().to_path_buf());
+
+        let pulled = puller.pull(&reference(&registry)).await.unwrap();
+        assert_eq!(pulled.bytecode, MODULE);
+    }
+
+    #[test]
+    fn parse_challenge() {
+        let parameters = parse_bearer_challenge(
+            r#"Bearer realm="https://auth.example.com/token",service=registry, scope="repository:app:pull,push""#,
+        )
+        .unwrap();
+        assert_eq!(parameters["realm"], "https://auth.example.com/token");
+        assert_eq!(parameters["service"], "registry");
+        assert_eq!(parameters["scope"], "repository:app:pull,push");
+        assert!(parse_bearer_challenge("Basic realm=\"x\"").is_none());
+    }
+}
@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::fmt;
+use std::str::FromStr;
+
+use derive_more::{Display, Error};
+
+use crate::digest::{Digest, DigestError};
+
+/// Registry assumed when a reference does not name one
+const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Host serving the API of [`DEFAULT_REGISTRY`]
+const DEFAULT_REGISTRY_HOST: &str = "registry-1.docker.io";
+
+const DEFAULT_TAG: &str = "latest";
+
+/// Reference to an artifact in a registry, such as
+/// `registry.example.com/app:1.0` or `registry.example.com/app@sha256:...`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reference {
+    registry: String,
+    repository: String,
+    tag: Option<String>,
+    digest: Option<Digest>,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum ReferenceError {
+    #[display("Invalid reference {_0}")]
+    Invalid(#[error(not(source))] String),
+    #[display("Invalid digest in reference: {_0}")]
+    Digest(DigestError),
+}
+
+impl Reference {
+    /// Registry host and optional port, e.g. `registry.example.com:5000`.
+    pub fn registry(&self) -> &str {
+        &self.registry
+    }
+
+    /// Host serving the registry API.
+    pub fn registry_host(&self) -> &str {
+        match self.registry.as_str() {
+            DEFAULT_REGISTRY => DEFAULT_REGISTRY_HOST,
+            registry => registry,
+        }
+    }
+
+    pub fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// Digest pinning the manifest, if any.
+    pub fn digest(&self) -> Option<&Digest> {
+        self.digest.as_ref()
+    }
+
+    /// How the manifest is addressed in the registry API: by digest if the
+    /// reference pins one, otherwise by tag.
+    pub fn manifest_reference(&self) -> String {
+        match (&self.digest, &self.tag) {
+            (Some(digest), _) => digest.to_string(),
+            (None, Some(tag)) => tag.clone(),
+            (None, None) => DEFAULT_TAG.to_string(),
+        }
+    }
+}
+
+impl FromStr for Reference {
+    type Err = ReferenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ReferenceError::Invalid(s.to_string());
+
+        let (name, digest) = match s.split_once('@') {
+            Some((name, digest)) => {
+                (name, Some(digest.parse().map_err(ReferenceError::Digest)?))
+            },
+            None => (s, None),
+        };
+
+        // A colon after the last slash separates the tag, any other one
+        // belongs to the registry port.
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => {
+                (name, Some(tag.to_string()))
+            },
+            _ => (name, None),
+        };
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((registry, repository))
+                if registry.contains(['.', ':']) || registry == "localhost" =>
+            {
+                (registry.to_string(), repository.to_string())
+            },
+            _ if !name.contains('/') => {
+                (DEFAULT_REGISTRY.to_string(), format!("library/{name}"))
+            },
+            _ => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+        };
+
+        let valid_repository = !repository.is_empty()
+            && repository.split('/').all(|component| {
+                !component.is_empty()
+                    && component.chars().all(|c| {
+                        c.is_ascii_lowercase()
+                            || c.is_ascii_digit()
+                            || matches!(c, '.' | '_' | '-')
+                    })
+            });
+        let valid_tag = tag.as_deref().is_none_or(|tag| {
+            !tag.is_empty()
+                && tag.len() <= 128
+                && tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+        });
+        if registry.is_empty() || !valid_repository || !valid_tag {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{tag}")?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{digest}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_references() {
+        let reference: Reference =
+            "registry.example.com:5000/team/app:1.0".parse().unwrap();
+        assert_eq!(reference.registry(), "registry.example.com:5000");
+        assert_eq!(reference.repository(), "team/app");
+        assert_eq!(reference.tag(), Some("1.0"));
+        assert_eq!(reference.manifest_reference(), "1.0");
+
+        let reference: Reference = "localhost/app".parse().unwrap();
+        assert_eq!(reference.registry(), "localhost");
+        assert_eq!(reference.manifest_reference(), "latest");
+
+        let reference: Reference = "app".parse().unwrap();
+        assert_eq!(reference.registry_host(), "registry-1.docker.io");
+        assert_eq!(reference.repository(), "library/app");
+
+        let digest = Digest::of(b"manifest");
+        let reference: Reference =
+            format!("ghcr.io/org/app:1.0@{digest}").parse().unwrap();
+        assert_eq!(reference.tag(), Some("1.0"));
+        assert_eq!(reference.digest(), Some(&digest));
+        assert_eq!(reference.manifest_reference(), digest.to_string());
+        assert_eq!(
+            reference.to_string(),
+            format!("ghcr.io/org/app:1.0@{digest}")
+        );
+    }
+
+    #[test]
+    fn reject_invalid_references() {
+        for reference in ["", "Registry.io/App", "example.com/", "app:", "a//b"]
+        {
+            assert!(reference.parse::<Reference>().is_err(), "{reference}");
+        }
+    }
+}
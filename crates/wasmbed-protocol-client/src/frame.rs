@@ -0,0 +1,34 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Frames of [`wasmbed_protocol::frame`] on an `embedded-io-async` stream.
+
+use embedded_io_async::{Read, Write};
+use wasmbed_protocol::frame::{LENGTH_SIZE, decode_header};
+
+use crate::Error;
+
+/// Reads a frame into `buf`, returning the encoded envelope.
+///
+/// The stream can no longer be read once a frame larger than `buf` has been
+/// rejected with [`Error::FrameTooLarge`].
+pub async fn read<'b, R: Read>(
+    reader: &mut R,
+    buf: &'b mut [u8],
+) -> Result<&'b [u8], Error<R::Error>> {
+    let mut header = [0u8; LENGTH_SIZE];
+    reader.read_exact(&mut header).await?;
+    let size = decode_header(header, buf.len())?;
+    let body = buf.get_mut(..size).ok_or(Error::BufferTooSmall)?;
+    reader.read_exact(body).await?;
+    Ok(body)
+}
+
+/// Writes a frame encoded with [`wasmbed_protocol::frame::encode`].
+pub async fn write<W: Write>(
+    writer: &mut W,
+    frame: &[u8],
+) -> Result<(), Error<W::Error>> {
+    writer.write_all(frame).await.map_err(Error::Io)?;
+    writer.flush().await.map_err(Error::Io)
+}
@@ -0,0 +1,594 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod frame;
+
+use core::fmt::Debug;
+use core::future::Future;
+
+use derive_more::Display;
+use embedded_io_async::{BufRead, Read, ReadExactError, Write};
+use minicbor::decode::Error as DecodeError;
+
+use wasmbed_protocol::frame::Error as FrameError;
+use wasmbed_protocol::{
+    CapabilitiesRef, ClientMessageRef, Envelope, ErrorCode, MAX_FRAME_SIZE,
+    MessageId, ServerEnvelopeRef, ServerMessageRef, Version, Versions,
+};
+#[cfg(feature = "alloc")]
+use wasmbed_protocol::{ClientMessage, ServerEnvelope, ServerMessage};
+
+#[derive(Debug, Display)]
+pub enum Error<E> {
+    #[display("I/O error: {_0:?}")]
+    Io(E),
+    #[display("Connection closed by the server")]
+    Closed,
+    #[display("Frame of {size} bytes exceeds the buffer of {capacity} bytes")]
+    FrameTooLarge {
+        size: usize,
+        capacity: usize,
+    },
+    #[display("Message does not fit in the buffer")]
+    BufferTooSmall,
+    #[display("CBOR decode error: {_0}")]
+    Decode(DecodeError),
+    #[display("No protocol version supported by the server")]
+    NoCommonVersion,
+    #[display("Request rejected: {code}")]
+    Rejected {
+        code: ErrorCode,
+    },
+    #[display("Unexpected reply to the request")]
+    UnexpectedReply,
+}
+
+impl<E: Debug> core::error::Error for Error<E> {}
+
+impl<E> From<ReadExactError<E>> for Error<E> {
+    fn from(error: ReadExactError<E>) -> Self {
+        match error {
+            ReadExactError::UnexpectedEof => Self::Closed,
+            ReadExactError::Other(e) => Self::Io(e),
+        }
+    }
+}
+
+impl<E> From<FrameError> for Error<E> {
+    fn from(error: FrameError) -> Self {
+        match error {
+            FrameError::TooLarge { size, limit } => Self::FrameTooLarge {
+                size,
+                capacity: limit,
+            },
+            FrameError::BufferTooSmall => Self::BufferTooSmall,
+        }
+    }
+}
+
+/// Application logic of a [`Client`].
+pub trait Handler {
+    /// Called for every message the server sends on its own initiative,
+    /// e.g. a deployment request. The returned message, if any, is sent
+    /// back as the reply. It may borrow from the handler or from `message`,
+    /// which borrows from the receive buffer of the client.
+    fn handle<'a>(
+        &'a mut self,
+        message: ServerMessageRef<'a>,
+    ) -> impl Future<Output = Option<ClientMessageRef<'a>>>;
+}
+
+/// Device side of a connection to a gateway.
+///
+/// Envelopes are encoded into and decoded from the buffers provided by the
+/// caller, which bound the size of the messages exchanged. Messages are
+/// borrowed from these buffers, unless the owned messages of the `alloc`
+/// feature are used. The client does not keep time: heartbeats are sent
+/// when the caller calls [`heartbeat`](Self::heartbeat).
+pub struct Client<'b, S> {
+    stream: S,
+    rx: &'b mut [u8],
+    tx: &'b mut [u8],
+    version: Version,
+    last_message_id: MessageId,
+}
+
+impl<'b, S: Read + Write> Client<'b, S> {
+    pub fn new(stream: S, rx: &'b mut [u8], tx: &'b mut [u8]) -> Self {
+        Self {
+            stream,
+            rx,
+            tx,
+            version: Version::V0,
+            last_message_id: MessageId::default(),
+        }
+    }
+
+    /// Protocol version used on the connection.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Sends `message` without waiting for a reply.
+    pub async fn send_ref(
+        &mut self,
+        message: ClientMessageRef<'_>,
+    ) -> Result<MessageId, Error<S::Error>> {
+        self.last_message_id = self.last_message_id.next();
+        let message_id = self.last_message_id;
+        let envelope = Envelope {
+            version: self.version,
+            message_id,
+            message,
+        };
+        write_envelope(&mut self.stream, self.tx, &envelope).await?;
+        Ok(message_id)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Sends `message` without waiting for a reply.
+    pub async fn send(
+        &mut self,
+        message: ClientMessage,
+    ) -> Result<MessageId, Error<S::Error>> {
+        self.send_ref(message.borrowed()).await
+    }
+
+    /// Waits for the next message of the server, borrowing its strings and
+    /// byte strings from the receive buffer instead of copying them. A
+    /// receive buffer of [`MAX_FRAME_SIZE`] bytes fits any message.
+    pub async fn receive_ref(
+        &mut self,
+    ) -> Result<ServerEnvelopeRef<'_>, Error<S::Error>> {
+        let size = self.read_frame().await?;
+        self.decode(size)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Waits for the next message of the server.
+    pub async fn receive(&mut self) -> Result<ServerEnvelope, Error<S::Error>> {
+        let envelope = self.receive_ref().await?;
+        Ok(ServerEnvelope {
+            version: envelope.version,
+            message_id: envelope.message_id,
+            message: envelope.message.into(),
+        })
+    }
+
+    /// Waits for the next message of the server and passes it to `handler`,
+    /// sending back its reply.
+    pub async fn dispatch(
+        &mut self,
+        handler: &mut impl Handler,
+    ) -> Result<(), Error<S::Error>> {
+        let size = self.read_frame().await?;
+        self.handle(size, handler).await
+    }
+
+    /// Reads a frame into the receive buffer, returning its size.
+    async fn read_frame(&mut self) -> Result<usize, Error<S::Error>> {
+        let frame = frame::read(&mut self.stream, self.rx).await?;
+        Ok(frame.len())
+    }
+
+    /// Decodes the frame of `size` bytes in the receive buffer.
+    fn decode(
+        &self,
+        size: usize,
+    ) -> Result<ServerEnvelopeRef<'_>, Error<S::Error>> {
+        let frame = self.rx.get(..size).ok_or(Error::BufferTooSmall)?;
+        minicbor::decode(frame).map_err(Error::Decode)
+    }
+
+    /// Passes the message in the frame of `size` bytes in the receive
+    /// buffer to `handler`, sending back its reply.
+    async fn handle(
+        &mut self,
+        size: usize,
+        handler: &mut impl Handler,
+    ) -> Result<(), Error<S::Error>> {
+        let frame = self.rx.get(..size).ok_or(Error::BufferTooSmall)?;
+        let envelope: ServerEnvelopeRef<'_> =
+            minicbor::decode(frame).map_err(Error::Decode)?;
+        let reply = match envelope.message {
+            ServerMessageRef::Unknown { .. } => Some(ClientMessageRef::Error {
+                code: ErrorCode::UnknownMessage,
+                detail: "Unknown message tag",
+                in_reply_to: envelope.message_id,
+            }),
+            message => handler.handle(message).await,
+        };
+        let Some(message) = reply else {
+            return Ok(());
+        };
+        let reply = Envelope {
+            version: self.version,
+            message_id: envelope.message_id,
+            message,
+        };
+        write_envelope(&mut self.stream, self.tx, &reply).await
+    }
+
+    /// Sends `message` and waits for the reply of the server, borrowed from
+    /// the receive buffer. Messages the server sends in the meantime are
+    /// passed to `handler`.
+    pub async fn request_ref(
+        &mut self,
+        message: ClientMessageRef<'_>,
+        handler: &mut impl Handler,
+    ) -> Result<ServerMessageRef<'_>, Error<S::Error>> {
+        let message_id = self.send_ref(message).await?;
+        let size = loop {
+            let size = self.read_frame().await?;
+            let envelope = self.decode(size)?;
+            if envelope.message_id == message_id && is_reply(&envelope.message)
+            {
+                break size;
+            }
+            self.handle(size, handler).await?;
+        };
+        match self.decode(size)?.message {
+            ServerMessageRef::Error { code, .. } => {
+                Err(Error::Rejected { code })
+            },
+            message => Ok(message),
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Sends `message` and waits for the reply of the server. Messages the
+    /// server sends in the meantime are passed to `handler`.
+    pub async fn request(
+        &mut self,
+        message: ClientMessage,
+        handler: &mut impl Handler,
+    ) -> Result<ServerMessage, Error<S::Error>> {
+        let reply = self.request_ref(message.borrowed(), handler).await?;
+        Ok(reply.into())
+    }
+
+    /// Negotiates the most recent protocol version supported by both sides.
+    pub async fn negotiate(
+        &mut self,
+        handler: &mut impl Handler,
+    ) -> Result<Version, Error<S::Error>> {
+        let message = ClientMessageRef::Negotiate {
+            versions: Versions::Slice(Version::SUPPORTED_NUMBERS),
+        };
+        match self.request_ref(message, handler).await? {
+            ServerMessageRef::Negotiated { version } => {
+                self.version = version;
+                Ok(version)
+            },
+            ServerMessageRef::VersionMismatch { .. } => {
+                Err(Error::NoCommonVersion)
+            },
+            _ => Err(Error::UnexpectedReply),
+        }
+    }
+
+    /// Advertises the `capabilities` of the device.
+    pub async fn hello(
+        &mut self,
+        capabilities: CapabilitiesRef<'_>,
+        handler: &mut impl Handler,
+    ) -> Result<(), Error<S::Error>> {
+        let message = ClientMessageRef::Hello { capabilities };
+        match self.request_ref(message, handler).await? {
+            ServerMessageRef::HelloAck => Ok(()),
+            _ => Err(Error::UnexpectedReply),
+        }
+    }
+
+    /// Sends a heartbeat and waits for its acknowledgment.
+    pub async fn heartbeat(
+        &mut self,
+        handler: &mut impl Handler,
+    ) -> Result<(), Error<S::Error>> {
+        match self
+            .request_ref(ClientMessageRef::Heartbeat, handler)
+            .await?
+        {
+            ServerMessageRef::HeartbeatAck => Ok(()),
+            _ => Err(Error::UnexpectedReply),
+        }
+    }
+
+    /// Tells the server the connection is about to be closed and waits for
+    /// its acknowledgment.
+    pub async fn disconnect(
+        &mut self,
+        reason: &str,
+        handler: &mut impl Handler,
+    ) -> Result<(), Error<S::Error>> {
+        let message = ClientMessageRef::Disconnect { reason };
+        match self.request_ref(message, handler).await? {
+            ServerMessageRef::DisconnectAck => Ok(()),
+            _ => Err(Error::UnexpectedReply),
+        }
+    }
+}
+
+impl<S: BufRead + Read + Write> Client<'_, S> {
+    /// Waits until the server has sent something, without reading it, so
+    /// that the caller can wait for other events at the same time and then
+    /// [`dispatch`](Client::dispatch) the message. Unlike `dispatch`, the
+    /// wait can be cancelled without losing data if the `fill_buf` of the
+    /// stream can.
+    pub async fn readable(&mut self) -> Result<(), Error<S::Error>> {
+        match self.stream.fill_buf().await {
+            Ok([]) => Err(Error::Closed),
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+}
+
+/// Encodes `envelope` in `tx` and writes it to `writer`.
+async fn write_envelope<W: Write>(
+    writer: &mut W,
+    tx: &mut [u8],
+    envelope: &Envelope<ClientMessageRef<'_>>,
+) -> Result<(), Error<W::Error>> {
+    let frame = wasmbed_protocol::frame::encode(envelope, tx, MAX_FRAME_SIZE)?;
+    frame::write(writer, frame).await
+}
+
+/// Whether `message` can be the reply to a client request. Message
+/// identifiers are chosen independently by both sides, so a message the
+/// server sends on its own initiative may carry the identifier of a pending
+/// request.
+fn is_reply(message: &ServerMessageRef<'_>) -> bool {
+    matches!(
+        message,
+        ServerMessageRef::HeartbeatAck
+            | ServerMessageRef::EnrollAck { .. }
+            | ServerMessageRef::HelloAck
+            | ServerMessageRef::Negotiated { .. }
+            | ServerMessageRef::VersionMismatch { .. }
+            | ServerMessageRef::DisconnectAck
+            | ServerMessageRef::Error { .. }
+    )
+}
+
+#[cfg(test)]
+#[cfg(feature = "alloc")]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use embedded_io_async::ErrorType;
+    use wasmbed_protocol::ClientEnvelope;
+
+    /// Stream reading from a slice and writing to a vector.
+    struct Loopback<'a> {
+        input: &'a [u8],
+        output: Vec<u8>,
+    }
+
+    impl ErrorType for Loopback<'_> {
+        type Error = embedded_io_async::ErrorKind;
+    }
+
+    impl Read for Loopback<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            Ok(self.input.read(buf).await.unwrap())
+        }
+    }
+
+    impl Write for Loopback<'_> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    /// Replies to every `StopApplication`.
+    struct Stopper;
+
+    impl Handler for Stopper {
+        async fn handle<'a>(
+            &'a mut self,
+            message: ServerMessageRef<'a>,
+        ) -> Option<ClientMessageRef<'a>> {
+            match message {
+                ServerMessageRef::StopApplication { app_id } => {
+                    Some(ClientMessageRef::ApplicationStopped { app_id })
+                },
+                _ => None,
+            }
+        }
+    }
+
+    /// Polls `future` once, the streams used in tests never being pending.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut cx = Context::from_waker(Waker::noop());
+        match pin!(future).poll(&mut cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("Future is pending"),
+        }
+    }
+
+    fn frames(envelopes: &[ServerEnvelope]) -> Vec<u8> {
+        let mut buf = [0u8; 256];
+        let mut frames = Vec::new();
+        for envelope in envelopes {
+            frames.extend_from_slice(
+                wasmbed_protocol::frame::encode(
+                    envelope,
+                    &mut buf,
+                    MAX_FRAME_SIZE,
+                )
+                .unwrap(),
+            );
+        }
+        frames
+    }
+
+    fn decode_frames(mut input: &[u8]) -> Vec<ClientEnvelope> {
+        let mut buf = [0u8; 256];
+        let mut envelopes = Vec::new();
+        while !input.is_empty() {
+            let frame = block_on(frame::read(&mut input, &mut buf)).unwrap();
+            envelopes.push(minicbor::decode(frame).unwrap());
+        }
+        envelopes
+    }
+
+    fn envelope(id: u32, message: ServerMessage) -> ServerEnvelope {
+        let mut message_id = MessageId::default();
+        for _ in 0..id {
+            message_id = message_id.next();
+        }
+        ServerEnvelope {
+            version: Version::V0,
+            message_id,
+            message,
+        }
+    }
+
+    #[test]
+    fn test_frame_too_large() {
+        let input = frames(&[envelope(0, ServerMessage::HeartbeatAck)]);
+        let mut buf = [0u8; 2];
+        assert!(matches!(
+            block_on(frame::read(&mut input.as_slice(), &mut buf)),
+            Err(Error::FrameTooLarge { capacity: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_receive_ref() {
+        let chunk = ServerMessage::TransferChunk {
+            app_id: "app-0".into(),
+            offset: 0,
+            data: vec![0x42; 64],
+        };
+        let input = frames(&[envelope(1, chunk)]);
+        let stream = Loopback {
+            input: &input,
+            output: Vec::new(),
+        };
+        let (mut rx, mut tx) = ([0u8; 256], [0u8; 0]);
+        let mut client = Client::new(stream, &mut rx, &mut tx);
+
+        let envelope = block_on(client.receive_ref()).unwrap();
+        assert_eq!(
+            envelope.message,
+            ServerMessageRef::TransferChunk {
+                app_id: "app-0",
+                offset: 0,
+                data: &[0x42; 64],
+            }
+        );
+    }
+
+    #[test]
+    fn test_request_dispatches_server_messages() {
+        let stop = ServerMessage::StopApplication {
+            app_id: "app-0".into(),
+        };
+        // The server request shares its identifier with the heartbeat.
+        let input = frames(&[
+            envelope(1, stop),
+            envelope(1, ServerMessage::HeartbeatAck),
+        ]);
+        let stream = Loopback {
+            input: &input,
+            output: Vec::new(),
+        };
+        let (mut rx, mut tx) = ([0u8; 256], [0u8; 256]);
+        let mut client = Client::new(stream, &mut rx, &mut tx);
+
+        block_on(client.heartbeat(&mut Stopper)).unwrap();
+
+        let sent = decode_frames(&client.into_inner().output);
+        let messages: Vec<_> = sent.into_iter().map(|e| e.message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                ClientMessage::Heartbeat,
+                ClientMessage::ApplicationStopped {
+                    app_id: "app-0".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let input = frames(&[
+            envelope(
+                0,
+                ServerMessage::Unknown {
+                    tag: 1000,
+                    raw: vec![0x81, 0x19, 0x03, 0xe8],
+                },
+            ),
+            envelope(1, ServerMessage::VersionMismatch { supported: vec![7] }),
+        ]);
+        let stream = Loopback {
+            input: &input,
+            output: Vec::new(),
+        };
+        let (mut rx, mut tx) = ([0u8; 256], [0u8; 256]);
+        let mut client = Client::new(stream, &mut rx, &mut tx);
+
+        assert!(matches!(
+            block_on(client.negotiate(&mut Stopper)),
+            Err(Error::NoCommonVersion)
+        ));
+
+        let sent = decode_frames(&client.into_inner().output);
+        assert!(matches!(
+            sent.as_slice(),
+            [
+                ClientEnvelope {
+                    message: ClientMessage::Negotiate { .. },
+                    ..
+                },
+                ClientEnvelope {
+                    message: ClientMessage::Error {
+                        code: ErrorCode::UnknownMessage,
+                        ..
+                    },
+                    ..
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_rejected() {
+        let error = ServerMessage::Error {
+            code: ErrorCode::Unauthorized,
+            detail: "Device not enrolled".into(),
+            in_reply_to: MessageId::default().next(),
+        };
+        let input = frames(&[envelope(1, error)]);
+        let stream = Loopback {
+            input: &input,
+            output: Vec::new(),
+        };
+        let (mut rx, mut tx) = ([0u8; 256], [0u8; 256]);
+        let mut client = Client::new(stream, &mut rx, &mut tx);
+
+        assert!(matches!(
+            block_on(client.heartbeat(&mut Stopper)),
+            Err(Error::Rejected {
+                code: ErrorCode::Unauthorized
+            })
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use rustls_pki_types::CertificateDer;
+
+use wasmbed_types::PublicKey;
+
+use crate::{AuthorizationResult, MessageContext};
+
+pub type OnClientConnect = dyn Send
+    + Sync
+    + Fn(
+        PublicKey<'static>,
+    ) -> Pin<Box<dyn Future<Output = AuthorizationResult> + Send>>;
+pub type OnClientDisconnect = dyn Send
+    + Sync
+    + Fn(PublicKey<'static>) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+pub type OnClientMessage = dyn Send
+    + Sync
+    + Fn(MessageContext) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Application logic of a [`Server`](crate::Server), invoked for every
+/// client connection.
+pub trait ServerHandler: Send + Sync + 'static {
+    /// Called once the TLS handshake of a connection has completed. The
+    /// connection is closed unless the client is authorized, possibly only
+    /// to enroll.
+    fn on_connect(
+        &self,
+        connection: &ConnectionContext,
+    ) -> impl Future<Output = AuthorizationResult> + Send;
+
+    /// Called for every message received from an authorized client, except
+    /// version negotiations, disconnections, unknown messages, answered with
+    /// an error, and replies consumed by an ongoing transfer or request.
+    fn on_message(
+        &self,
+        ctx: MessageContext,
+    ) -> impl Future<Output = ()> + Send;
+
+    /// Called once an authorized client has disconnected, including clients
+    /// only allowed to enroll, see
+    /// [`AuthorizationResult::EnrollOnly`](crate::AuthorizationResult). Not
+    /// called for a connection replaced by a newer connection of the same
+    /// client, see [`DuplicatePolicy`](crate::DuplicatePolicy).
+    fn on_disconnect(
+        &self,
+        connection: &ConnectionContext,
+    ) -> impl Future<Output = ()> + Send {
+        let _ = connection;
+        async {}
+    }
+}
+
+/// Identifier of a client connection, unique for the lifetime of a
+/// [`Server`](crate::Server).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionId(pub(crate) u64);
+
+impl ConnectionId {
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Information about a client connection.
+#[derive(Debug, Clone)]
+pub struct ConnectionContext {
+    inner: Arc<ConnectionInfo>,
+}
+
+#[derive(Debug)]
+struct ConnectionInfo {
+    id: ConnectionId,
+    peer_addr: SocketAddr,
+    certificates: Vec<CertificateDer<'static>>,
+    client_key: PublicKey<'static>,
+}
+
+impl ConnectionContext {
+    pub(crate) fn new(
+        id: ConnectionId,
+        peer_addr: SocketAddr,
+        certificates: Vec<CertificateDer<'static>>,
+        client_key: PublicKey<'static>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(ConnectionInfo {
+                id,
+                peer_addr,
+                certificates,
+                client_key,
+            }),
+        }
+    }
+
+    pub fn id(&self) -> ConnectionId {
+        self.inner.id
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.inner.peer_addr
+    }
+
+    /// Certificate chain presented by the client, starting with its own
+    /// certificate.
+    pub fn certificates(&self) -> &[CertificateDer<'static>] {
+        &self.inner.certificates
+    }
+
+    /// Public key of the client certificate.
+    pub fn client_key(&self) -> &PublicKey<'static> {
+        &self.inner.client_key
+    }
+}
+
+/// Adapter implementing [`ServerHandler`] with closures.
+#[derive(Clone)]
+pub struct Callbacks {
+    pub on_client_connect: Arc<OnClientConnect>,
+    pub on_client_disconnect: Arc<OnClientDisconnect>,
+    pub on_client_message: Arc<OnClientMessage>,
+}
+
+impl ServerHandler for Callbacks {
+    fn on_connect(
+        &self,
+        connection: &ConnectionContext,
+    ) -> impl Future<Output = AuthorizationResult> + Send {
+        (self.on_client_connect)(connection.client_key().clone())
+    }
+
+    fn on_message(
+        &self,
+        ctx: MessageContext,
+    ) -> impl Future<Output = ()> + Send {
+        (self.on_client_message)(ctx)
+    }
+
+    fn on_disconnect(
+        &self,
+        connection: &ConnectionContext,
+    ) -> impl Future<Output = ()> + Send {
+        (self.on_client_disconnect)(connection.client_key().clone())
+    }
+}
@@ -1,20 +1,27 @@
 // SPDX-License-Identifier: AGPL-3.0
 // Copyright © 2025 Wasmbed contributors
 
-use std::collections::HashMap;
-use std::future::Future;
+mod handler;
+mod outbound;
+mod request;
+mod transfer;
+
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
-use std::pin::Pin;
-use std::sync::Arc;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 use rustls::{Error as RustlsError, RootCertStore, ServerConfig as RustlsConfig};
 use rustls::server::WebPkiClientVerifier;
 use rustls_pki_types::CertificateDer;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::RwLock;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use tokio::sync::{RwLock, oneshot};
+use tokio::sync::mpsc::{Receiver, UnboundedSender, unbounded_channel};
 use tokio::sync::mpsc::error::SendError;
+use tokio::task::JoinSet;
 use tokio_rustls::TlsAcceptor;
 use tokio_rustls::server::TlsStream;
 use tokio_util::sync::CancellationToken;
@@ -22,83 +29,183 @@ use tracing::{error, info, warn, debug};
 
 use wasmbed_cert::ServerIdentity;
 use wasmbed_protocol::{
-    ClientEnvelope, ClientMessage, MessageId, ServerEnvelope, ServerMessage,
-    Version,
+    ApplicationId, Capabilities, ClientEnvelope, ClientMessage, ErrorCode,
+    MAX_FRAME_SIZE, MIN_MESSAGE_SIZE, MessageId, NoCommonVersion,
+    ServerEnvelope, ServerMessage, Version, frame,
 };
 use wasmbed_types::PublicKey;
 
-/// Maximum message size to prevent DoS attacks (16MB)
-const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
-
-type Clients = Arc<RwLock<HashMap<PublicKey<'static>, Sender>>>;
-type LastMessageId = Arc<RwLock<MessageId>>;
-pub type OnClientConnect = dyn Send
-    + Sync
-    + Fn(
-        PublicKey<'static>,
-    ) -> Pin<Box<dyn Future<Output = AuthorizationResult> + Send>>;
-pub type OnClientDisconnect = dyn Send
-    + Sync
-    + Fn(PublicKey<'static>) -> Pin<Box<dyn Future<Output = ()> + Send>>;
-pub type OnClientMessage = dyn Send
-    + Sync
-    + Fn(MessageContext) -> Pin<Box<dyn Future<Output = ()> + Send>>;
-type Sender = UnboundedSender<ServerEnvelope>;
+pub use handler::{
+    Callbacks, ConnectionContext, ConnectionId, OnClientConnect,
+    OnClientDisconnect, OnClientMessage, ServerHandler,
+};
+pub use request::RequestError;
+pub use transfer::{Module, TransferConfig, TransferError};
+
+use outbound::Outbound;
+use request::Requests;
+
+/// How long the messages queued for a client may take to be written once
+/// its connection is closing.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long clients are asked to wait before reconnecting when the server
+/// shuts down, leaving the other servers time to take over.
+const SHUTDOWN_RECONNECT_AFTER: Duration = Duration::from_secs(1);
+
+/// How long the connections closed at the end of a shutdown may take to
+/// run their disconnection handlers before being aborted.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Clients = Arc<RwLock<HashMap<PublicKey<'static>, Arc<Outbound>>>>;
+type Transfers = Arc<
+    RwLock<
+        HashMap<
+            (PublicKey<'static>, ApplicationId),
+            UnboundedSender<ClientMessage>,
+        >,
+    >,
+>;
+type LastMessageId = Arc<Mutex<MessageId>>;
+/// Authorized connections whose disconnection has not been handled yet,
+/// whether they are registered as clients or only allowed to enroll.
+type Connections = Arc<Mutex<HashMap<ConnectionId, Arc<Outbound>>>>;
+/// Keys of the clients connected under [`DuplicatePolicy::RejectNew`],
+/// reserved before they are authorized.
+type Reserved = Arc<Mutex<HashSet<PublicKey<'static>>>>;
+
+/// Where messages exchanged with clients are routed, shared by the server
+/// and the connection handlers.
+#[derive(Clone, Default)]
+struct Routes {
+    clients: Clients,
+    transfers: Transfers,
+    requests: Requests,
+    connections: Connections,
+    reserved: Reserved,
+}
 
 pub struct ServerConfig {
     pub bind_addr: SocketAddr,
     pub identity: ServerIdentity,
     pub client_ca: CertificateDer<'static>,
-    pub on_client_connect: Arc<OnClientConnect>,
-    pub on_client_disconnect: Arc<OnClientDisconnect>,
-    pub on_client_message: Arc<OnClientMessage>,
+    /// Connections on which no message is received for this long are closed
+    pub idle_timeout: Duration,
+    /// Maximum number of messages queued for a client before sending fails
+    /// with [`MessageDeliveryError::QueueFull`]
+    pub queue_depth: NonZeroUsize,
+    /// Connections whose queue stays full for this long are closed. `None`
+    /// keeps them open.
+    pub saturation_timeout: Option<Duration>,
+    /// What to do when a client connects while already connected
+    pub duplicate_policy: DuplicatePolicy,
+    /// Once shutdown is requested, how long clients are given to finish
+    /// what they are doing and disconnect before their connections are
+    /// closed
+    pub drain_timeout: Duration,
     pub shutdown: CancellationToken,
 }
 
+/// Per-connection settings of a [`ServerConfig`].
+#[derive(Clone, Copy)]
+struct ConnectionSettings {
+    idle_timeout: Duration,
+    queue_depth: NonZeroUsize,
+    saturation_timeout: Option<Duration>,
+    duplicate_policy: DuplicatePolicy,
+}
+
+/// How a connection is handled when another registered connection of the
+/// same client is already open. Either way, only one connection per client
+/// is ever registered, and [`ServerHandler::on_disconnect`] is only called
+/// for the registered one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Close the existing connection in favor of the new one, e.g. because
+    /// the device rebooted before the server noticed the old connection
+    /// was dead.
+    #[default]
+    ReplaceExisting,
+    /// Refuse the new connection, before it is authorized.
+    RejectNew,
+}
+
 pub enum AuthorizationResult {
     Authorized,
+    /// The client may only enroll: the connection is not registered, so
+    /// that no message can be sent to the client and it is not listed by
+    /// [`Server::clients`], and its messages other than `Enroll` are
+    /// answered with an error.
+    EnrollOnly,
     Unauthorized,
 }
 
+#[derive(Debug)]
 pub enum MessageDeliveryError {
     ClientNotFound(PublicKey<'static>),
     SendError(SendError<ServerEnvelope>),
+    /// The queue of messages waiting to be written to the client is full
+    QueueFull(PublicKey<'static>),
+    /// The encoded message exceeds the size the client can receive
+    MessageTooLarge {
+        size: usize,
+        limit: usize,
+    },
 }
 
 pub struct MessageContext {
     envelope: ClientEnvelope,
-    sender: Sender,
+    connection: ConnectionContext,
+    outbound: Arc<Outbound>,
 }
 
 impl MessageContext {
-    pub fn message(&self) -> ClientMessage {
-        self.envelope.message.clone()
+    pub fn message(&self) -> &ClientMessage {
+        &self.envelope.message
+    }
+
+    pub fn message_id(&self) -> MessageId {
+        self.envelope.message_id
+    }
+
+    pub fn client_key(&self) -> &PublicKey<'static> {
+        self.connection.client_key()
+    }
+
+    pub fn connection(&self) -> &ConnectionContext {
+        &self.connection
+    }
+
+    /// Protocol version negotiated with the client.
+    pub fn version(&self) -> Version {
+        self.outbound.version()
     }
 
     pub fn reply(
         &self,
         message: ServerMessage,
-    ) -> Result<(), SendError<ServerEnvelope>> {
-        self.sender.send(ServerEnvelope {
-            version: Version::V0,
-            message_id: self.envelope.message_id,
-            message,
-        })
+    ) -> Result<(), MessageDeliveryError> {
+        self.outbound
+            .send(self.outbound.envelope(self.envelope.message_id, message))
     }
 }
 
-pub struct Server {
+pub struct Server<H: ServerHandler = Callbacks> {
     config: ServerConfig,
-    clients: Clients,
+    handler: Arc<H>,
+    routes: Routes,
     last_message_id: LastMessageId,
+    last_connection_id: Arc<AtomicU64>,
 }
 
-impl Server {
-    pub fn new(config: ServerConfig) -> Self {
+impl<H: ServerHandler> Server<H> {
+    pub fn new(config: ServerConfig, handler: H) -> Self {
         Self {
             config,
-            clients: Default::default(),
+            handler: Arc::new(handler),
+            routes: Default::default(),
             last_message_id: Default::default(),
+            last_connection_id: Default::default(),
         }
     }
 
@@ -111,25 +218,36 @@ impl Server {
 
         info!("Server listening on {}", self.config.bind_addr);
 
+        let mut connections = JoinSet::new();
         loop {
             tokio::select! {
                 result = listener.accept() => {
                     match result {
-                        Ok((stream, addr)) => {
-                            debug!("Accepted connection from {}", addr);
+                        Ok(accepted) => {
+                            debug!("Accepted connection from {}", accepted.1);
+                            let id = ConnectionId(
+                                self.last_connection_id
+                                    .fetch_add(1, Ordering::Relaxed),
+                            );
                             let acceptor = Arc::clone(&acceptor);
-                            let clients = Arc::clone(&self.clients);
-                            let on_client_connect = Arc::clone(&self.config.on_client_connect);
-                            let on_client_disconnect = Arc::clone(&self.config.on_client_disconnect);
-                            let on_client_message = Arc::clone(&self.config.on_client_message);
-                            tokio::spawn(async move {
+                            let routes = self.routes.clone();
+                            let handler = Arc::clone(&self.handler);
+                            let settings = ConnectionSettings {
+                                idle_timeout: self.config.idle_timeout,
+                                queue_depth: self.config.queue_depth,
+                                saturation_timeout:
+                                    self.config.saturation_timeout,
+                                duplicate_policy:
+                                    self.config.duplicate_policy,
+                            };
+                            connections.spawn(async move {
                                 if let Err(e) = handle_client(
-                                    stream,
+                                    accepted,
+                                    id,
                                     acceptor,
-                                    clients,
-                                    &*on_client_connect,
-                                    &*on_client_disconnect,
-                                    &*on_client_message,
+                                    routes,
+                                    handler,
+                                    settings,
                                 ).await {
                                     error!("Client handler error: {}", e);
                                 }
@@ -140,6 +258,13 @@ impl Server {
                         }
                     }
                 }
+                Some(result) = connections.join_next(),
+                    if !connections.is_empty() =>
+                {
+                    if let Err(e) = result {
+                        error!("Client handler failed: {e}");
+                    }
+                }
                 _ = self.config.shutdown.cancelled() => {
                     info!("Server shutdown requested");
                     break;
@@ -147,27 +272,162 @@ impl Server {
             }
         }
 
+        drop(listener);
+        self.drain(connections).await;
+        info!("Server stopped");
         Ok(())
     }
 
+    /// Ends the `connections` of a server no longer accepting new ones.
+    ///
+    /// Clients are asked to go away and given the drain timeout to
+    /// disconnect, after which their connections are closed. Either way,
+    /// [`ServerHandler::on_disconnect`] is called for every client: for the
+    /// connections that do not close in time, once they are aborted.
+    async fn drain(&self, mut connections: JoinSet<()>) {
+        self.go_away("Server shutting down", SHUTDOWN_RECONNECT_AFTER)
+            .await;
+
+        let drain_timeout = self.config.drain_timeout;
+        if tokio::time::timeout(drain_timeout, join_all(&mut connections))
+            .await
+            .is_ok()
+        {
+            return;
+        }
+
+        {
+            let open = lock(&self.routes.connections);
+            warn!(
+                "{} clients still connected after {drain_timeout:?}, closing \
+                 their connections",
+                open.len()
+            );
+            for outbound in open.values() {
+                outbound.close();
+            }
+        }
+        if tokio::time::timeout(CLOSE_TIMEOUT, join_all(&mut connections))
+            .await
+            .is_ok()
+        {
+            return;
+        }
+
+        warn!("Aborting {} connections", connections.len());
+        connections.abort_all();
+        join_all(&mut connections).await;
+
+        // The aborted connections did not run their disconnection handler,
+        // or not until the end.
+        let aborted: Vec<_> = lock(&self.routes.connections)
+            .drain()
+            .map(|(_, outbound)| outbound)
+            .collect();
+        for outbound in aborted {
+            let connection = outbound.connection();
+            info!("Client disconnected: {}", connection.client_key());
+            self.handler.on_disconnect(connection).await;
+        }
+    }
+
     pub async fn send(
         &self,
         client_key: &PublicKey<'static>,
         message: ServerMessage,
     ) -> Result<MessageId, MessageDeliveryError> {
-        let message_id = self.next_message_id().await;
-        let envelope = ServerEnvelope {
-            version: Version::V0,
-            message_id,
-            message,
-        };
+        let message_id = self.next_message_id();
+        self.send_with_id(client_key, message_id, message).await?;
+        Ok(message_id)
+    }
 
-        match self.clients.read().await.get(client_key) {
-            Some(client) => {
-                client
-                    .send(envelope)
-                    .map_err(MessageDeliveryError::SendError)?;
-                Ok(message_id)
+    /// Sends `message` to a client and waits for its reply, i.e. the first
+    /// message the client sends with the same identifier.
+    ///
+    /// The reply is not passed to [`ServerHandler::on_message`]. If no reply
+    /// arrives within `timeout` or the client disconnects, the request fails
+    /// and a late reply is handled like any other message.
+    pub async fn request(
+        &self,
+        client_key: &PublicKey<'static>,
+        message: ServerMessage,
+        timeout: Duration,
+    ) -> Result<ClientMessage, RequestError> {
+        let message_id = self.next_message_id();
+        let request_key = (client_key.clone(), message_id);
+        let (tx, rx) = oneshot::channel();
+        self.routes
+            .requests
+            .write()
+            .await
+            .insert(request_key.clone(), tx);
+
+        if let Err(e) = self.send_with_id(client_key, message_id, message).await
+        {
+            self.routes.requests.write().await.remove(&request_key);
+            return Err(match e {
+                MessageDeliveryError::QueueFull(_) => RequestError::QueueFull,
+                MessageDeliveryError::MessageTooLarge { size, limit } => {
+                    RequestError::MessageTooLarge { size, limit }
+                },
+                MessageDeliveryError::ClientNotFound(_)
+                | MessageDeliveryError::SendError(_) => {
+                    RequestError::ClientNotFound
+                },
+            });
+        }
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(RequestError::Disconnected),
+            Err(_) => {
+                self.routes.requests.write().await.remove(&request_key);
+                Err(RequestError::Timeout)
+            },
+        }
+    }
+
+    /// Asks every connected client to disconnect and connect again after
+    /// `reconnect_after`, with a `GoAway` message.
+    pub async fn go_away(&self, reason: &str, reconnect_after: Duration) {
+        let reconnect_after =
+            u32::try_from(reconnect_after.as_secs()).unwrap_or(u32::MAX);
+        for outbound in self.routes.clients.read().await.values() {
+            let message = ServerMessage::GoAway {
+                reason: reason.to_string(),
+                reconnect_after,
+            };
+            let envelope = outbound.envelope(self.next_message_id(), message);
+            if let Err(e) = outbound.send(envelope) {
+                warn!(
+                    "Unable to send GoAway to {}: {e:?}",
+                    outbound.connection().client_key()
+                );
+            }
+        }
+    }
+
+    /// Returns the connections of the authorized clients currently
+    /// connected.
+    pub async fn clients(&self) -> Vec<ConnectionContext> {
+        self.routes
+            .clients
+            .read()
+            .await
+            .values()
+            .map(|outbound| outbound.connection().clone())
+            .collect()
+    }
+
+    async fn send_with_id(
+        &self,
+        client_key: &PublicKey<'static>,
+        message_id: MessageId,
+        message: ServerMessage,
+    ) -> Result<(), MessageDeliveryError> {
+        match self.routes.clients.read().await.get(client_key) {
+            Some(outbound) => {
+                outbound.send(outbound.envelope(message_id, message))
             },
             None => {
                 Err(MessageDeliveryError::ClientNotFound(client_key.clone()))
@@ -175,14 +435,93 @@ impl Server {
         }
     }
 
-    async fn next_message_id(&self) -> MessageId {
-        let mut last = self.last_message_id.write().await;
+    /// Transfers `module` to a client in chunks and waits until the client
+    /// has deployed it.
+    ///
+    /// While the transfer is in progress, the client replies concerning
+    /// `module` are consumed by the transfer and not passed to
+    /// [`ServerHandler::on_message`]. If the client disconnects, the transfer
+    /// fails with [`TransferError::Disconnected`]; calling this method again
+    /// once the client has reconnected resumes from the last offset the
+    /// client acknowledged.
+    pub async fn transfer(
+        &self,
+        client_key: &PublicKey<'static>,
+        module: &Module,
+        config: &TransferConfig,
+    ) -> Result<(), TransferError> {
+        let transfer_key = (client_key.clone(), module.app_id.clone());
+        let (tx, mut rx) = unbounded_channel::<ClientMessage>();
+
+        let Some(outbound) =
+            self.routes.clients.read().await.get(client_key).cloned()
+        else {
+            return Err(TransferError::ClientNotFound);
+        };
+
+        {
+            let mut guard = self.routes.transfers.write().await;
+            if guard.contains_key(&transfer_key) {
+                return Err(TransferError::AlreadyInProgress {
+                    app_id: module.app_id.clone(),
+                });
+            }
+            guard.insert(transfer_key.clone(), tx);
+        }
+
+        let result = transfer::drive(
+            module,
+            config,
+            |message| {
+                let envelope =
+                    outbound.envelope(self.next_message_id(), message);
+                match outbound.send(envelope) {
+                    // Messages that do not fit in the queue are lost like on
+                    // an unreliable link, and sent again after a timeout.
+                    Ok(()) | Err(MessageDeliveryError::QueueFull(_)) => Ok(()),
+                    Err(MessageDeliveryError::MessageTooLarge {
+                        size,
+                        limit,
+                    }) => Err(TransferError::MessageTooLarge { size, limit }),
+                    Err(_) => Err(TransferError::ClientNotFound),
+                }
+            },
+            &mut rx,
+        )
+        .await;
+
+        self.routes.transfers.write().await.remove(&transfer_key);
+        result
+    }
+
+    fn next_message_id(&self) -> MessageId {
+        let mut last = self
+            .last_message_id
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
         *last = last.next();
         *last
     }
 }
 
-fn build_tls_acceptor(
+async fn join_all(connections: &mut JoinSet<()>) {
+    while let Some(result) = connections.join_next().await {
+        match result {
+            Err(e) if !e.is_cancelled() => {
+                error!("Client handler failed: {e}");
+            },
+            _ => {},
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Builds a TLS acceptor presenting `identity` and requiring client
+/// certificates issued by `client_ca`.
+pub fn build_tls_acceptor(
     identity: &ServerIdentity,
     client_ca: &CertificateDer<'static>,
 ) -> Result<TlsAcceptor, RustlsError> {
@@ -203,88 +542,318 @@ fn build_tls_acceptor(
     Ok(TlsAcceptor::from(Arc::new(config)))
 }
 
-fn extract_client_public_key(
+fn extract_client_certificates(
     tls_stream: &TlsStream<TcpStream>,
-) -> Option<PublicKey<'static>> {
+) -> Option<(Vec<CertificateDer<'static>>, PublicKey<'static>)> {
     let (_, session) = tls_stream.get_ref();
-    let client_cert = session.peer_certificates()?.first()?;
-    Some(PublicKey::try_from(client_cert).ok()?.into_owned())
+    let certificates = session.peer_certificates()?;
+    let public_key = PublicKey::try_from(certificates.first()?).ok()?;
+    Some((
+        certificates
+            .iter()
+            .map(|c| c.clone().into_owned())
+            .collect(),
+        public_key.into_owned(),
+    ))
 }
 
-async fn handle_client(
-    stream: TcpStream,
+async fn handle_client<H: ServerHandler>(
+    (stream, peer_addr): (TcpStream, SocketAddr),
+    id: ConnectionId,
     acceptor: Arc<TlsAcceptor>,
-    clients: Clients,
-    on_client_connect: &OnClientConnect,
-    on_client_disconnect: &OnClientDisconnect,
-    on_client_message: &OnClientMessage,
+    routes: Routes,
+    handler: Arc<H>,
+    settings: ConnectionSettings,
 ) -> std::io::Result<()> {
     let tls_stream = acceptor.accept(stream).await?;
 
-    let public_key =
-        extract_client_public_key(&tls_stream).ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Failed to extract client public key",
-            )
-        })?;
+    let (certificates, public_key) = extract_client_certificates(&tls_stream)
+        .ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Failed to extract client public key",
+        )
+    })?;
+    let connection =
+        ConnectionContext::new(id, peer_addr, certificates, public_key);
+    let public_key = connection.client_key();
+
+    info!("Client connected: {} ({id}, {peer_addr})", public_key);
+
+    // Reserved before the handler authorizes the client, so that it is not
+    // told about a connection about to be rejected.
+    let reservation = match settings.duplicate_policy {
+        DuplicatePolicy::RejectNew => {
+            let Some(reservation) =
+                Reservation::new(&routes.reserved, public_key)
+            else {
+                warn!("Client already connected, rejecting {id}: {public_key}");
+                return Ok(());
+            };
+            Some(reservation)
+        },
+        DuplicatePolicy::ReplaceExisting => None,
+    };
+
+    let enroll_only = match handler.on_connect(&connection).await {
+        AuthorizationResult::Authorized => false,
+        AuthorizationResult::EnrollOnly => true,
+        AuthorizationResult::Unauthorized => {
+            warn!("Client authorization failed: {:?}", public_key);
+            return Ok(());
+        },
+    };
 
-    info!("Client connected: {}", public_key);
+    let (outbound, rx) = Outbound::new(
+        connection.clone(),
+        settings.queue_depth.get(),
+        settings.saturation_timeout,
+    );
+    let outbound = Arc::new(outbound);
 
-    if matches!(
-        on_client_connect(public_key.clone()).await,
-        AuthorizationResult::Unauthorized
-    ) {
-        warn!("Client authorization failed: {:?}", public_key);
-        return Ok(());
+    if enroll_only {
+        info!("Client authorized to enroll: {}", public_key);
+        // Connections only allowed to enroll are not registered.
+        drop(reservation);
+        lock(&routes.connections).insert(id, Arc::clone(&outbound));
+        let result = client_handler(
+            tls_stream,
+            &connection,
+            &routes,
+            (outbound, rx),
+            &*handler,
+            settings.idle_timeout,
+            enroll_only,
+        )
+        .await;
+        info!("Client disconnected: {}", public_key);
+        handler.on_disconnect(&connection).await;
+        lock(&routes.connections).remove(&id);
+        return result;
     }
 
     info!("Client authorized: {}", public_key);
 
-    let (tx, rx) = unbounded_channel::<ServerEnvelope>();
-    register_client(&clients, &public_key, tx.clone()).await;
+    match register_client(&routes.clients, public_key, Arc::clone(&outbound))
+        .await
+    {
+        Registration::Registered => {},
+        Registration::Replaced(previous) => {
+            info!(
+                "Client reconnected, closing {}: {public_key}",
+                previous.connection_id()
+            );
+            previous.close();
+            lock(&routes.connections).remove(&previous.connection_id());
+            abort_transfers(&routes.transfers, public_key).await;
+            request::abort_requests(&routes.requests, public_key).await;
+        },
+    }
+    lock(&routes.connections).insert(id, Arc::clone(&outbound));
 
     let result = client_handler(
         tls_stream,
-        &public_key,
-        &clients,
-        rx,
-        on_client_message,
+        &connection,
+        &routes,
+        (outbound, rx),
+        &*handler,
+        settings.idle_timeout,
+        enroll_only,
     )
     .await;
 
-    unregister_client(&clients, &public_key).await;
-    info!("Client disconnected: {}", public_key);
-    on_client_disconnect(public_key.clone()).await;
+    // A connection replaced by a newer one no longer owns the client key:
+    // the state of the client belongs to the newer connection.
+    if unregister_client(&routes.clients, public_key, id).await {
+        abort_transfers(&routes.transfers, public_key).await;
+        request::abort_requests(&routes.requests, public_key).await;
+        info!("Client disconnected: {}", public_key);
+        handler.on_disconnect(&connection).await;
+    } else {
+        info!("Replaced connection {id} closed: {public_key}");
+    }
+    lock(&routes.connections).remove(&id);
+    drop(reservation);
 
     result
 }
 
-async fn register_client<'a>(
+/// Exclusive use of a client key by a connection, released when dropped.
+struct Reservation {
+    reserved: Reserved,
+    key: PublicKey<'static>,
+}
+
+impl Reservation {
+    /// Reserves `key`, unless another connection already did.
+    fn new(reserved: &Reserved, key: &PublicKey<'static>) -> Option<Self> {
+        lock(reserved).insert(key.clone()).then(|| Self {
+            reserved: Arc::clone(reserved),
+            key: key.clone(),
+        })
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        lock(&self.reserved).remove(&self.key);
+    }
+}
+
+enum Registration {
+    Registered,
+    /// The connection replaced this one
+    Replaced(Arc<Outbound>),
+}
+
+async fn register_client(
     clients: &Clients,
-    public_key: &PublicKey<'a>,
-    sender: UnboundedSender<ServerEnvelope>,
-) {
+    public_key: &PublicKey<'static>,
+    outbound: Arc<Outbound>,
+) -> Registration {
     let mut guard = clients.write().await;
-    guard.insert(public_key.clone().into_owned(), sender);
+    match guard.insert(public_key.clone(), outbound) {
+        Some(previous) => Registration::Replaced(previous),
+        None => Registration::Registered,
+    }
 }
 
-async fn unregister_client(clients: &Clients, client_key: &PublicKey<'static>) {
+/// Unregisters the connection `id` of a client, unless it has been replaced
+/// by another connection. Returns whether the connection was registered.
+async fn unregister_client(
+    clients: &Clients,
+    client_key: &PublicKey<'static>,
+    id: ConnectionId,
+) -> bool {
     let mut guard = clients.write().await;
-    guard.remove(client_key);
+    if guard
+        .get(client_key)
+        .is_some_and(|outbound| outbound.connection_id() == id)
+    {
+        guard.remove(client_key);
+        true
+    } else {
+        false
+    }
+}
+
+/// Drops the reply channels of the transfers to a client, making them fail
+/// with [`TransferError::Disconnected`].
+async fn abort_transfers(
+    transfers: &Transfers,
+    client_key: &PublicKey<'static>,
+) {
+    let mut guard = transfers.write().await;
+    guard.retain(|(key, _), _| key != client_key);
 }
 
-async fn client_handler<'a>(
+/// Negotiates the protocol version of a connection from the `versions`
+/// offered by the client, replying with the chosen version or with the
+/// versions the server supports.
+fn negotiate(
+    outbound: &Outbound,
+    message_id: MessageId,
+    versions: &[u32],
+) -> Result<Version, NoCommonVersion> {
+    match Version::negotiate(versions) {
+        Ok(version) => {
+            outbound.set_version(version);
+            let reply = ServerMessage::Negotiated { version };
+            let _ = outbound.send(outbound.envelope(message_id, reply));
+            Ok(version)
+        },
+        Err(e) => {
+            let reply = ServerMessage::VersionMismatch {
+                supported: Version::supported_numbers(),
+            };
+            let _ = outbound.send(outbound.envelope(message_id, reply));
+            Err(e)
+        },
+    }
+}
+
+/// Checks that a message of a client follows the protocol: its envelope has
+/// the `version` of the connection, and `Negotiate` is only sent `first`.
+/// Returns why it does not otherwise.
+fn check_envelope(
+    envelope: &ClientEnvelope,
+    version: Version,
+    first: bool,
+) -> Result<(), String> {
+    if envelope.version != version {
+        return Err(format!(
+            "Protocol version {} expected, got {}",
+            version.number(),
+            envelope.version.number()
+        ));
+    }
+    if !first && matches!(envelope.message, ClientMessage::Negotiate { .. }) {
+        return Err("Negotiate must be the first message".into());
+    }
+    Ok(())
+}
+
+/// Checks that the server can reply to a client advertising `capabilities`
+/// in its `Hello`. Returns why it cannot otherwise.
+fn check_capabilities(capabilities: &Capabilities) -> Result<(), String> {
+    let max_message_size =
+        usize::try_from(capabilities.max_message_size).unwrap_or(usize::MAX);
+    if max_message_size < MIN_MESSAGE_SIZE {
+        return Err(format!(
+            "Maximum message size of at least {MIN_MESSAGE_SIZE} bytes \
+             expected, got {max_message_size}"
+        ));
+    }
+    Ok(())
+}
+
+/// Hands the message in `envelope` over to the transfer waiting for it, if
+/// any. Returns the envelope back if no transfer is interested in it.
+async fn route_to_transfer(
+    transfers: &Transfers,
+    client_key: &PublicKey<'_>,
+    envelope: ClientEnvelope,
+) -> Option<ClientEnvelope> {
+    let Some(app_id) = transfer::transfer_reply_app_id(&envelope.message)
+    else {
+        return Some(envelope);
+    };
+
+    let transfer_key = (client_key.clone().into_owned(), app_id.clone());
+    match transfers.read().await.get(&transfer_key) {
+        Some(tx) if tx.send(envelope.message.clone()).is_ok() => None,
+        _ => Some(envelope),
+    }
+}
+
+async fn client_handler<H: ServerHandler>(
     tls_stream: TlsStream<TcpStream>,
-    client_key: &PublicKey<'a>,
-    clients: &Clients,
-    mut rx: UnboundedReceiver<ServerEnvelope>,
-    on_client_message: &OnClientMessage,
+    connection: &ConnectionContext,
+    routes: &Routes,
+    (outbound, mut rx): (Arc<Outbound>, Receiver<ServerEnvelope>),
+    handler: &H,
+    idle_timeout: Duration,
+    enroll_only: bool,
 ) -> std::io::Result<()> {
+    let client_key = connection.client_key();
     let (mut reader, mut writer) = tokio::io::split(tls_stream);
 
-    let writer_task = tokio::spawn(async move {
-        while let Some(envelope) = rx.recv().await {
+    // Whether no message has been received yet, `Negotiate` being only
+    // allowed first.
+    let mut first = true;
+
+    let closed = outbound.closed().clone();
+    let mut writer_task = tokio::spawn(async move {
+        loop {
+            let envelope = tokio::select! {
+                envelope = rx.recv() => envelope,
+                // Write what is already queued, e.g. the reason the
+                // connection is being closed, and stop.
+                _ = closed.cancelled() => rx.try_recv().ok(),
+            };
+            let Some(envelope) = envelope else {
+                break;
+            };
             if let Err(e) = write_envelope(&mut writer, &envelope).await {
                 error!("Failed to write envelope: {}", e);
                 break;
@@ -294,55 +863,185 @@ async fn client_handler<'a>(
 
     let result = loop {
         tokio::select! {
-            result = read_envelope(&mut reader) => {
+            result = tokio::time::timeout(
+                idle_timeout,
+                read_envelope(&mut reader),
+            ) => {
                 match result {
-                    Ok(envelope) => {
-                        let sender = {
-                            let guard = clients.read().await;
-                            guard.get(client_key).cloned()
-                        };
-
-                        if let Some(sender) = sender {
-                            let ctx = MessageContext {
-                                envelope,
-                                sender,
+                    Err(_) => {
+                        warn!(
+                            "Client idle for more than {idle_timeout:?}, \
+                             disconnecting: {client_key}"
+                        );
+                        break Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            "Client idle timeout",
+                        ));
+                    }
+                    Ok(Ok(envelope)) => {
+                        let first = std::mem::replace(&mut first, false);
+                        if let Err(detail) =
+                            check_envelope(&envelope, outbound.version(), first)
+                        {
+                            warn!("{detail}: {client_key}");
+                            let reply = ServerMessage::Error {
+                                code: ErrorCode::MalformedRequest,
+                                detail,
+                                in_reply_to: envelope.message_id,
                             };
+                            let _ = outbound.send(
+                                outbound.envelope(envelope.message_id, reply),
+                            );
+                            continue;
+                        }
+
+                        if let ClientMessage::Negotiate { versions } =
+                            &envelope.message
+                        {
+                            match negotiate(
+                                &outbound,
+                                envelope.message_id,
+                                versions,
+                            ) {
+                                Ok(version) => {
+                                    debug!(
+                                        "Negotiated {version:?}: {client_key}"
+                                    );
+                                    continue;
+                                }
+                                Err(e) => {
+                                    warn!("{e}, disconnecting: {client_key}");
+                                    break Err(std::io::Error::new(
+                                        std::io::ErrorKind::InvalidData,
+                                        e,
+                                    ));
+                                }
+                            }
+                        }
+
+                        if let ClientMessage::Hello { capabilities } =
+                            &envelope.message
+                        {
+                            // Nothing could be sent to the client anymore.
+                            if let Err(detail) =
+                                check_capabilities(capabilities)
+                            {
+                                warn!("{detail}, disconnecting: {client_key}");
+                                let reply = ServerMessage::Error {
+                                    code: ErrorCode::MalformedRequest,
+                                    detail: detail.clone(),
+                                    in_reply_to: envelope.message_id,
+                                };
+                                let _ = outbound.send(outbound.envelope(
+                                    envelope.message_id,
+                                    reply,
+                                ));
+                                break Err(std::io::Error::new(
+                                    std::io::ErrorKind::InvalidData,
+                                    detail,
+                                ));
+                            }
+                            outbound.set_max_message_size(
+                                capabilities.max_message_size,
+                            );
+                        }
 
-                            on_client_message(ctx).await;
-                        } else {
-                            error!("Client sender not found: {client_key:?}");
-                            break Err(std::io::Error::other(
-                                format!("Client not found: {client_key:?}")
+                        if let ClientMessage::Disconnect { reason } =
+                            &envelope.message
+                        {
+                            info!("Client disconnecting ({reason}): {client_key}");
+                            let _ = outbound.send(outbound.envelope(
+                                envelope.message_id,
+                                ServerMessage::DisconnectAck,
                             ));
+                            break Ok(());
                         }
+
+                        if let ClientMessage::Unknown { tag, .. } =
+                            &envelope.message
+                        {
+                            warn!("Unknown message {tag}: {client_key}");
+                            let reply = ServerMessage::Error {
+                                code: ErrorCode::UnknownMessage,
+                                detail: format!("Unknown message tag {tag}"),
+                                in_reply_to: envelope.message_id,
+                            };
+                            let _ = outbound.send(
+                                outbound.envelope(envelope.message_id, reply),
+                            );
+                            continue;
+                        }
+
+                        if enroll_only
+                            && envelope.message != ClientMessage::Enroll
+                        {
+                            warn!("Message before enrollment: {client_key}");
+                            let reply = ServerMessage::Error {
+                                code: ErrorCode::Unauthorized,
+                                detail: "Device not enrolled".into(),
+                                in_reply_to: envelope.message_id,
+                            };
+                            let _ = outbound.send(
+                                outbound.envelope(envelope.message_id, reply),
+                            );
+                            continue;
+                        }
+
+                        let Some(envelope) = route_to_transfer(
+                            &routes.transfers,
+                            client_key,
+                            envelope,
+                        ).await else {
+                            continue;
+                        };
+                        let Some(envelope) = request::route_to_request(
+                            &routes.requests,
+                            client_key,
+                            envelope,
+                        ).await else {
+                            continue;
+                        };
+
+                        let ctx = MessageContext {
+                            envelope,
+                            connection: connection.clone(),
+                            outbound: Arc::clone(&outbound),
+                        };
+
+                        handler.on_message(ctx).await;
                     }
-                    Err(e) => {
+                    Ok(Err(e)) => {
                         error!("Failed to read envelope: {e}");
                         break Err(e);
                     }
                 }
             }
+            _ = outbound.closed().cancelled() => {
+                break Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "Connection closed by the server",
+                ));
+            }
         }
     };
 
-    writer_task.abort();
+    outbound.close();
+    if tokio::time::timeout(DRAIN_TIMEOUT, &mut writer_task)
+        .await
+        .is_err()
+    {
+        writer_task.abort();
+    }
     result
 }
 
 async fn read_envelope(
     reader: &mut (impl AsyncReadExt + Unpin),
 ) -> std::io::Result<ClientEnvelope> {
-    // Read length prefix (4 bytes, big endian)
-    let mut len_buf = [0u8; 4];
-    reader.read_exact(&mut len_buf).await?;
-    let len = u32::from_be_bytes(len_buf) as usize;
-
-    if len > MAX_MESSAGE_SIZE {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!("Message too large: {len} bytes (max: {MAX_MESSAGE_SIZE})"),
-        ));
-    }
+    let mut header = [0u8; frame::LENGTH_SIZE];
+    reader.read_exact(&mut header).await?;
+    let len = frame::decode_header(header, MAX_FRAME_SIZE)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
 
     let mut data = vec![0u8; len];
     reader.read_exact(&mut data).await?;
@@ -359,25 +1058,117 @@ async fn write_envelope(
     writer: &mut (impl AsyncWriteExt + Unpin),
     envelope: &ServerEnvelope,
 ) -> std::io::Result<()> {
-    let data = minicbor::to_vec(envelope).map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!("CBOR encode error: {e}"),
-        )
-    })?;
-
-    // Write length prefix
-    let len: u32 = data.len().try_into().map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!("Invalid message length: {e}"),
-        )
-    })?;
-    writer.write_all(&len.to_be_bytes()).await?;
-
-    // Write message data
+    let data = frame::to_vec(envelope, MAX_FRAME_SIZE)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
     writer.write_all(&data).await?;
     writer.flush().await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outbound(id: u64, key: &PublicKey<'static>) -> Arc<Outbound> {
+        let connection = outbound::tests::connection(id, key);
+        Arc::new(Outbound::new(connection, 1, None).0)
+    }
+
+    #[tokio::test]
+    async fn replaced_connection_does_not_unregister_client() {
+        let clients = Clients::default();
+        let key = PublicKey::from(vec![1; 32]);
+
+        let first = outbound(0, &key);
+        assert!(matches!(
+            register_client(&clients, &key, first).await,
+            Registration::Registered
+        ));
+        assert!(matches!(
+            register_client(&clients, &key, outbound(1, &key)).await,
+            Registration::Replaced(previous)
+                if previous.connection_id() == ConnectionId(0)
+        ));
+
+        assert!(!unregister_client(&clients, &key, ConnectionId(0)).await);
+        assert!(clients.read().await.contains_key(&key));
+        assert!(unregister_client(&clients, &key, ConnectionId(1)).await);
+        assert!(clients.read().await.is_empty());
+    }
+
+    #[test]
+    fn duplicate_connection_is_rejected() {
+        let reserved = Reserved::default();
+        let key = PublicKey::from(vec![1; 32]);
+
+        let reservation = Reservation::new(&reserved, &key);
+        assert!(reservation.is_some());
+        assert!(Reservation::new(&reserved, &key).is_none());
+
+        // Released once the first connection ends, or is not authorized.
+        drop(reservation);
+        assert!(Reservation::new(&reserved, &key).is_some());
+    }
+
+    #[test]
+    fn negotiate_only_first() {
+        let envelope = ClientEnvelope {
+            version: Version::V0,
+            message_id: MessageId::default(),
+            message: ClientMessage::Negotiate { versions: vec![0] },
+        };
+        assert_eq!(check_envelope(&envelope, Version::V0, true), Ok(()));
+        assert!(check_envelope(&envelope, Version::V0, false).is_err());
+
+        let heartbeat = ClientEnvelope {
+            message: ClientMessage::Heartbeat,
+            ..envelope
+        };
+        assert_eq!(check_envelope(&heartbeat, Version::V0, false), Ok(()));
+    }
+
+    #[test]
+    fn small_max_message_size_is_rejected() {
+        let hello_ack = ServerEnvelope {
+            version: Version::V0,
+            message_id: MessageId::default(),
+            message: ServerMessage::HelloAck,
+        };
+        assert!(frame::size(&hello_ack) <= MIN_MESSAGE_SIZE);
+
+        let mut capabilities = Capabilities {
+            max_message_size: 0,
+            ..Capabilities::default()
+        };
+        assert!(check_capabilities(&capabilities).is_err());
+        capabilities.max_message_size =
+            u32::try_from(MIN_MESSAGE_SIZE).unwrap();
+        assert_eq!(check_capabilities(&capabilities), Ok(()));
+    }
+
+    #[test]
+    fn negotiation_sets_connection_version() {
+        let key = PublicKey::from(vec![1; 32]);
+        let connection = outbound::tests::connection(0, &key);
+        let (outbound, mut receiver) = Outbound::new(connection, 2, None);
+        let id = MessageId::default();
+
+        assert_eq!(negotiate(&outbound, id, &[0, 99]), Ok(Version::V0));
+        assert_eq!(outbound.version(), Version::V0);
+        assert_eq!(
+            receiver.try_recv().unwrap().message,
+            ServerMessage::Negotiated {
+                version: Version::V0
+            }
+        );
+
+        assert!(negotiate(&outbound, id, &[99]).is_err());
+        assert_eq!(
+            receiver.try_recv().unwrap().message,
+            ServerMessage::VersionMismatch {
+                supported: Version::supported_numbers()
+            }
+        );
+    }
+}
@@ -0,0 +1,299 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::mpsc::{Receiver, Sender, WeakSender, channel};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use wasmbed_protocol::{
+    MAX_FRAME_SIZE, MessageId, ServerEnvelope, ServerMessage, Version, frame,
+};
+
+use crate::{ConnectionContext, ConnectionId, MessageDeliveryError};
+
+/// Bounded queue of the messages waiting to be written to a client.
+///
+/// Sending never waits: when the queue is full the message is rejected with
+/// [`MessageDeliveryError::QueueFull`]. If the queue stays full for longer
+/// than the saturation timeout, the connection is closed, whether or not
+/// more messages are sent in the meantime. Messages larger
+/// than what the client can receive are rejected with
+/// [`MessageDeliveryError::MessageTooLarge`].
+pub(crate) struct Outbound {
+    connection: ConnectionContext,
+    /// Protocol version negotiated with the client
+    version: Mutex<Version>,
+    /// Size of the largest envelope the client can receive
+    max_message_size: Mutex<usize>,
+    sender: Sender<ServerEnvelope>,
+    saturation_timeout: Option<Duration>,
+    saturated_since: Arc<Mutex<Option<Instant>>>,
+    closed: CancellationToken,
+}
+
+impl Outbound {
+    pub(crate) fn new(
+        connection: ConnectionContext,
+        depth: usize,
+        saturation_timeout: Option<Duration>,
+    ) -> (Self, Receiver<ServerEnvelope>) {
+        let (sender, receiver) = channel(depth);
+        let outbound = Self {
+            connection,
+            version: Mutex::new(Version::V0),
+            max_message_size: Mutex::new(MAX_FRAME_SIZE),
+            sender,
+            saturation_timeout,
+            saturated_since: Arc::default(),
+            closed: CancellationToken::new(),
+        };
+        (outbound, receiver)
+    }
+
+    pub(crate) fn version(&self) -> Version {
+        *self.version.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn set_version(&self, version: Version) {
+        *self.version.lock().unwrap_or_else(PoisonError::into_inner) = version;
+    }
+
+    fn max_message_size(&self) -> usize {
+        *self
+            .max_message_size
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Limits the messages sent from then on to the `max_message_size` the
+    /// client advertised, within [`MAX_FRAME_SIZE`].
+    pub(crate) fn set_max_message_size(&self, max_message_size: u32) {
+        let max_message_size = usize::try_from(max_message_size)
+            .map_or(MAX_FRAME_SIZE, |size| size.min(MAX_FRAME_SIZE));
+        *self
+            .max_message_size
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = max_message_size;
+    }
+
+    /// Wraps `message` in an envelope of the negotiated version.
+    pub(crate) fn envelope(
+        &self,
+        message_id: MessageId,
+        message: ServerMessage,
+    ) -> ServerEnvelope {
+        ServerEnvelope {
+            version: self.version(),
+            message_id,
+            message,
+        }
+    }
+
+    pub(crate) fn send(
+        &self,
+        envelope: ServerEnvelope,
+    ) -> Result<(), MessageDeliveryError> {
+        let size = frame::size(&envelope);
+        let limit = self.max_message_size();
+        if size > limit {
+            return Err(MessageDeliveryError::MessageTooLarge { size, limit });
+        }
+
+        let mut saturated_since = self
+            .saturated_since
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        match self.sender.try_send(envelope) {
+            Ok(()) => {
+                *saturated_since = None;
+                Ok(())
+            },
+            Err(TrySendError::Closed(envelope)) => {
+                Err(MessageDeliveryError::SendError(SendError(envelope)))
+            },
+            Err(TrySendError::Full(_)) => {
+                let saturated = saturated_since.is_none();
+                let since = *saturated_since.get_or_insert_with(Instant::now);
+                if let Some(timeout) = self.saturation_timeout
+                    && !self.closed.is_cancelled()
+                {
+                    if since.elapsed() >= timeout {
+                        warn!(
+                            "Outbound queue saturated for {:?}, \
+                             disconnecting: {}",
+                            since.elapsed(),
+                            self.connection.client_key()
+                        );
+                        self.closed.cancel();
+                    } else if saturated {
+                        self.watch_saturation(since, timeout);
+                    }
+                }
+                Err(MessageDeliveryError::QueueFull(
+                    self.connection.client_key().clone(),
+                ))
+            },
+        }
+    }
+
+    /// Closes the connection if the queue, full since `since`, is still full
+    /// once `timeout` elapsed.
+    fn watch_saturation(&self, since: Instant, timeout: Duration) {
+        let sender: WeakSender<_> = self.sender.downgrade();
+        let saturated_since = Arc::clone(&self.saturated_since);
+        let closed = self.closed.clone();
+        let client_key = self.connection.client_key().clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                () = tokio::time::sleep(timeout) => {},
+                () = closed.cancelled() => return,
+            }
+            let still_saturated = *saturated_since
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                == Some(since)
+                && sender
+                    .upgrade()
+                    .is_some_and(|sender| sender.capacity() == 0);
+            if still_saturated {
+                warn!(
+                    "Outbound queue saturated for {:?}, disconnecting: \
+                     {client_key}",
+                    since.elapsed(),
+                );
+                closed.cancel();
+            }
+        });
+    }
+
+    pub(crate) fn connection(&self) -> &ConnectionContext {
+        &self.connection
+    }
+
+    pub(crate) fn connection_id(&self) -> ConnectionId {
+        self.connection.id()
+    }
+
+    /// Requests the connection to be closed.
+    pub(crate) fn close(&self) {
+        self.closed.cancel();
+    }
+
+    /// Cancelled when the connection must be closed.
+    pub(crate) fn closed(&self) -> &CancellationToken {
+        &self.closed
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use wasmbed_types::PublicKey;
+
+    fn envelope() -> ServerEnvelope {
+        ServerEnvelope {
+            version: Version::V0,
+            message_id: MessageId::default(),
+            message: ServerMessage::HeartbeatAck,
+        }
+    }
+
+    pub(crate) fn connection(
+        id: u64,
+        key: &PublicKey<'static>,
+    ) -> ConnectionContext {
+        let peer_addr = "127.0.0.1:0".parse().unwrap();
+        ConnectionContext::new(
+            ConnectionId(id),
+            peer_addr,
+            Vec::new(),
+            key.clone(),
+        )
+    }
+
+    #[test]
+    fn full_queue_rejects_messages() {
+        let key = PublicKey::from(vec![1; 32]);
+        let (outbound, mut receiver) =
+            Outbound::new(connection(0, &key), 2, None);
+
+        assert!(outbound.send(envelope()).is_ok());
+        assert!(outbound.send(envelope()).is_ok());
+        assert!(matches!(
+            outbound.send(envelope()),
+            Err(MessageDeliveryError::QueueFull(_))
+        ));
+        assert!(!outbound.closed().is_cancelled());
+
+        receiver.try_recv().unwrap();
+        assert!(outbound.send(envelope()).is_ok());
+    }
+
+    #[test]
+    fn saturated_queue_closes_connection() {
+        let key = PublicKey::from(vec![1; 32]);
+        let (outbound, _receiver) =
+            Outbound::new(connection(0, &key), 1, Some(Duration::ZERO));
+
+        assert!(outbound.send(envelope()).is_ok());
+        assert!(outbound.send(envelope()).is_err());
+        assert!(outbound.closed().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn saturated_queue_closes_connection_without_sending() {
+        let key = PublicKey::from(vec![1; 32]);
+        let timeout = Duration::from_millis(50);
+        let (outbound, _receiver) =
+            Outbound::new(connection(0, &key), 1, Some(timeout));
+
+        assert!(outbound.send(envelope()).is_ok());
+        assert!(outbound.send(envelope()).is_err());
+        assert!(!outbound.closed().is_cancelled());
+
+        tokio::time::timeout(timeout * 10, outbound.closed().cancelled())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn drained_queue_keeps_connection() {
+        let key = PublicKey::from(vec![1; 32]);
+        let timeout = Duration::from_millis(50);
+        let (outbound, mut receiver) =
+            Outbound::new(connection(0, &key), 1, Some(timeout));
+
+        assert!(outbound.send(envelope()).is_ok());
+        assert!(outbound.send(envelope()).is_err());
+        receiver.recv().await.unwrap();
+
+        tokio::time::sleep(timeout * 2).await;
+        assert!(!outbound.closed().is_cancelled());
+    }
+
+    #[test]
+    fn oversized_messages_are_rejected() {
+        let key = PublicKey::from(vec![1; 32]);
+        let (outbound, _receiver) = Outbound::new(connection(0, &key), 1, None);
+        let stop = ServerEnvelope {
+            message: ServerMessage::StopApplication {
+                app_id: "app-0".into(),
+            },
+            ..envelope()
+        };
+        let size = frame::size(&stop);
+
+        outbound.set_max_message_size(u32::try_from(size - 1).unwrap());
+        assert!(matches!(
+            outbound.send(stop.clone()),
+            Err(MessageDeliveryError::MessageTooLarge { limit, .. })
+                if limit == size - 1
+        ));
+        assert!(outbound.send(envelope()).is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use derive_more::{Display, Error};
+use tokio::sync::RwLock;
+use tokio::sync::oneshot;
+
+use wasmbed_protocol::{ClientEnvelope, ClientMessage, MessageId};
+use wasmbed_types::PublicKey;
+
+/// Replies awaited by [`Server::request`](crate::Server::request), keyed by
+/// client and by the identifier of the request.
+pub(crate) type Requests = Arc<
+    RwLock<
+        HashMap<
+            (PublicKey<'static>, MessageId),
+            oneshot::Sender<ClientMessage>,
+        >,
+    >,
+>;
+
+#[derive(Debug, Display, Error)]
+pub enum RequestError {
+    #[display("Client not connected")]
+    ClientNotFound,
+    /// The queue of messages waiting to be written to the client is full
+    #[display("Client message queue full")]
+    QueueFull,
+    #[display("Message of {size} bytes exceeds the limit of {limit} bytes")]
+    MessageTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    #[display("Client disconnected before replying")]
+    Disconnected,
+    #[display("No reply received in time")]
+    Timeout,
+}
+
+/// Whether `message` can be the reply to a server request. Messages the
+/// client sends on its own initiative are never routed to a request, even
+/// if their identifier happens to match one.
+fn is_reply(message: &ClientMessage) -> bool {
+    !matches!(
+        message,
+        ClientMessage::Heartbeat
+            | ClientMessage::Enroll
+            | ClientMessage::Hello { .. }
+    )
+}
+
+/// Hands the message in `envelope` over to the request waiting for it, if
+/// any. Returns the envelope back if no request is interested in it.
+pub(crate) async fn route_to_request(
+    requests: &Requests,
+    client_key: &PublicKey<'static>,
+    envelope: ClientEnvelope,
+) -> Option<ClientEnvelope> {
+    if !is_reply(&envelope.message) {
+        return Some(envelope);
+    }
+
+    let key = (client_key.clone(), envelope.message_id);
+    let Some(tx) = requests.write().await.remove(&key) else {
+        return Some(envelope);
+    };
+
+    match tx.send(envelope.message) {
+        Ok(()) => None,
+        // The request timed out in the meantime.
+        Err(message) => Some(ClientEnvelope {
+            message,
+            ..envelope
+        }),
+    }
+}
+
+/// Drops the reply channels of the requests to a client, making them fail
+/// with [`RequestError::Disconnected`].
+pub(crate) async fn abort_requests(
+    requests: &Requests,
+    client_key: &PublicKey<'static>,
+) {
+    let mut guard = requests.write().await;
+    guard.retain(|(key, _), _| key != client_key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmbed_protocol::Version;
+
+    fn envelope(
+        message_id: MessageId,
+        message: ClientMessage,
+    ) -> ClientEnvelope {
+        ClientEnvelope {
+            version: Version::V0,
+            message_id,
+            message,
+        }
+    }
+
+    #[tokio::test]
+    async fn reply_is_routed_to_request() {
+        let requests = Requests::default();
+        let client_key = PublicKey::from(vec![1; 32]);
+        let message_id = MessageId::default().next();
+        let (tx, rx) = oneshot::channel();
+        requests
+            .write()
+            .await
+            .insert((client_key.clone(), message_id), tx);
+
+        // A heartbeat reusing the identifier is not a reply.
+        let heartbeat = envelope(message_id, ClientMessage::Heartbeat);
+        assert!(
+            route_to_request(&requests, &client_key, heartbeat)
+                .await
+                .is_some()
+        );
+
+        let reply = ClientMessage::ApplicationDeployed {
+            app_id: "app-0".into(),
+        };
+        let routed = route_to_request(
+            &requests,
+            &client_key,
+            envelope(message_id, reply.clone()),
+        )
+        .await;
+        assert!(routed.is_none());
+        assert_eq!(rx.await.unwrap(), reply);
+        assert!(requests.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn disconnect_aborts_requests() {
+        let requests = Requests::default();
+        let client_key = PublicKey::from(vec![1; 32]);
+        let other_key = PublicKey::from(vec![2; 32]);
+        let message_id = MessageId::default().next();
+        let (tx, rx) = oneshot::channel();
+        let (other_tx, _other_rx) = oneshot::channel();
+        requests
+            .write()
+            .await
+            .insert((client_key.clone(), message_id), tx);
+        requests
+            .write()
+            .await
+            .insert((other_key.clone(), message_id), other_tx);
+
+        abort_requests(&requests, &client_key).await;
+
+        assert!(rx.await.is_err());
+        assert!(requests.read().await.contains_key(&(other_key, message_id)));
+    }
+}
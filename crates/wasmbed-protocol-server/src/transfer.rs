@@ -0,0 +1,392 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::time::Duration;
+
+use derive_more::{Display, Error};
+use sha2::{Digest as _, Sha256};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::timeout;
+use tracing::debug;
+
+use wasmbed_protocol::{
+    ApplicationId, ClientMessage, ResourceLimits, ServerMessage,
+};
+
+/// A Wasm module to be transferred to a client and deployed.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub app_id: ApplicationId,
+    pub bytecode: Vec<u8>,
+    pub entry_point: String,
+    pub limits: ResourceLimits,
+}
+
+/// Tuning parameters of a chunked module transfer.
+#[derive(Debug, Clone)]
+pub struct TransferConfig {
+    /// Maximum number of module bytes carried by a single chunk
+    pub chunk_size: usize,
+    /// Maximum number of chunks sent but not yet acknowledged
+    pub window: usize,
+    /// How long to wait for a reply before retrying
+    pub timeout: Duration,
+    /// How many consecutive timeouts are tolerated before giving up
+    pub max_retries: u32,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1024,
+            window: 4,
+            timeout: Duration::from_secs(10),
+            max_retries: 3,
+        }
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum TransferError {
+    #[display("Client not connected")]
+    ClientNotFound,
+    #[display("A transfer of application {app_id} is already in progress")]
+    AlreadyInProgress {
+        #[error(not(source))]
+        app_id: ApplicationId,
+    },
+    #[display("Module too large: {size} bytes")]
+    ModuleTooLarge {
+        size: usize,
+    },
+    #[display("Invalid transfer configuration")]
+    InvalidConfig,
+    #[display("Message of {size} bytes exceeds the limit of {limit} bytes")]
+    MessageTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    #[display("Client disconnected after acknowledging {acked} bytes")]
+    Disconnected {
+        acked: u32,
+    },
+    #[display("Client stopped responding after acknowledging {acked} bytes")]
+    Timeout {
+        acked: u32,
+    },
+    #[display("Client acknowledged offset {offset} beyond what was sent")]
+    InvalidOffset {
+        offset: u32,
+    },
+    #[display("Client rejected the module: {reason}")]
+    Rejected {
+        #[error(not(source))]
+        reason: String,
+    },
+}
+
+/// Returns the application a client message is about, if it is one of the
+/// replies a transfer waits for.
+pub(crate) fn transfer_reply_app_id(
+    message: &ClientMessage,
+) -> Option<&ApplicationId> {
+    match message {
+        ClientMessage::TransferReady { app_id, .. }
+        | ClientMessage::ChunkAck { app_id, .. }
+        | ClientMessage::ApplicationDeployed { app_id }
+        | ClientMessage::ApplicationFailed { app_id, .. } => Some(app_id),
+        _ => None,
+    }
+}
+
+/// Drives a chunked transfer of `module`.
+///
+/// Messages are sent through `send` and the client replies concerning this
+/// transfer are read from `replies`. The client decides where the transfer
+/// starts: after a reconnection it can resume from the last offset it
+/// acknowledged. Chunks are sent ahead of acknowledgements up to
+/// `config.window`; on timeout every unacknowledged chunk is sent again.
+pub(crate) async fn drive(
+    module: &Module,
+    config: &TransferConfig,
+    mut send: impl FnMut(ServerMessage) -> Result<(), TransferError>,
+    replies: &mut UnboundedReceiver<ClientMessage>,
+) -> Result<(), TransferError> {
+    if config.chunk_size == 0 || config.window == 0 {
+        return Err(TransferError::InvalidConfig);
+    }
+
+    let size = module.bytecode.len();
+    let size_u32 = u32::try_from(size)
+        .map_err(|_| TransferError::ModuleTooLarge { size })?;
+    let app_id = &module.app_id;
+
+    // Begin
+    let mut acked = 0;
+    let mut retries = 0;
+    let start = loop {
+        send(ServerMessage::BeginTransfer {
+            app_id: app_id.clone(),
+            size: size_u32,
+            entry_point: module.entry_point.clone(),
+            limits: module.limits,
+        })?;
+
+        match wait(replies, config, acked).await? {
+            Some(ClientMessage::TransferReady { offset, .. }) => {
+                break offset;
+            },
+            Some(ClientMessage::ApplicationFailed { reason, .. }) => {
+                return Err(TransferError::Rejected { reason });
+            },
+            Some(_) => {},
+            None => retry(&mut retries, config, acked)?,
+        }
+    };
+
+    // Chunks
+    acked = start.min(size_u32);
+    let mut next = usize::try_from(acked).unwrap_or(size);
+    let max_in_flight = config.window.saturating_mul(config.chunk_size);
+    retries = 0;
+
+    debug!("Transferring {app_id} ({size} bytes) from offset {acked}");
+
+    while usize::try_from(acked).unwrap_or(size) < size {
+        let acked_usize = usize::try_from(acked).unwrap_or(size);
+        while next < size && next.saturating_sub(acked_usize) < max_in_flight {
+            let end = next.saturating_add(config.chunk_size).min(size);
+            let data = module.bytecode.get(next..end).unwrap_or_default();
+            send(ServerMessage::TransferChunk {
+                app_id: app_id.clone(),
+                offset: u32::try_from(next).unwrap_or(size_u32),
+                data: data.to_vec(),
+            })?;
+            next = end;
+        }
+
+        match wait(replies, config, acked).await? {
+            Some(ClientMessage::ChunkAck { offset, .. }) => {
+                if usize::try_from(offset).unwrap_or(usize::MAX) > next {
+                    return Err(TransferError::InvalidOffset { offset });
+                }
+                if offset > acked {
+                    acked = offset;
+                    retries = 0;
+                }
+            },
+            Some(ClientMessage::ApplicationFailed { reason, .. }) => {
+                return Err(TransferError::Rejected { reason });
+            },
+            Some(_) => {},
+            None => {
+                retry(&mut retries, config, acked)?;
+                next = usize::try_from(acked).unwrap_or(size);
+            },
+        }
+    }
+
+    // Commit
+    let digest: [u8; 32] = Sha256::digest(&module.bytecode).into();
+    retries = 0;
+    loop {
+        send(ServerMessage::CommitTransfer {
+            app_id: app_id.clone(),
+            digest,
+        })?;
+
+        match wait(replies, config, acked).await? {
+            Some(ClientMessage::ApplicationDeployed { .. }) => return Ok(()),
+            Some(ClientMessage::ApplicationFailed { reason, .. }) => {
+                return Err(TransferError::Rejected { reason });
+            },
+            Some(_) => {},
+            None => retry(&mut retries, config, acked)?,
+        }
+    }
+}
+
+/// Waits for the next reply. `Ok(None)` means the wait timed out.
+async fn wait(
+    replies: &mut UnboundedReceiver<ClientMessage>,
+    config: &TransferConfig,
+    acked: u32,
+) -> Result<Option<ClientMessage>, TransferError> {
+    match timeout(config.timeout, replies.recv()).await {
+        Ok(Some(message)) => Ok(Some(message)),
+        Ok(None) => Err(TransferError::Disconnected { acked }),
+        Err(_) => Ok(None),
+    }
+}
+
+fn retry(
+    retries: &mut u32,
+    config: &TransferConfig,
+    acked: u32,
+) -> Result<(), TransferError> {
+    *retries = retries.saturating_add(1);
+    if *retries > config.max_retries {
+        Err(TransferError::Timeout { acked })
+    } else {
+        debug!("Transfer timed out, retry {retries}/{}", config.max_retries);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+
+    fn module(size: usize) -> Module {
+        Module {
+            app_id: "app-0".into(),
+            bytecode: (0..=u8::MAX).cycle().take(size).collect(),
+            entry_point: "_start".into(),
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    fn config() -> TransferConfig {
+        TransferConfig {
+            chunk_size: 16,
+            window: 2,
+            timeout: Duration::from_millis(100),
+            max_retries: 2,
+        }
+    }
+
+    /// A well-behaved client that already holds `resume` bytes of the
+    /// module. Returns the module bytes it received.
+    fn client(
+        resume: Vec<u8>,
+        replies: UnboundedSender<ClientMessage>,
+    ) -> (
+        UnboundedSender<ServerMessage>,
+        tokio::task::JoinHandle<Vec<u8>>,
+    ) {
+        let (tx, mut rx) = unbounded_channel::<ServerMessage>();
+        let handle = tokio::spawn(async move {
+            let mut received = resume;
+            while let Some(message) = rx.recv().await {
+                let reply = match message {
+                    ServerMessage::BeginTransfer { app_id, .. } => {
+                        ClientMessage::TransferReady {
+                            app_id,
+                            offset: u32::try_from(received.len()).unwrap(),
+                        }
+                    },
+                    ServerMessage::TransferChunk {
+                        app_id,
+                        offset,
+                        data,
+                    } => {
+                        if offset as usize == received.len() {
+                            received.extend_from_slice(&data);
+                        }
+                        ClientMessage::ChunkAck {
+                            app_id,
+                            offset: u32::try_from(received.len()).unwrap(),
+                        }
+                    },
+                    ServerMessage::CommitTransfer { app_id, digest } => {
+                        let actual: [u8; 32] = Sha256::digest(&received).into();
+                        if actual == digest {
+                            ClientMessage::ApplicationDeployed { app_id }
+                        } else {
+                            ClientMessage::ApplicationFailed {
+                                app_id,
+                                reason: "digest mismatch".into(),
+                            }
+                        }
+                    },
+                    _ => continue,
+                };
+                let _ = replies.send(reply);
+            }
+            received
+        });
+        (tx, handle)
+    }
+
+    async fn run(
+        module: &Module,
+        resume: Vec<u8>,
+    ) -> (Result<(), TransferError>, Vec<u8>, usize) {
+        let (replies_tx, mut replies) = unbounded_channel();
+        let (client_tx, handle) = client(resume, replies_tx);
+        let mut chunks: usize = 0;
+        let result = drive(
+            module,
+            &config(),
+            |message| {
+                if matches!(message, ServerMessage::TransferChunk { .. }) {
+                    chunks = chunks.saturating_add(1);
+                }
+                client_tx
+                    .send(message)
+                    .map_err(|_| TransferError::ClientNotFound)
+            },
+            &mut replies,
+        )
+        .await;
+        drop(client_tx);
+        (result, handle.await.unwrap(), chunks)
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let module = module(100);
+        let (result, received, chunks) = run(&module, Vec::new()).await;
+        assert!(result.is_ok());
+        assert_eq!(received, module.bytecode);
+        assert_eq!(chunks, 7);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_resume() {
+        let module = module(100);
+        let resume = module.bytecode[..64].to_vec();
+        let (result, received, chunks) = run(&module, resume).await;
+        assert!(result.is_ok());
+        assert_eq!(received, module.bytecode);
+        assert_eq!(chunks, 3);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_digest_mismatch() {
+        let module = module(100);
+        let (result, _, _) = run(&module, vec![0xFF; 32]).await;
+        assert!(matches!(result, Err(TransferError::Rejected { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_timeout() {
+        let (_replies_tx, mut replies) = unbounded_channel();
+        let mut begins: usize = 0;
+        let result = drive(
+            &module(100),
+            &config(),
+            |_| {
+                begins = begins.saturating_add(1);
+                Ok(())
+            },
+            &mut replies,
+        )
+        .await;
+        assert!(matches!(result, Err(TransferError::Timeout { acked: 0 })));
+        assert_eq!(begins, 3);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_disconnected() {
+        let (replies_tx, mut replies) = unbounded_channel();
+        drop(replies_tx);
+        let result =
+            drive(&module(100), &config(), |_| Ok(()), &mut replies).await;
+        assert!(matches!(
+            result,
+            Err(TransferError::Disconnected { acked: 0 })
+        ));
+    }
+}
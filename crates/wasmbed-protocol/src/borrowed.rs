@@ -0,0 +1,583 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Messages borrowing their strings and byte strings from the buffer they
+//! are decoded from, for devices without an allocator.
+
+use core::fmt;
+use core::iter::Copied;
+use core::slice;
+
+use minicbor::{Decode, Decoder};
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
+use crate::{Digest, Envelope, ErrorCode, MessageId, ResourceLimits, Version};
+
+/// Envelope of a borrowed client message
+pub type ClientEnvelopeRef<'a> = Envelope<ClientMessageRef<'a>>;
+
+/// Envelope of a borrowed server message
+pub type ServerEnvelopeRef<'a> = Envelope<ServerMessageRef<'a>>;
+
+/// Borrowed form of [`ClientMessage`](crate::ClientMessage), see its
+/// variants.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessageRef<'a> {
+    Heartbeat,
+    ApplicationDeployed {
+        app_id: &'a str,
+    },
+    ApplicationFailed {
+        app_id: &'a str,
+        reason: &'a str,
+    },
+    ApplicationStopped {
+        app_id: &'a str,
+    },
+    TransferReady {
+        app_id: &'a str,
+        offset: u32,
+    },
+    ChunkAck {
+        app_id: &'a str,
+        offset: u32,
+    },
+    Enroll,
+    Hello {
+        capabilities: CapabilitiesRef<'a>,
+    },
+    Negotiate {
+        versions: Versions<'a>,
+    },
+    Disconnect {
+        reason: &'a str,
+    },
+    Error {
+        code: ErrorCode,
+        detail: &'a str,
+        in_reply_to: MessageId,
+    },
+    Unknown {
+        tag: u32,
+        raw: &'a [u8],
+    },
+}
+
+/// Borrowed form of [`ServerMessage`](crate::ServerMessage), see its
+/// variants.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerMessageRef<'a> {
+    HeartbeatAck,
+    DeployApplication {
+        app_id: &'a str,
+        bytecode: &'a [u8],
+        entry_point: &'a str,
+        limits: ResourceLimits,
+    },
+    StopApplication {
+        app_id: &'a str,
+    },
+    BeginTransfer {
+        app_id: &'a str,
+        size: u32,
+        entry_point: &'a str,
+        limits: ResourceLimits,
+    },
+    TransferChunk {
+        app_id: &'a str,
+        offset: u32,
+        data: &'a [u8],
+    },
+    CommitTransfer {
+        app_id: &'a str,
+        digest: Digest,
+    },
+    EnrollAck {
+        device: &'a str,
+    },
+    HelloAck,
+    Negotiated {
+        version: Version,
+    },
+    VersionMismatch {
+        supported: Versions<'a>,
+    },
+    DisconnectAck,
+    GoAway {
+        reason: &'a str,
+        reconnect_after: u32,
+    },
+    Error {
+        code: ErrorCode,
+        detail: &'a str,
+        in_reply_to: MessageId,
+    },
+    Unknown {
+        tag: u32,
+        raw: &'a [u8],
+    },
+}
+
+/// Borrowed form of [`Capabilities`](crate::Capabilities).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapabilitiesRef<'a> {
+    pub firmware_version: &'a str,
+    pub runtime_version: &'a str,
+    pub free_memory: u32,
+    pub host_modules: HostModules<'a>,
+    pub max_message_size: u32,
+}
+
+/// Protocol version numbers, either given by the sender or still encoded in
+/// the buffer they were decoded from.
+#[derive(Clone, Copy)]
+pub enum Versions<'a> {
+    Slice(&'a [u32]),
+    /// A CBOR array of unsigned integers, checked when decoded
+    Encoded(&'a [u8]),
+}
+
+impl<'a> Versions<'a> {
+    pub fn iter(&self) -> VersionsIter<'a> {
+        match *self {
+            Versions::Slice(slice) => {
+                VersionsIter::Slice(slice.iter().copied())
+            },
+            Versions::Encoded(raw) => {
+                VersionsIter::Encoded(EncodedIter::new(raw))
+            },
+        }
+    }
+}
+
+pub enum VersionsIter<'a> {
+    Slice(Copied<slice::Iter<'a, u32>>),
+    Encoded(EncodedIter<'a>),
+}
+
+impl Iterator for VersionsIter<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        match self {
+            VersionsIter::Slice(iter) => iter.next(),
+            VersionsIter::Encoded(iter) => iter.next_item(),
+        }
+    }
+}
+
+/// Names of host modules, either given by the sender or still encoded in
+/// the buffer they were decoded from.
+#[derive(Clone, Copy)]
+pub enum HostModules<'a> {
+    Slice(&'a [&'a str]),
+    #[cfg(feature = "alloc")]
+    Owned(&'a [String]),
+    /// A CBOR array of text strings, checked when decoded
+    Encoded(&'a [u8]),
+}
+
+impl<'a> HostModules<'a> {
+    pub fn iter(&self) -> HostModulesIter<'a> {
+        match *self {
+            HostModules::Slice(slice) => {
+                HostModulesIter::Slice(slice.iter().copied())
+            },
+            #[cfg(feature = "alloc")]
+            HostModules::Owned(slice) => HostModulesIter::Owned(slice.iter()),
+            HostModules::Encoded(raw) => {
+                HostModulesIter::Encoded(EncodedIter::new(raw))
+            },
+        }
+    }
+}
+
+pub enum HostModulesIter<'a> {
+    Slice(Copied<slice::Iter<'a, &'a str>>),
+    #[cfg(feature = "alloc")]
+    Owned(slice::Iter<'a, String>),
+    Encoded(EncodedIter<'a>),
+}
+
+impl<'a> Iterator for HostModulesIter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        match self {
+            HostModulesIter::Slice(iter) => iter.next(),
+            #[cfg(feature = "alloc")]
+            HostModulesIter::Owned(iter) => iter.next().map(String::as_str),
+            HostModulesIter::Encoded(iter) => iter.next_item(),
+        }
+    }
+}
+
+/// Items of a definite length CBOR array, decoded on demand.
+pub struct EncodedIter<'a> {
+    decoder: Decoder<'a>,
+    remaining: u64,
+}
+
+impl<'a> EncodedIter<'a> {
+    fn new(raw: &'a [u8]) -> Self {
+        let mut decoder = Decoder::new(raw);
+        let remaining = decoder.array().ok().flatten().unwrap_or(0);
+        Self { decoder, remaining }
+    }
+
+    /// Decodes the next item, the array having been checked beforehand.
+    fn next_item<T: Decode<'a, ()>>(&mut self) -> Option<T> {
+        self.remaining = self.remaining.checked_sub(1)?;
+        self.decoder.decode().ok()
+    }
+}
+
+macro_rules! impl_list {
+    ($list:ident) => {
+        impl fmt::Debug for $list<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_list().entries(self.iter()).finish()
+            }
+        }
+
+        /// Lists are equal when their items are, however they are stored.
+        impl PartialEq for $list<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                self.iter().eq(other.iter())
+            }
+        }
+    };
+}
+
+impl_list!(Versions);
+impl_list!(HostModules);
+
+#[cfg(feature = "alloc")]
+mod owned {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::{Capabilities, ClientMessage, ServerMessage};
+
+    impl ClientMessage {
+        /// Borrows the strings and byte strings of the message.
+        pub fn borrowed(&self) -> ClientMessageRef<'_> {
+            match self {
+                ClientMessage::Heartbeat => ClientMessageRef::Heartbeat,
+                ClientMessage::ApplicationDeployed { app_id } => {
+                    ClientMessageRef::ApplicationDeployed { app_id }
+                },
+                ClientMessage::ApplicationFailed { app_id, reason } => {
+                    ClientMessageRef::ApplicationFailed { app_id, reason }
+                },
+                ClientMessage::ApplicationStopped { app_id } => {
+                    ClientMessageRef::ApplicationStopped { app_id }
+                },
+                ClientMessage::TransferReady { app_id, offset } => {
+                    ClientMessageRef::TransferReady {
+                        app_id,
+                        offset: *offset,
+                    }
+                },
+                ClientMessage::ChunkAck { app_id, offset } => {
+                    ClientMessageRef::ChunkAck {
+                        app_id,
+                        offset: *offset,
+                    }
+                },
+                ClientMessage::Enroll => ClientMessageRef::Enroll,
+                ClientMessage::Hello { capabilities } => {
+                    ClientMessageRef::Hello {
+                        capabilities: capabilities.borrowed(),
+                    }
+                },
+                ClientMessage::Negotiate { versions } => {
+                    ClientMessageRef::Negotiate {
+                        versions: Versions::Slice(versions),
+                    }
+                },
+                ClientMessage::Disconnect { reason } => {
+                    ClientMessageRef::Disconnect { reason }
+                },
+                ClientMessage::Error {
+                    code,
+                    detail,
+                    in_reply_to,
+                } => ClientMessageRef::Error {
+                    code: *code,
+                    detail,
+                    in_reply_to: *in_reply_to,
+                },
+                ClientMessage::Unknown { tag, raw } => {
+                    ClientMessageRef::Unknown { tag: *tag, raw }
+                },
+            }
+        }
+    }
+
+    impl From<ClientMessageRef<'_>> for ClientMessage {
+        fn from(message: ClientMessageRef<'_>) -> Self {
+            match message {
+                ClientMessageRef::Heartbeat => ClientMessage::Heartbeat,
+                ClientMessageRef::ApplicationDeployed { app_id } => {
+                    ClientMessage::ApplicationDeployed {
+                        app_id: app_id.into(),
+                    }
+                },
+                ClientMessageRef::ApplicationFailed { app_id, reason } => {
+                    ClientMessage::ApplicationFailed {
+                        app_id: app_id.into(),
+                        reason: reason.into(),
+                    }
+                },
+                ClientMessageRef::ApplicationStopped { app_id } => {
+                    ClientMessage::ApplicationStopped {
+                        app_id: app_id.into(),
+                    }
+                },
+                ClientMessageRef::TransferReady { app_id, offset } => {
+                    ClientMessage::TransferReady {
+                        app_id: app_id.into(),
+                        offset,
+                    }
+                },
+                ClientMessageRef::ChunkAck { app_id, offset } => {
+                    ClientMessage::ChunkAck {
+                        app_id: app_id.into(),
+                        offset,
+                    }
+                },
+                ClientMessageRef::Enroll => ClientMessage::Enroll,
+                ClientMessageRef::Hello { capabilities } => {
+                    ClientMessage::Hello {
+                        capabilities: capabilities.into(),
+                    }
+                },
+                ClientMessageRef::Negotiate { versions } => {
+                    ClientMessage::Negotiate {
+                        versions: versions.iter().collect(),
+                    }
+                },
+                ClientMessageRef::Disconnect { reason } => {
+                    ClientMessage::Disconnect {
+                        reason: reason.into(),
+                    }
+                },
+                ClientMessageRef::Error {
+                    code,
+                    detail,
+                    in_reply_to,
+                } => ClientMessage::Error {
+                    code,
+                    detail: detail.into(),
+                    in_reply_to,
+                },
+                ClientMessageRef::Unknown { tag, raw } => {
+                    ClientMessage::Unknown {
+                        tag,
+                        raw: raw.into(),
+                    }
+                },
+            }
+        }
+    }
+
+    impl ServerMessage {
+        /// Borrows the strings and byte strings of the message.
+        pub fn borrowed(&self) -> ServerMessageRef<'_> {
+            match self {
+                ServerMessage::HeartbeatAck => ServerMessageRef::HeartbeatAck,
+                ServerMessage::DeployApplication {
+                    app_id,
+                    bytecode,
+                    entry_point,
+                    limits,
+                } => ServerMessageRef::DeployApplication {
+                    app_id,
+                    bytecode,
+                    entry_point,
+                    limits: *limits,
+                },
+                ServerMessage::StopApplication { app_id } => {
+                    ServerMessageRef::StopApplication { app_id }
+                },
+                ServerMessage::BeginTransfer {
+                    app_id,
+                    size,
+                    entry_point,
+                    limits,
+                } => ServerMessageRef::BeginTransfer {
+                    app_id,
+                    size: *size,
+                    entry_point,
+                    limits: *limits,
+                },
+                ServerMessage::TransferChunk {
+                    app_id,
+                    offset,
+                    data,
+                } => ServerMessageRef::TransferChunk {
+                    app_id,
+                    offset: *offset,
+                    data,
+                },
+                ServerMessage::CommitTransfer { app_id, digest } => {
+                    ServerMessageRef::CommitTransfer {
+                        app_id,
+                        digest: *digest,
+                    }
+                },
+                ServerMessage::EnrollAck { device } => {
+                    ServerMessageRef::EnrollAck { device }
+                },
+                ServerMessage::HelloAck => ServerMessageRef::HelloAck,
+                ServerMessage::Negotiated { version } => {
+                    ServerMessageRef::Negotiated { version: *version }
+                },
+                ServerMessage::VersionMismatch { supported } => {
+                    ServerMessageRef::VersionMismatch {
+                        supported: Versions::Slice(supported),
+                    }
+                },
+                ServerMessage::DisconnectAck => ServerMessageRef::DisconnectAck,
+                ServerMessage::GoAway {
+                    reason,
+                    reconnect_after,
+                } => ServerMessageRef::GoAway {
+                    reason,
+                    reconnect_after: *reconnect_after,
+                },
+                ServerMessage::Error {
+                    code,
+                    detail,
+                    in_reply_to,
+                } => ServerMessageRef::Error {
+                    code: *code,
+                    detail,
+                    in_reply_to: *in_reply_to,
+                },
+                ServerMessage::Unknown { tag, raw } => {
+                    ServerMessageRef::Unknown { tag: *tag, raw }
+                },
+            }
+        }
+    }
+
+    impl From<ServerMessageRef<'_>> for ServerMessage {
+        fn from(message: ServerMessageRef<'_>) -> Self {
+            match message {
+                ServerMessageRef::HeartbeatAck => ServerMessage::HeartbeatAck,
+                ServerMessageRef::DeployApplication {
+                    app_id,
+                    bytecode,
+                    entry_point,
+                    limits,
+                } => ServerMessage::DeployApplication {
+                    app_id: app_id.into(),
+                    bytecode: bytecode.into(),
+                    entry_point: entry_point.into(),
+                    limits,
+                },
+                ServerMessageRef::StopApplication { app_id } => {
+                    ServerMessage::StopApplication {
+                        app_id: app_id.into(),
+                    }
+                },
+                ServerMessageRef::BeginTransfer {
+                    app_id,
+                    size,
+                    entry_point,
+                    limits,
+                } => ServerMessage::BeginTransfer {
+                    app_id: app_id.into(),
+                    size,
+                    entry_point: entry_point.into(),
+                    limits,
+                },
+                ServerMessageRef::TransferChunk {
+                    app_id,
+                    offset,
+                    data,
+                } => ServerMessage::TransferChunk {
+                    app_id: app_id.into(),
+                    offset,
+                    data: data.into(),
+                },
+                ServerMessageRef::CommitTransfer { app_id, digest } => {
+                    ServerMessage::CommitTransfer {
+                        app_id: app_id.into(),
+                        digest,
+                    }
+                },
+                ServerMessageRef::EnrollAck { device } => {
+                    ServerMessage::EnrollAck {
+                        device: device.into(),
+                    }
+                },
+                ServerMessageRef::HelloAck => ServerMessage::HelloAck,
+                ServerMessageRef::Negotiated { version } => {
+                    ServerMessage::Negotiated { version }
+                },
+                ServerMessageRef::VersionMismatch { supported } => {
+                    ServerMessage::VersionMismatch {
+                        supported: supported.iter().collect(),
+                    }
+                },
+                ServerMessageRef::DisconnectAck => ServerMessage::DisconnectAck,
+                ServerMessageRef::GoAway {
+                    reason,
+                    reconnect_after,
+                } => ServerMessage::GoAway {
+                    reason: reason.into(),
+                    reconnect_after,
+                },
+                ServerMessageRef::Error {
+                    code,
+                    detail,
+                    in_reply_to,
+                } => ServerMessage::Error {
+                    code,
+                    detail: detail.into(),
+                    in_reply_to,
+                },
+                ServerMessageRef::Unknown { tag, raw } => {
+                    ServerMessage::Unknown {
+                        tag,
+                        raw: raw.into(),
+                    }
+                },
+            }
+        }
+    }
+
+    impl Capabilities {
+        /// Borrows the strings of the capabilities.
+        pub fn borrowed(&self) -> CapabilitiesRef<'_> {
+            CapabilitiesRef {
+                firmware_version: &self.firmware_version,
+                runtime_version: &self.runtime_version,
+                free_memory: self.free_memory,
+                host_modules: HostModules::Owned(&self.host_modules),
+                max_message_size: self.max_message_size,
+            }
+        }
+    }
+
+    impl From<CapabilitiesRef<'_>> for Capabilities {
+        fn from(capabilities: CapabilitiesRef<'_>) -> Self {
+            Self {
+                firmware_version: capabilities.firmware_version.into(),
+                runtime_version: capabilities.runtime_version.into(),
+                free_memory: capabilities.free_memory,
+                host_modules: capabilities
+                    .host_modules
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                max_message_size: capabilities.max_message_size,
+            }
+        }
+    }
+}
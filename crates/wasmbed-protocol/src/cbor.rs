@@ -5,109 +5,704 @@ use derive_more::{Display, Error};
 use minicbor::{Decode, Decoder, Encode, Encoder};
 use minicbor::encode::{Error as EncodeError, Write};
 use minicbor::decode::Error as DecodeError;
-use crate::{ClientMessage, ServerMessage};
+use crate::{
+    CapabilitiesRef, ClientMessageRef, Digest, ErrorCode, HostModules,
+    ServerMessageRef, Versions,
+};
+#[cfg(feature = "alloc")]
+use crate::{Capabilities, ClientMessage, ServerMessage};
 
 const CLIENT_HEARTBEAT: u32 = 0;
 const SERVER_HEARTBEAT_ACK: u32 = 1;
+const SERVER_DEPLOY_APPLICATION: u32 = 2;
+const SERVER_STOP_APPLICATION: u32 = 3;
+const CLIENT_APPLICATION_DEPLOYED: u32 = 4;
+const CLIENT_APPLICATION_FAILED: u32 = 5;
+const CLIENT_APPLICATION_STOPPED: u32 = 6;
+const SERVER_BEGIN_TRANSFER: u32 = 7;
+const SERVER_TRANSFER_CHUNK: u32 = 8;
+const SERVER_COMMIT_TRANSFER: u32 = 9;
+const CLIENT_TRANSFER_READY: u32 = 10;
+const CLIENT_CHUNK_ACK: u32 = 11;
+const CLIENT_ENROLL: u32 = 12;
+const SERVER_ENROLL_ACK: u32 = 13;
+const CLIENT_HELLO: u32 = 14;
+const SERVER_HELLO_ACK: u32 = 15;
+const CLIENT_NEGOTIATE: u32 = 16;
+const SERVER_NEGOTIATED: u32 = 17;
+const SERVER_VERSION_MISMATCH: u32 = 18;
+const SERVER_ERROR: u32 = 19;
+const CLIENT_ERROR: u32 = 20;
+const CLIENT_DISCONNECT: u32 = 21;
+const SERVER_DISCONNECT_ACK: u32 = 22;
+const SERVER_GO_AWAY: u32 = 23;
+
+const ERROR_UNKNOWN_MESSAGE: u32 = 0;
+const ERROR_MALFORMED_REQUEST: u32 = 1;
+const ERROR_UNAUTHORIZED: u32 = 2;
+const ERROR_RESOURCE_EXHAUSTED: u32 = 3;
+const ERROR_INTERNAL: u32 = 4;
+
+/// Number of fields of the encoded capabilities. Later versions may append
+/// fields, which are skipped.
+const CAPABILITIES_LEN: u64 = 5;
 
 #[derive(Debug, Display, Error)]
 enum MessageDecodeError {
     #[display(
         "Unexpected array length: it should be {expected} but it is {actual}"
     )]
-    UnexpectedArrayLength {
+    ArrayLength {
         expected: u64,
         actual: u64,
     },
-    #[display("Unknown tag: {tag}")]
-    UnknownTag {
-        tag: u32,
-    },
     #[display("Unexpected indefinite length array")]
-    UnexpectedIndefiniteLengthArray,
+    IndefiniteLengthArray,
+    #[display("Unexpected empty array, a message starts with its tag")]
+    EmptyArray,
+    #[display(
+        "Unexpected byte string length: it should be {expected} but it is {actual}"
+    )]
+    BytesLength {
+        expected: usize,
+        actual: usize,
+    },
 }
 
-impl Encode<()> for ClientMessage {
+impl From<MessageDecodeError> for DecodeError {
+    #[cfg(feature = "alloc")]
+    fn from(error: MessageDecodeError) -> Self {
+        DecodeError::custom(error)
+    }
+
+    /// Without an allocator, only a static description of the error is kept.
+    #[cfg(not(feature = "alloc"))]
+    fn from(error: MessageDecodeError) -> Self {
+        DecodeError::message(match error {
+            MessageDecodeError::ArrayLength { .. } => "Unexpected array length",
+            MessageDecodeError::IndefiniteLengthArray => {
+                "Unexpected indefinite length array"
+            },
+            MessageDecodeError::EmptyArray => "Unexpected empty array",
+            MessageDecodeError::BytesLength { .. } => {
+                "Unexpected byte string length"
+            },
+        })
+    }
+}
+
+/// Reads the length of a definite length array.
+fn decode_array_len(d: &mut Decoder<'_>) -> Result<u64, DecodeError> {
+    d.array()?
+        .ok_or_else(|| MessageDecodeError::IndefiniteLengthArray.into())
+}
+
+/// Reads the header shared by all messages, i.e. the definite length of the
+/// enclosing array and the message tag.
+fn decode_header(d: &mut Decoder<'_>) -> Result<(u32, u64), DecodeError> {
+    let array_len = decode_array_len(d)?;
+    if array_len == 0 {
+        return Err(MessageDecodeError::EmptyArray.into());
+    }
+    let tag = d.u32()?;
+    Ok((tag, array_len))
+}
+
+fn expect_array_len(expected: u64, actual: u64) -> Result<(), DecodeError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(MessageDecodeError::ArrayLength { expected, actual }.into())
+    }
+}
+
+/// Returns the bytes read by `d` since `start`.
+fn consumed<'b>(
+    d: &Decoder<'b>,
+    start: usize,
+) -> Result<&'b [u8], DecodeError> {
+    d.input()
+        .get(start..d.position())
+        .ok_or_else(DecodeError::end_of_input)
+}
+
+/// Skips the remaining fields of a message with an unknown tag, whose
+/// encoding starts at `start`, and returns the whole encoded message.
+fn decode_unknown<'b>(
+    d: &mut Decoder<'b>,
+    start: usize,
+    array_len: u64,
+) -> Result<&'b [u8], DecodeError> {
+    if array_len == 0 {
+        return Err(MessageDecodeError::EmptyArray.into());
+    }
+    for _ in 1..array_len {
+        d.skip()?;
+    }
+    consumed(d, start)
+}
+
+/// Checks that the next item is an array of `T` and returns it encoded.
+fn decode_array<'b, T: Decode<'b, ()>>(
+    d: &mut Decoder<'b>,
+) -> Result<&'b [u8], DecodeError> {
+    let start = d.position();
+    for _ in 0..decode_array_len(d)? {
+        d.decode::<T>()?;
+    }
+    consumed(d, start)
+}
+
+fn decode_digest(d: &mut Decoder<'_>) -> Result<Digest, DecodeError> {
+    let bytes = d.bytes()?;
+    bytes.try_into().map_err(|_| {
+        MessageDecodeError::BytesLength {
+            expected: core::mem::size_of::<Digest>(),
+            actual: bytes.len(),
+        }
+        .into()
+    })
+}
+
+/// Writes a message kept encoded, e.g. one with an unknown tag.
+fn encode_raw<W: Write>(
+    e: &mut Encoder<W>,
+    raw: &[u8],
+) -> Result<(), EncodeError<W::Error>> {
+    e.writer_mut().write_all(raw).map_err(EncodeError::write)
+}
+
+fn array_len(len: usize) -> u64 {
+    u64::try_from(len).unwrap_or(u64::MAX)
+}
+
+impl Encode<()> for ClientMessageRef<'_> {
     fn encode<W: Write>(
         &self,
         e: &mut Encoder<W>,
-        _ctx: &mut (),
+        ctx: &mut (),
     ) -> Result<(), EncodeError<W::Error>> {
         match self {
-            ClientMessage::Heartbeat => {
+            ClientMessageRef::Heartbeat => {
                 e.array(1)?.u32(CLIENT_HEARTBEAT)?;
             },
+            ClientMessageRef::ApplicationDeployed { app_id } => {
+                e.array(2)?.u32(CLIENT_APPLICATION_DEPLOYED)?.str(app_id)?;
+            },
+            ClientMessageRef::ApplicationFailed { app_id, reason } => {
+                e.array(3)?
+                    .u32(CLIENT_APPLICATION_FAILED)?
+                    .str(app_id)?
+                    .str(reason)?;
+            },
+            ClientMessageRef::ApplicationStopped { app_id } => {
+                e.array(2)?.u32(CLIENT_APPLICATION_STOPPED)?.str(app_id)?;
+            },
+            ClientMessageRef::TransferReady { app_id, offset } => {
+                e.array(3)?
+                    .u32(CLIENT_TRANSFER_READY)?
+                    .str(app_id)?
+                    .u32(*offset)?;
+            },
+            ClientMessageRef::ChunkAck { app_id, offset } => {
+                e.array(3)?
+                    .u32(CLIENT_CHUNK_ACK)?
+                    .str(app_id)?
+                    .u32(*offset)?;
+            },
+            ClientMessageRef::Enroll => {
+                e.array(1)?.u32(CLIENT_ENROLL)?;
+            },
+            ClientMessageRef::Hello { capabilities } => {
+                e.array(2)?
+                    .u32(CLIENT_HELLO)?
+                    .encode_with(capabilities, ctx)?;
+            },
+            ClientMessageRef::Negotiate { versions } => {
+                e.array(2)?
+                    .u32(CLIENT_NEGOTIATE)?
+                    .encode_with(versions, ctx)?;
+            },
+            ClientMessageRef::Disconnect { reason } => {
+                e.array(2)?.u32(CLIENT_DISCONNECT)?.str(reason)?;
+            },
+            ClientMessageRef::Error {
+                code,
+                detail,
+                in_reply_to,
+            } => {
+                e.array(4)?
+                    .u32(CLIENT_ERROR)?
+                    .encode_with(code, ctx)?
+                    .str(detail)?
+                    .encode_with(in_reply_to, ctx)?;
+            },
+            ClientMessageRef::Unknown { raw, .. } => encode_raw(e, raw)?,
         }
         Ok(())
     }
 }
 
-impl<'b> Decode<'b, ()> for ClientMessage {
-    fn decode(d: &mut Decoder<'b>, _ctx: &mut ()) -> Result<Self, DecodeError> {
-        let array_len = d.array()?.ok_or_else(|| {
-            DecodeError::custom(
-                MessageDecodeError::UnexpectedIndefiniteLengthArray,
-            )
-        })?;
-
-        let tag = d.u32()?;
-        match (tag, array_len) {
-            (CLIENT_HEARTBEAT, 1) => Ok(ClientMessage::Heartbeat),
-            (CLIENT_HEARTBEAT, _) => Err(DecodeError::custom(
-                MessageDecodeError::UnexpectedArrayLength {
-                    expected: 1,
-                    actual: array_len,
-                },
-            )),
-            _ => {
-                Err(DecodeError::custom(MessageDecodeError::UnknownTag { tag }))
+impl<'b> Decode<'b, ()> for ClientMessageRef<'b> {
+    fn decode(d: &mut Decoder<'b>, ctx: &mut ()) -> Result<Self, DecodeError> {
+        let start = d.position();
+        let (tag, array_len) = decode_header(d)?;
+        match tag {
+            CLIENT_HEARTBEAT => {
+                expect_array_len(1, array_len)?;
+                Ok(ClientMessageRef::Heartbeat)
+            },
+            CLIENT_APPLICATION_DEPLOYED => {
+                expect_array_len(2, array_len)?;
+                Ok(ClientMessageRef::ApplicationDeployed { app_id: d.str()? })
             },
+            CLIENT_APPLICATION_FAILED => {
+                expect_array_len(3, array_len)?;
+                Ok(ClientMessageRef::ApplicationFailed {
+                    app_id: d.str()?,
+                    reason: d.str()?,
+                })
+            },
+            CLIENT_APPLICATION_STOPPED => {
+                expect_array_len(2, array_len)?;
+                Ok(ClientMessageRef::ApplicationStopped { app_id: d.str()? })
+            },
+            CLIENT_TRANSFER_READY => {
+                expect_array_len(3, array_len)?;
+                Ok(ClientMessageRef::TransferReady {
+                    app_id: d.str()?,
+                    offset: d.u32()?,
+                })
+            },
+            CLIENT_CHUNK_ACK => {
+                expect_array_len(3, array_len)?;
+                Ok(ClientMessageRef::ChunkAck {
+                    app_id: d.str()?,
+                    offset: d.u32()?,
+                })
+            },
+            CLIENT_ENROLL => {
+                expect_array_len(1, array_len)?;
+                Ok(ClientMessageRef::Enroll)
+            },
+            CLIENT_HELLO => {
+                expect_array_len(2, array_len)?;
+                Ok(ClientMessageRef::Hello {
+                    capabilities: d.decode_with(ctx)?,
+                })
+            },
+            CLIENT_NEGOTIATE => {
+                expect_array_len(2, array_len)?;
+                Ok(ClientMessageRef::Negotiate {
+                    versions: d.decode_with(ctx)?,
+                })
+            },
+            CLIENT_DISCONNECT => {
+                expect_array_len(2, array_len)?;
+                Ok(ClientMessageRef::Disconnect { reason: d.str()? })
+            },
+            CLIENT_ERROR => {
+                expect_array_len(4, array_len)?;
+                Ok(ClientMessageRef::Error {
+                    code: d.decode_with(ctx)?,
+                    detail: d.str()?,
+                    in_reply_to: d.decode_with(ctx)?,
+                })
+            },
+            _ => Ok(ClientMessageRef::Unknown {
+                tag,
+                raw: decode_unknown(d, start, array_len)?,
+            }),
         }
     }
 }
 
-impl Encode<()> for ServerMessage {
+impl Encode<()> for ServerMessageRef<'_> {
     fn encode<W: Write>(
         &self,
         e: &mut Encoder<W>,
-        _ctx: &mut (),
+        ctx: &mut (),
     ) -> Result<(), EncodeError<W::Error>> {
         match self {
-            ServerMessage::HeartbeatAck => {
+            ServerMessageRef::HeartbeatAck => {
                 e.array(1)?.u32(SERVER_HEARTBEAT_ACK)?;
             },
+            ServerMessageRef::DeployApplication {
+                app_id,
+                bytecode,
+                entry_point,
+                limits,
+            } => {
+                e.array(5)?
+                    .u32(SERVER_DEPLOY_APPLICATION)?
+                    .str(app_id)?
+                    .bytes(bytecode)?
+                    .str(entry_point)?
+                    .encode_with(limits, ctx)?;
+            },
+            ServerMessageRef::StopApplication { app_id } => {
+                e.array(2)?.u32(SERVER_STOP_APPLICATION)?.str(app_id)?;
+            },
+            ServerMessageRef::BeginTransfer {
+                app_id,
+                size,
+                entry_point,
+                limits,
+            } => {
+                e.array(5)?
+                    .u32(SERVER_BEGIN_TRANSFER)?
+                    .str(app_id)?
+                    .u32(*size)?
+                    .str(entry_point)?
+                    .encode_with(limits, ctx)?;
+            },
+            ServerMessageRef::TransferChunk {
+                app_id,
+                offset,
+                data,
+            } => {
+                e.array(4)?
+                    .u32(SERVER_TRANSFER_CHUNK)?
+                    .str(app_id)?
+                    .u32(*offset)?
+                    .bytes(data)?;
+            },
+            ServerMessageRef::CommitTransfer { app_id, digest } => {
+                e.array(3)?
+                    .u32(SERVER_COMMIT_TRANSFER)?
+                    .str(app_id)?
+                    .bytes(digest)?;
+            },
+            ServerMessageRef::EnrollAck { device } => {
+                e.array(2)?.u32(SERVER_ENROLL_ACK)?.str(device)?;
+            },
+            ServerMessageRef::HelloAck => {
+                e.array(1)?.u32(SERVER_HELLO_ACK)?;
+            },
+            ServerMessageRef::Negotiated { version } => {
+                e.array(2)?
+                    .u32(SERVER_NEGOTIATED)?
+                    .encode_with(version, ctx)?;
+            },
+            ServerMessageRef::VersionMismatch { supported } => {
+                e.array(2)?
+                    .u32(SERVER_VERSION_MISMATCH)?
+                    .encode_with(supported, ctx)?;
+            },
+            ServerMessageRef::DisconnectAck => {
+                e.array(1)?.u32(SERVER_DISCONNECT_ACK)?;
+            },
+            ServerMessageRef::GoAway {
+                reason,
+                reconnect_after,
+            } => {
+                e.array(3)?
+                    .u32(SERVER_GO_AWAY)?
+                    .str(reason)?
+                    .u32(*reconnect_after)?;
+            },
+            ServerMessageRef::Error {
+                code,
+                detail,
+                in_reply_to,
+            } => {
+                e.array(4)?
+                    .u32(SERVER_ERROR)?
+                    .encode_with(code, ctx)?
+                    .str(detail)?
+                    .encode_with(in_reply_to, ctx)?;
+            },
+            ServerMessageRef::Unknown { raw, .. } => encode_raw(e, raw)?,
         }
         Ok(())
     }
 }
 
-impl<'b> Decode<'b, ()> for ServerMessage {
+impl<'b> Decode<'b, ()> for ServerMessageRef<'b> {
+    fn decode(d: &mut Decoder<'b>, ctx: &mut ()) -> Result<Self, DecodeError> {
+        let start = d.position();
+        let (tag, array_len) = decode_header(d)?;
+        match tag {
+            SERVER_HEARTBEAT_ACK => {
+                expect_array_len(1, array_len)?;
+                Ok(ServerMessageRef::HeartbeatAck)
+            },
+            SERVER_DEPLOY_APPLICATION => {
+                expect_array_len(5, array_len)?;
+                Ok(ServerMessageRef::DeployApplication {
+                    app_id: d.str()?,
+                    bytecode: d.bytes()?,
+                    entry_point: d.str()?,
+                    limits: d.decode_with(ctx)?,
+                })
+            },
+            SERVER_STOP_APPLICATION => {
+                expect_array_len(2, array_len)?;
+                Ok(ServerMessageRef::StopApplication { app_id: d.str()? })
+            },
+            SERVER_BEGIN_TRANSFER => {
+                expect_array_len(5, array_len)?;
+                Ok(ServerMessageRef::BeginTransfer {
+                    app_id: d.str()?,
+                    size: d.u32()?,
+                    entry_point: d.str()?,
+                    limits: d.decode_with(ctx)?,
+                })
+            },
+            SERVER_TRANSFER_CHUNK => {
+                expect_array_len(4, array_len)?;
+                Ok(ServerMessageRef::TransferChunk {
+                    app_id: d.str()?,
+                    offset: d.u32()?,
+                    data: d.bytes()?,
+                })
+            },
+            SERVER_COMMIT_TRANSFER => {
+                expect_array_len(3, array_len)?;
+                Ok(ServerMessageRef::CommitTransfer {
+                    app_id: d.str()?,
+                    digest: decode_digest(d)?,
+                })
+            },
+            SERVER_ENROLL_ACK => {
+                expect_array_len(2, array_len)?;
+                Ok(ServerMessageRef::EnrollAck { device: d.str()? })
+            },
+            SERVER_HELLO_ACK => {
+                expect_array_len(1, array_len)?;
+                Ok(ServerMessageRef::HelloAck)
+            },
+            SERVER_NEGOTIATED => {
+                expect_array_len(2, array_len)?;
+                Ok(ServerMessageRef::Negotiated {
+                    version: d.decode_with(ctx)?,
+                })
+            },
+            SERVER_VERSION_MISMATCH => {
+                expect_array_len(2, array_len)?;
+                Ok(ServerMessageRef::VersionMismatch {
+                    supported: d.decode_with(ctx)?,
+                })
+            },
+            SERVER_DISCONNECT_ACK => {
+                expect_array_len(1, array_len)?;
+                Ok(ServerMessageRef::DisconnectAck)
+            },
+            SERVER_GO_AWAY => {
+                expect_array_len(3, array_len)?;
+                Ok(ServerMessageRef::GoAway {
+                    reason: d.str()?,
+                    reconnect_after: d.u32()?,
+                })
+            },
+            SERVER_ERROR => {
+                expect_array_len(4, array_len)?;
+                Ok(ServerMessageRef::Error {
+                    code: d.decode_with(ctx)?,
+                    detail: d.str()?,
+                    in_reply_to: d.decode_with(ctx)?,
+                })
+            },
+            _ => Ok(ServerMessageRef::Unknown {
+                tag,
+                raw: decode_unknown(d, start, array_len)?,
+            }),
+        }
+    }
+}
+
+impl Encode<()> for CapabilitiesRef<'_> {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        ctx: &mut (),
+    ) -> Result<(), EncodeError<W::Error>> {
+        e.array(CAPABILITIES_LEN)?
+            .str(self.firmware_version)?
+            .str(self.runtime_version)?
+            .u32(self.free_memory)?
+            .encode_with(self.host_modules, ctx)?
+            .u32(self.max_message_size)?;
+        Ok(())
+    }
+}
+
+impl<'b> Decode<'b, ()> for CapabilitiesRef<'b> {
+    fn decode(d: &mut Decoder<'b>, ctx: &mut ()) -> Result<Self, DecodeError> {
+        let array_len = decode_array_len(d)?;
+        if array_len < CAPABILITIES_LEN {
+            return Err(MessageDecodeError::ArrayLength {
+                expected: CAPABILITIES_LEN,
+                actual: array_len,
+            }
+            .into());
+        }
+        let capabilities = CapabilitiesRef {
+            firmware_version: d.str()?,
+            runtime_version: d.str()?,
+            free_memory: d.u32()?,
+            host_modules: d.decode_with(ctx)?,
+            max_message_size: d.u32()?,
+        };
+        for _ in CAPABILITIES_LEN..array_len {
+            d.skip()?;
+        }
+        Ok(capabilities)
+    }
+}
+
+impl Encode<()> for Versions<'_> {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        _ctx: &mut (),
+    ) -> Result<(), EncodeError<W::Error>> {
+        match self {
+            Versions::Slice(versions) => {
+                e.array(array_len(versions.len()))?;
+                for version in *versions {
+                    e.u32(*version)?;
+                }
+                Ok(())
+            },
+            Versions::Encoded(raw) => encode_raw(e, raw),
+        }
+    }
+}
+
+impl<'b> Decode<'b, ()> for Versions<'b> {
     fn decode(d: &mut Decoder<'b>, _ctx: &mut ()) -> Result<Self, DecodeError> {
-        let array_len = d.array()?.ok_or_else(|| {
-            DecodeError::custom(
-                MessageDecodeError::UnexpectedIndefiniteLengthArray,
-            )
-        })?;
-
-        let tag = d.u32()?;
-        match (tag, array_len) {
-            (SERVER_HEARTBEAT_ACK, 1) => Ok(ServerMessage::HeartbeatAck),
-            (SERVER_HEARTBEAT_ACK, _) => Err(DecodeError::custom(
-                MessageDecodeError::UnexpectedArrayLength {
-                    expected: 1,
-                    actual: array_len,
-                },
-            )),
-            _ => {
-                Err(DecodeError::custom(MessageDecodeError::UnknownTag { tag }))
+        decode_array::<u32>(d).map(Versions::Encoded)
+    }
+}
+
+impl Encode<()> for HostModules<'_> {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        _ctx: &mut (),
+    ) -> Result<(), EncodeError<W::Error>> {
+        match self {
+            HostModules::Slice(modules) => {
+                e.array(array_len(modules.len()))?;
+                for module in *modules {
+                    e.str(module)?;
+                }
+                Ok(())
             },
+            #[cfg(feature = "alloc")]
+            HostModules::Owned(modules) => {
+                e.array(array_len(modules.len()))?;
+                for module in *modules {
+                    e.str(module)?;
+                }
+                Ok(())
+            },
+            HostModules::Encoded(raw) => encode_raw(e, raw),
         }
     }
 }
 
-#[cfg(test)]
+impl<'b> Decode<'b, ()> for HostModules<'b> {
+    fn decode(d: &mut Decoder<'b>, _ctx: &mut ()) -> Result<Self, DecodeError> {
+        decode_array::<&str>(d).map(HostModules::Encoded)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Encode<()> for ClientMessage {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        ctx: &mut (),
+    ) -> Result<(), EncodeError<W::Error>> {
+        self.borrowed().encode(e, ctx)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'b> Decode<'b, ()> for ClientMessage {
+    fn decode(d: &mut Decoder<'b>, ctx: &mut ()) -> Result<Self, DecodeError> {
+        ClientMessageRef::decode(d, ctx).map(Self::from)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Encode<()> for ServerMessage {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        ctx: &mut (),
+    ) -> Result<(), EncodeError<W::Error>> {
+        self.borrowed().encode(e, ctx)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'b> Decode<'b, ()> for ServerMessage {
+    fn decode(d: &mut Decoder<'b>, ctx: &mut ()) -> Result<Self, DecodeError> {
+        ServerMessageRef::decode(d, ctx).map(Self::from)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Encode<()> for Capabilities {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        ctx: &mut (),
+    ) -> Result<(), EncodeError<W::Error>> {
+        self.borrowed().encode(e, ctx)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'b> Decode<'b, ()> for Capabilities {
+    fn decode(d: &mut Decoder<'b>, ctx: &mut ()) -> Result<Self, DecodeError> {
+        CapabilitiesRef::decode(d, ctx).map(Self::from)
+    }
+}
+
+impl Encode<()> for ErrorCode {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        _ctx: &mut (),
+    ) -> Result<(), EncodeError<W::Error>> {
+        let code = match self {
+            ErrorCode::UnknownMessage => ERROR_UNKNOWN_MESSAGE,
+            ErrorCode::MalformedRequest => ERROR_MALFORMED_REQUEST,
+            ErrorCode::Unauthorized => ERROR_UNAUTHORIZED,
+            ErrorCode::ResourceExhausted => ERROR_RESOURCE_EXHAUSTED,
+            ErrorCode::Internal => ERROR_INTERNAL,
+            ErrorCode::Other(code) => *code,
+        };
+        e.u32(code)?;
+        Ok(())
+    }
+}
+
+impl<'b> Decode<'b, ()> for ErrorCode {
+    fn decode(d: &mut Decoder<'b>, _ctx: &mut ()) -> Result<Self, DecodeError> {
+        Ok(match d.u32()? {
+            ERROR_UNKNOWN_MESSAGE => ErrorCode::UnknownMessage,
+            ERROR_MALFORMED_REQUEST => ErrorCode::MalformedRequest,
+            ERROR_UNAUTHORIZED => ErrorCode::Unauthorized,
+            ERROR_RESOURCE_EXHAUSTED => ErrorCode::ResourceExhausted,
+            ERROR_INTERNAL => ErrorCode::Internal,
+            code => ErrorCode::Other(code),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
 mod test {
     use super::*;
+    use crate::{
+        ClientEnvelopeRef, ServerEnvelopeRef, Envelope, MessageId,
+        ResourceLimits, ServerEnvelope, Version,
+    };
+    use alloc::vec::Vec;
     use wasmbed_test_utils::minicbor::assert_encode_decode;
 
     #[test]
@@ -115,8 +710,366 @@ mod test {
         assert_encode_decode(&ClientMessage::Heartbeat);
     }
 
+    #[test]
+    fn test_client_message_application_deployed() {
+        assert_encode_decode(&ClientMessage::ApplicationDeployed {
+            app_id: "app-0".into(),
+        });
+    }
+
+    #[test]
+    fn test_client_message_application_failed() {
+        assert_encode_decode(&ClientMessage::ApplicationFailed {
+            app_id: "app-0".into(),
+            reason: "unreachable executed".into(),
+        });
+    }
+
+    #[test]
+    fn test_client_message_application_stopped() {
+        assert_encode_decode(&ClientMessage::ApplicationStopped {
+            app_id: "app-0".into(),
+        });
+    }
+
     #[test]
     fn test_server_message_heartbeat_ack() {
         assert_encode_decode(&ServerMessage::HeartbeatAck);
     }
+
+    #[test]
+    fn test_server_message_deploy_application() {
+        assert_encode_decode(&ServerMessage::DeployApplication {
+            app_id: "app-0".into(),
+            bytecode: b"\0asm\x01\0\0\0".to_vec(),
+            entry_point: "_start".into(),
+            limits: ResourceLimits {
+                max_memory_pages: Some(1),
+                fuel: None,
+            },
+        });
+    }
+
+    #[test]
+    fn test_server_message_stop_application() {
+        assert_encode_decode(&ServerMessage::StopApplication {
+            app_id: "app-0".into(),
+        });
+    }
+
+    #[test]
+    fn test_client_message_transfer_ready() {
+        assert_encode_decode(&ClientMessage::TransferReady {
+            app_id: "app-0".into(),
+            offset: 1024,
+        });
+    }
+
+    #[test]
+    fn test_client_message_chunk_ack() {
+        assert_encode_decode(&ClientMessage::ChunkAck {
+            app_id: "app-0".into(),
+            offset: 2048,
+        });
+    }
+
+    #[test]
+    fn test_server_message_begin_transfer() {
+        assert_encode_decode(&ServerMessage::BeginTransfer {
+            app_id: "app-0".into(),
+            size: 4096,
+            entry_point: "_start".into(),
+            limits: ResourceLimits::default(),
+        });
+    }
+
+    #[test]
+    fn test_server_message_transfer_chunk() {
+        assert_encode_decode(&ServerMessage::TransferChunk {
+            app_id: "app-0".into(),
+            offset: 1024,
+            data: [0xAB; 16].to_vec(),
+        });
+    }
+
+    #[test]
+    fn test_server_message_commit_transfer() {
+        assert_encode_decode(&ServerMessage::CommitTransfer {
+            app_id: "app-0".into(),
+            digest: [0x42; 32],
+        });
+    }
+
+    #[test]
+    fn test_client_message_enroll() {
+        assert_encode_decode(&ClientMessage::Enroll);
+    }
+
+    #[test]
+    fn test_server_message_enroll_ack() {
+        assert_encode_decode(&ServerMessage::EnrollAck {
+            device: "device-0".into(),
+        });
+    }
+
+    #[test]
+    fn test_client_message_hello() {
+        assert_encode_decode(&ClientMessage::Hello {
+            capabilities: Capabilities {
+                firmware_version: "0.0.1".into(),
+                runtime_version: "wasmi 0.32.3".into(),
+                free_memory: 12 * 1024,
+                host_modules: alloc::vec!["wasmbed".into()],
+                max_message_size: 4096,
+            },
+        });
+    }
+
+    #[test]
+    fn test_server_message_hello_ack() {
+        assert_encode_decode(&ServerMessage::HelloAck);
+    }
+
+    #[test]
+    fn test_client_message_negotiate() {
+        assert_encode_decode(&ClientMessage::Negotiate {
+            versions: alloc::vec![0, 1],
+        });
+    }
+
+    #[test]
+    fn test_server_message_negotiated() {
+        assert_encode_decode(&ServerMessage::Negotiated {
+            version: Version::V0,
+        });
+    }
+
+    #[test]
+    fn test_server_message_version_mismatch() {
+        assert_encode_decode(&ServerMessage::VersionMismatch {
+            supported: alloc::vec![0],
+        });
+    }
+
+    #[test]
+    fn test_client_message_disconnect() {
+        assert_encode_decode(&ClientMessage::Disconnect {
+            reason: "Rebooting".into(),
+        });
+    }
+
+    #[test]
+    fn test_server_message_disconnect_ack() {
+        assert_encode_decode(&ServerMessage::DisconnectAck);
+    }
+
+    #[test]
+    fn test_server_message_go_away() {
+        assert_encode_decode(&ServerMessage::GoAway {
+            reason: "Shutting down".into(),
+            reconnect_after: 5,
+        });
+    }
+
+    #[test]
+    fn test_server_message_error() {
+        assert_encode_decode(&ServerMessage::Error {
+            code: ErrorCode::UnknownMessage,
+            detail: "Unknown message tag 1000".into(),
+            in_reply_to: MessageId::default().next(),
+        });
+        assert_encode_decode(&ServerMessage::Error {
+            code: ErrorCode::Other(1000),
+            detail: "".into(),
+            in_reply_to: MessageId::default(),
+        });
+    }
+
+    #[test]
+    fn test_client_message_error() {
+        for code in [
+            ErrorCode::MalformedRequest,
+            ErrorCode::Unauthorized,
+            ErrorCode::ResourceExhausted,
+            ErrorCode::Internal,
+        ] {
+            assert_encode_decode(&ClientMessage::Error {
+                code,
+                detail: "Out of memory".into(),
+                in_reply_to: MessageId::default(),
+            });
+        }
+    }
+
+    #[test]
+    fn test_unknown_message() {
+        let mut raw = Vec::new();
+        Encoder::new(&mut raw)
+            .array(3)
+            .unwrap()
+            .u32(1000)
+            .unwrap()
+            .str("app-0")
+            .unwrap()
+            .array(2)
+            .unwrap()
+            .u8(1)
+            .unwrap()
+            .bytes(&[0x42; 4])
+            .unwrap();
+
+        let message = minicbor::decode::<ClientMessage>(&raw).unwrap();
+        assert_eq!(
+            message,
+            ClientMessage::Unknown {
+                tag: 1000,
+                raw: raw.clone()
+            }
+        );
+        assert_encode_decode(&message);
+        assert!(matches!(
+            minicbor::decode::<ServerMessage>(&raw),
+            Ok(ServerMessage::Unknown { tag: 1000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_digest_length() {
+        let mut buf = alloc::vec::Vec::new();
+        Encoder::new(&mut buf)
+            .array(3)
+            .unwrap()
+            .u32(SERVER_COMMIT_TRANSFER)
+            .unwrap()
+            .str("app-0")
+            .unwrap()
+            .bytes(&[0x42; 16])
+            .unwrap();
+        assert!(minicbor::decode::<ServerMessage>(&buf).is_err());
+    }
+
+    #[test]
+    fn test_empty_array() {
+        // The item following the empty array must not be read as its tag.
+        let mut buf = alloc::vec::Vec::new();
+        Encoder::new(&mut buf)
+            .array(0)
+            .unwrap()
+            .u32(CLIENT_HEARTBEAT)
+            .unwrap();
+        assert!(minicbor::decode::<ClientMessage>(&buf).is_err());
+        assert!(minicbor::decode::<ServerMessage>(&buf).is_err());
+        assert!(minicbor::decode::<ClientMessageRef<'_>>(&buf).is_err());
+    }
+
+    #[test]
+    fn test_unexpected_array_length() {
+        let mut buf = alloc::vec::Vec::new();
+        Encoder::new(&mut buf)
+            .array(2)
+            .unwrap()
+            .u32(CLIENT_HEARTBEAT)
+            .unwrap()
+            .u32(0)
+            .unwrap();
+        assert!(minicbor::decode::<ClientMessage>(&buf).is_err());
+    }
+
+    #[test]
+    fn test_borrowed_server_message() {
+        let message = ServerMessage::DeployApplication {
+            app_id: "app-0".into(),
+            bytecode: alloc::vec![0x00, 0x61, 0x73, 0x6d],
+            entry_point: "main".into(),
+            limits: ResourceLimits::default(),
+        };
+        let buf = minicbor::to_vec(&message).unwrap();
+
+        let borrowed = minicbor::decode::<ServerMessageRef>(&buf).unwrap();
+        assert_eq!(borrowed, message.borrowed());
+        assert_eq!(minicbor::to_vec(&borrowed).unwrap(), buf);
+        assert_eq!(ServerMessage::from(borrowed), message);
+    }
+
+    #[test]
+    fn test_borrowed_client_envelope() {
+        let capabilities = Capabilities {
+            firmware_version: "0.0.1".into(),
+            runtime_version: "wasmi 0.32.3".into(),
+            free_memory: 12 * 1024,
+            host_modules: alloc::vec!["wasmbed".into(), "env".into()],
+            max_message_size: 4096,
+        };
+        let envelope = Envelope {
+            version: Version::V0,
+            message_id: MessageId::default(),
+            message: ClientMessage::Hello { capabilities },
+        };
+        let buf = minicbor::to_vec(&envelope).unwrap();
+
+        let borrowed = minicbor::decode::<ClientEnvelopeRef>(&buf).unwrap();
+        let ClientMessageRef::Hello { capabilities } = &borrowed.message else {
+            panic!("Unexpected message {:?}", borrowed.message);
+        };
+        assert!(capabilities.host_modules.iter().eq(["wasmbed", "env"]));
+        assert_eq!(
+            capabilities.host_modules,
+            HostModules::Slice(&["wasmbed", "env"])
+        );
+        assert_eq!(minicbor::to_vec(&borrowed).unwrap(), buf);
+    }
+
+    #[test]
+    fn test_borrowed_versions() {
+        let message = ClientMessageRef::Negotiate {
+            versions: Versions::Slice(&[0, 1]),
+        };
+        let buf = minicbor::to_vec(&message).unwrap();
+        assert_eq!(
+            minicbor::decode::<ClientMessage>(&buf).unwrap(),
+            ClientMessage::Negotiate {
+                versions: alloc::vec![0, 1]
+            }
+        );
+        assert_eq!(
+            minicbor::decode::<ClientMessageRef>(&buf).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn test_capabilities_extra_fields() {
+        let mut buf = Vec::new();
+        Encoder::new(&mut buf)
+            .array(6)
+            .unwrap()
+            .str("0.0.1")
+            .unwrap()
+            .str("wasmi 0.32.3")
+            .unwrap()
+            .u32(1024)
+            .unwrap()
+            .array(0)
+            .unwrap()
+            .u32(4096)
+            .unwrap()
+            .bool(true)
+            .unwrap();
+        let capabilities = minicbor::decode::<Capabilities>(&buf).unwrap();
+        assert_eq!(capabilities.max_message_size, 4096);
+    }
+
+    #[test]
+    fn test_borrowed_envelope_type() {
+        let envelope = ServerEnvelope {
+            version: Version::V0,
+            message_id: MessageId::default(),
+            message: ServerMessage::HeartbeatAck,
+        };
+        let buf = minicbor::to_vec(&envelope).unwrap();
+        assert_eq!(
+            minicbor::decode::<ServerEnvelopeRef>(&buf).unwrap().message,
+            ServerMessageRef::HeartbeatAck
+        );
+    }
 }
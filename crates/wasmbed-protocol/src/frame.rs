@@ -0,0 +1,147 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Framing of envelopes on a stream, shared by clients and servers. Frames
+//! are made of the length of the encoded envelope, as a big endian `u32`,
+//! followed by the envelope.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use derive_more::{Display, Error};
+use minicbor::Encode;
+use minicbor::encode::write::Cursor;
+
+use crate::Len;
+
+/// Size of the length prefix of a frame.
+pub const LENGTH_SIZE: usize = 4;
+
+/// A frame could not be encoded or decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Error)]
+pub enum Error {
+    #[display("Frame of {size} bytes exceeds the limit of {limit} bytes")]
+    TooLarge {
+        size: usize,
+        limit: usize,
+    },
+    #[display("Buffer too small for the frame")]
+    BufferTooSmall,
+}
+
+/// Decodes the length prefix of a frame, returning the size of the envelope
+/// following it, if it is at most `limit` bytes.
+pub fn decode_header(
+    header: [u8; LENGTH_SIZE],
+    limit: usize,
+) -> Result<usize, Error> {
+    let size =
+        usize::try_from(u32::from_be_bytes(header)).unwrap_or(usize::MAX);
+    if size > limit {
+        return Err(Error::TooLarge { size, limit });
+    }
+    Ok(size)
+}
+
+/// Size of `envelope` once encoded, without the length prefix.
+pub fn size<T: Encode<()>>(envelope: &T) -> usize {
+    let mut len = Len(0);
+    let _ = minicbor::encode(envelope, &mut len);
+    len.0
+}
+
+/// Encodes `envelope` as a frame in `buf`, returning the frame. Envelopes
+/// larger than `limit` bytes are rejected, the peer being unable to read
+/// them.
+pub fn encode<'b, T: Encode<()>>(
+    envelope: &T,
+    buf: &'b mut [u8],
+    limit: usize,
+) -> Result<&'b [u8], Error> {
+    let (header, body) = buf
+        .split_at_mut_checked(LENGTH_SIZE)
+        .ok_or(Error::BufferTooSmall)?;
+    let mut cursor = Cursor::new(body);
+    minicbor::encode(envelope, &mut cursor)
+        .map_err(|_| Error::BufferTooSmall)?;
+    let size = cursor.position();
+    header.copy_from_slice(&encode_header(size, limit)?);
+
+    LENGTH_SIZE
+        .checked_add(size)
+        .and_then(|end| buf.get(..end))
+        .ok_or(Error::BufferTooSmall)
+}
+
+#[cfg(feature = "alloc")]
+/// Encodes `envelope` as a frame in a new vector, rejecting envelopes
+/// larger than `limit` bytes like [`encode`].
+pub fn to_vec<T: Encode<()>>(
+    envelope: &T,
+    limit: usize,
+) -> Result<Vec<u8>, Error> {
+    let mut frame = Vec::from([0; LENGTH_SIZE]);
+    minicbor::encode(envelope, &mut frame)
+        .map_err(|_| Error::BufferTooSmall)?;
+    let size = frame.len().saturating_sub(LENGTH_SIZE);
+    let header = encode_header(size, limit)?;
+    frame
+        .get_mut(..LENGTH_SIZE)
+        .ok_or(Error::BufferTooSmall)?
+        .copy_from_slice(&header);
+    Ok(frame)
+}
+
+fn encode_header(
+    size: usize,
+    limit: usize,
+) -> Result<[u8; LENGTH_SIZE], Error> {
+    u32::try_from(size)
+        .ok()
+        .filter(|_| size <= limit)
+        .map(u32::to_be_bytes)
+        .ok_or(Error::TooLarge { size, limit })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Envelope, MessageId, ServerMessageRef, Version};
+
+    #[test]
+    fn test_frame() {
+        let envelope = Envelope {
+            version: Version::V0,
+            message_id: MessageId::default(),
+            message: ServerMessageRef::StopApplication { app_id: "app-0" },
+        };
+        let mut buf = [0; 64];
+        let frame = encode(&envelope, &mut buf, 64).unwrap();
+        let (header, body) = frame.split_first_chunk().unwrap();
+        assert_eq!(decode_header(*header, body.len()), Ok(body.len()));
+        assert_eq!(
+            decode_header(*header, body.len() - 1),
+            Err(Error::TooLarge {
+                size: body.len(),
+                limit: body.len() - 1,
+            })
+        );
+        #[cfg(feature = "alloc")]
+        assert_eq!(to_vec(&envelope, 64).unwrap(), frame);
+        assert_eq!(size(&envelope), body.len());
+
+        let too_large = Error::TooLarge {
+            size: body.len(),
+            limit: 8,
+        };
+        assert_eq!(encode(&envelope, &mut [0; 64], 8).err(), Some(too_large));
+        #[cfg(feature = "alloc")]
+        assert_eq!(to_vec(&envelope, 8).err(), Some(too_large));
+
+        let mut short = [0; 8];
+        assert_eq!(
+            encode(&envelope, &mut short, 64),
+            Err(Error::BufferTooSmall)
+        );
+    }
+}
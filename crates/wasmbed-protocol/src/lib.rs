@@ -3,10 +3,82 @@
 
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+mod borrowed;
 mod cbor;
+pub mod frame;
 
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use derive_more::Display;
+#[cfg(feature = "alloc")]
+use derive_more::Error;
 use minicbor::{Decode, Encode};
 
+pub use borrowed::{
+    CapabilitiesRef, ClientEnvelopeRef, ClientMessageRef, EncodedIter,
+    HostModules, HostModulesIter, ServerEnvelopeRef, ServerMessageRef,
+    Versions, VersionsIter,
+};
+
+/// Largest encoded frame exchanged by clients and servers, in bytes. Devices
+/// can decode any message into a receive buffer of this size.
+pub const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// Smallest `max_message_size` a device may advertise in its `Hello`, in
+/// bytes, leaving room for the acknowledgments and errors of the server.
+pub const MIN_MESSAGE_SIZE: usize = 256;
+
+/// Size of `message` once encoded in an envelope, in bytes, whatever the
+/// identifier of the envelope.
+pub fn envelope_len<T: Encode<()>>(message: &T) -> usize {
+    let envelope = Envelope {
+        version: Version::V0,
+        message_id: MessageId(u32::MAX),
+        message,
+    };
+    let mut len = Len(0);
+    let _ = minicbor::encode(&envelope, &mut len);
+    len.0
+}
+
+/// Number of module bytes a `TransferChunk` of `app_id` can carry in a
+/// message of at most `max_message_size` bytes, `None` if it cannot carry
+/// any.
+pub fn chunk_capacity(app_id: &str, max_message_size: usize) -> Option<usize> {
+    let empty = envelope_len(&ServerMessageRef::TransferChunk {
+        app_id,
+        offset: u32::MAX,
+        data: &[],
+    });
+    let available = max_message_size.checked_sub(empty)?;
+    // The header encoding the length of the data grows with it, by the
+    // number of bytes given for each range of lengths.
+    [(0, 23), (1, 0xff), (2, 0xffff), (4, usize::MAX)]
+        .into_iter()
+        .filter_map(|(header, max)| {
+            available.checked_sub(header).map(|len| len.min(max))
+        })
+        .max()
+        .filter(|len| *len > 0)
+}
+
+/// Writer counting the bytes written to it.
+struct Len(usize);
+
+impl minicbor::encode::Write for Len {
+    type Error = core::convert::Infallible;
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.0 = self.0.saturating_add(buf.len());
+        Ok(())
+    }
+}
+
 /// A protocol message wrapper that provides versioning and correlation tracking.
 #[derive(Debug, Clone, PartialEq, Decode, Encode)]
 pub struct Envelope<T> {
@@ -21,22 +93,81 @@ pub struct Envelope<T> {
     pub message: T,
 }
 
+#[cfg(feature = "alloc")]
 /// Type alias for envelopes containing client-originated messages
 pub type ClientEnvelope = Envelope<ClientMessage>;
 
+#[cfg(feature = "alloc")]
 /// Type alias for envelopes containing server-originated messages
 pub type ServerEnvelope = Envelope<ServerMessage>;
 
 /// Protocol version.
-#[derive(Debug, Clone, Copy, PartialEq, Decode, Encode)]
+///
+/// Connections use `V0` until the client negotiates another version with
+/// `Negotiate`. The envelope layout is the same in every version, so that
+/// the negotiation itself can be exchanged by any client and server.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Decode, Encode,
+)]
 #[cbor(index_only)]
 pub enum Version {
     #[cbor(n(0))]
     V0,
 }
 
+impl Version {
+    /// Versions supported by this implementation, oldest first
+    pub const SUPPORTED: &'static [Version] = &[Version::V0];
+
+    /// Numbers of the [supported](Self::SUPPORTED) versions, e.g. to offer
+    /// them without allocating.
+    pub const SUPPORTED_NUMBERS: &'static [u32] = &[Version::V0.number()];
+
+    /// Number identifying the version on the wire.
+    pub const fn number(self) -> u32 {
+        match self {
+            Version::V0 => 0,
+        }
+    }
+
+    pub fn from_number(number: u32) -> Option<Self> {
+        Self::SUPPORTED
+            .iter()
+            .copied()
+            .find(|v| v.number() == number)
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Picks the most recent supported version among the version numbers
+    /// `offered` by the peer.
+    pub fn negotiate(offered: &[u32]) -> Result<Self, NoCommonVersion> {
+        offered
+            .iter()
+            .filter_map(|number| Self::from_number(*number))
+            .max()
+            .ok_or_else(|| NoCommonVersion {
+                offered: offered.to_vec(),
+            })
+    }
+
+    #[cfg(feature = "alloc")]
+    /// Numbers of the [supported](Self::SUPPORTED) versions.
+    pub fn supported_numbers() -> Vec<u32> {
+        Self::SUPPORTED_NUMBERS.to_vec()
+    }
+}
+
+#[cfg(feature = "alloc")]
+/// None of the protocol versions offered by the peer is supported.
+#[derive(Debug, Clone, PartialEq, Display, Error)]
+#[display("No common protocol version, offered {offered:?}")]
+pub struct NoCommonVersion {
+    #[error(not(source))]
+    pub offered: Vec<u32>,
+}
+
 /// Unique identifier for correlating requests with responses
-#[derive(Debug, Default, Clone, Copy, PartialEq, Decode, Encode)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Decode, Encode)]
 #[cbor(transparent)]
 pub struct MessageId(u32);
 
@@ -46,16 +177,263 @@ impl MessageId {
     }
 }
 
+#[cfg(feature = "alloc")]
+/// Identifier of an application deployed to a device
+pub type ApplicationId = String;
+
+/// SHA-256 digest of a Wasm module
+pub type Digest = [u8; 32];
+
+#[cfg(feature = "alloc")]
 /// Messages sent from client to server
 #[derive(Debug, Clone, PartialEq)]
 pub enum ClientMessage {
     /// Periodic heartbeat to maintain connection liveness
     Heartbeat,
+    /// The application has been instantiated and its entry point started
+    ApplicationDeployed {
+        app_id: ApplicationId,
+    },
+    /// The application could not be deployed or stopped running
+    ApplicationFailed {
+        app_id: ApplicationId,
+        reason: String,
+    },
+    /// The application has been stopped and its resources released
+    ApplicationStopped {
+        app_id: ApplicationId,
+    },
+    /// The client is ready to receive the chunks of a module transfer,
+    /// starting at `offset`. A non-zero offset resumes an interrupted
+    /// transfer of the same module.
+    TransferReady {
+        app_id: ApplicationId,
+        offset: u32,
+    },
+    /// Every byte of the module before `offset` has been received
+    ChunkAck {
+        app_id: ApplicationId,
+        offset: u32,
+    },
+    /// Request to enroll the client, sent by a device whose public key is
+    /// not yet registered while pairing is enabled. The server replies with
+    /// `EnrollAck`, after which the client reconnects as a registered device.
+    Enroll,
+    /// First message sent by the client once connected, describing what it
+    /// is able to run. The server replies with `HelloAck`.
+    Hello {
+        capabilities: Capabilities,
+    },
+    /// Offers the protocol versions the client supports, before any other
+    /// message. The server replies with `Negotiated` and uses the version it
+    /// picked from then on, or with `VersionMismatch` and closes the
+    /// connection. Clients that do not negotiate use `Version::V0`.
+    Negotiate {
+        versions: Vec<u32>,
+    },
+    /// The client is about to close the connection. The server replies
+    /// with `DisconnectAck` and closes it.
+    Disconnect {
+        reason: String,
+    },
+    /// The server message `in_reply_to` could not be processed
+    Error {
+        code: ErrorCode,
+        /// Human readable description of the problem
+        detail: String,
+        in_reply_to: MessageId,
+    },
+    /// A message with a tag this implementation does not know, e.g. one
+    /// added by a later version of the protocol. `raw` holds the whole
+    /// encoded message, which is encoded back unchanged.
+    Unknown {
+        tag: u32,
+        raw: Vec<u8>,
+    },
 }
 
+#[cfg(feature = "alloc")]
 /// Messages sent from server to client
 #[derive(Debug, Clone, PartialEq)]
 pub enum ServerMessage {
     /// Acknowledgment of a client heartbeat
     HeartbeatAck,
+    /// Request to deploy and start a Wasm module
+    DeployApplication {
+        app_id: ApplicationId,
+        /// Wasm module bytecode
+        bytecode: Vec<u8>,
+        /// Name of the exported function to invoke
+        entry_point: String,
+        /// Resources the application is allowed to consume
+        limits: ResourceLimits,
+    },
+    /// Request to stop a running application
+    StopApplication {
+        app_id: ApplicationId,
+    },
+    /// Starts a chunked transfer of a Wasm module too large to be sent in a
+    /// single `DeployApplication` message. The client replies with
+    /// `TransferReady`.
+    BeginTransfer {
+        app_id: ApplicationId,
+        /// Total size of the module in bytes
+        size: u32,
+        /// Name of the exported function to invoke
+        entry_point: String,
+        /// Resources the application is allowed to consume
+        limits: ResourceLimits,
+    },
+    /// A slice of the module starting at `offset`. The client replies with
+    /// `ChunkAck`.
+    TransferChunk {
+        app_id: ApplicationId,
+        offset: u32,
+        data: Vec<u8>,
+    },
+    /// Completes a transfer. The client verifies the digest of the received
+    /// module and deploys it, replying with `ApplicationDeployed` or
+    /// `ApplicationFailed`.
+    CommitTransfer {
+        app_id: ApplicationId,
+        digest: Digest,
+    },
+    /// The client has been enrolled and registered under the given name
+    EnrollAck {
+        device: String,
+    },
+    /// Acknowledgment of a client `Hello`
+    HelloAck,
+    /// The version used by both sides for the rest of the connection
+    Negotiated {
+        version: Version,
+    },
+    /// None of the versions offered in `Negotiate` is supported. Lists the
+    /// versions the server supports.
+    VersionMismatch {
+        supported: Vec<u32>,
+    },
+    /// Acknowledgment of a client `Disconnect`, the last message sent on the
+    /// connection
+    DisconnectAck,
+    /// The server is going away, e.g. because it is shutting down. The
+    /// client should disconnect and connect again, preferably to another
+    /// server, after waiting `reconnect_after` seconds.
+    GoAway {
+        reason: String,
+        reconnect_after: u32,
+    },
+    /// The client message `in_reply_to` could not be processed
+    Error {
+        code: ErrorCode,
+        /// Human readable description of the problem
+        detail: String,
+        in_reply_to: MessageId,
+    },
+    /// A message with a tag this implementation does not know, e.g. one
+    /// added by a later version of the protocol. `raw` holds the whole
+    /// encoded message, which is encoded back unchanged.
+    Unknown {
+        tag: u32,
+        raw: Vec<u8>,
+    },
+}
+
+/// Reason a message could not be processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum ErrorCode {
+    /// The tag of the message is not known to the receiver
+    #[display("Unknown message")]
+    UnknownMessage,
+    /// The message is known but its content is invalid
+    #[display("Malformed request")]
+    MalformedRequest,
+    /// The sender is not allowed to make the request
+    #[display("Unauthorized")]
+    Unauthorized,
+    /// The receiver lacks the memory, storage or other resources the request
+    /// needs
+    #[display("Resource exhausted")]
+    ResourceExhausted,
+    /// The request failed for a reason unrelated to its content
+    #[display("Internal error")]
+    Internal,
+    /// A code not known to this implementation
+    #[display("Error {_0}")]
+    Other(u32),
+}
+
+#[cfg(feature = "alloc")]
+/// What a device is able to run, advertised in its `Hello`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Capabilities {
+    /// Version of the device firmware
+    pub firmware_version: String,
+    /// Name and version of the Wasm runtime, e.g. `wasmi 0.32.3`
+    pub runtime_version: String,
+    /// Memory available to applications, in bytes
+    pub free_memory: u32,
+    /// Modules from which applications can import host functions
+    pub host_modules: Vec<String>,
+    /// Largest encoded message the device accepts, in bytes, at least
+    /// [`MIN_MESSAGE_SIZE`]
+    pub max_message_size: u32,
+}
+
+/// Limits enforced by the device runtime on a deployed application
+#[derive(Debug, Default, Clone, Copy, PartialEq, Decode, Encode)]
+pub struct ResourceLimits {
+    /// Maximum number of 64 KiB linear memory pages
+    #[cbor(n(0))]
+    pub max_memory_pages: Option<u32>,
+    /// Amount of fuel the entry point may consume
+    #[cbor(n(1))]
+    pub fuel: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_negotiate_version() {
+        assert_eq!(Version::negotiate(&[0, 7]), Ok(Version::V0));
+        assert_eq!(
+            Version::negotiate(&[7]),
+            Err(NoCommonVersion {
+                offered: alloc::vec![7]
+            })
+        );
+    }
+
+    #[test]
+    fn test_supported_numbers() {
+        assert!(
+            Version::SUPPORTED
+                .iter()
+                .map(|v| v.number())
+                .eq(Version::SUPPORTED_NUMBERS.iter().copied())
+        );
+    }
+
+    #[test]
+    fn test_chunk_capacity() {
+        let data = [0; 1024];
+        for max_message_size in [16, 40, 41, 64, 300, 301, 302, 1000] {
+            let Some(len) = chunk_capacity("app-0", max_message_size) else {
+                assert!(max_message_size < 20);
+                continue;
+            };
+            let chunk = |len| {
+                envelope_len(&ServerMessageRef::TransferChunk {
+                    app_id: "app-0",
+                    offset: u32::MAX,
+                    data: data.get(..len).unwrap(),
+                })
+            };
+            assert!(chunk(len) <= max_message_size);
+            assert!(chunk(len + 1) > max_message_size);
+        }
+    }
 }
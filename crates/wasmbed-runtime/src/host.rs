@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Host functions of the `wasmbed` module, see `wasmbed_wasm::HOST_ABI`.
+//!
+//! Host functions do not act on the device themselves: they suspend the
+//! application with the [`Call`] it made, which the runtime carries out
+//! before resuming the application with its results.
+
+use alloc::string::String;
+
+use derive_more::Display;
+use wasmi::core::HostError;
+use wasmi::errors::LinkerError;
+use wasmi::{Caller, Extern, Linker, ResourceLimiter, StoreLimits};
+
+/// Module from which applications import the host functions.
+const HOST_MODULE: &str = "wasmbed";
+
+/// State of the store of an application.
+pub struct Host {
+    limits: StoreLimits,
+}
+
+impl Host {
+    pub fn new(limits: StoreLimits) -> Self {
+        Self { limits }
+    }
+
+    pub fn limits(&mut self) -> &mut dyn ResourceLimiter {
+        &mut self.limits
+    }
+}
+
+/// Host function called by an application.
+#[derive(Debug, Display)]
+pub enum Call {
+    #[display("log")]
+    Log(String),
+    /// Resumed with the uptime of the device
+    #[display("uptime_ms")]
+    UptimeMs,
+    #[display("sleep_ms")]
+    SleepMs(u32),
+}
+
+impl HostError for Call {}
+
+/// Defines the host functions in `linker`.
+pub fn define(linker: &mut Linker<Host>) -> Result<(), LinkerError> {
+    linker.func_wrap(HOST_MODULE, "log", log)?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "uptime_ms",
+        |_: Caller<'_, Host>| -> Result<i64, wasmi::Error> {
+            Err(wasmi::Error::host(Call::UptimeMs))
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "sleep_ms",
+        |_: Caller<'_, Host>, duration: i32| -> Result<(), wasmi::Error> {
+            // Negative durations do not suspend the application.
+            let duration = u32::try_from(duration).unwrap_or(0);
+            Err(wasmi::Error::host(Call::SleepMs(duration)))
+        },
+    )?;
+    Ok(())
+}
+
+/// Logs the UTF-8 string of `len` bytes at `ptr` in the memory exported by
+/// the application.
+fn log(
+    caller: Caller<'_, Host>,
+    ptr: i32,
+    len: i32,
+) -> Result<(), wasmi::Error> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmi::Error::new("No memory exported"))?;
+    let data = memory.data(&caller);
+
+    let start = usize::try_from(ptr).ok();
+    let end = start
+        .zip(usize::try_from(len).ok())
+        .and_then(|(start, len)| start.checked_add(len));
+    let message = start
+        .zip(end)
+        .and_then(|(start, end)| data.get(start..end))
+        .ok_or_else(|| wasmi::Error::new("Message out of bounds"))?;
+    let message = core::str::from_utf8(message)
+        .map_err(|_| wasmi::Error::new("Message is not valid UTF-8"))?;
+
+    Err(wasmi::Error::host(Call::Log(message.into())))
+}
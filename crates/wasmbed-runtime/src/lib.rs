@@ -0,0 +1,695 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Runtime executing the applications deployed to a device, interpreting
+//! their Wasm modules with wasmi.
+//!
+//! The runtime needs an allocator and far more memory than the 16 KiB of
+//! RAM of the HiFive1, whose firmware does not run applications. The
+//! gateway test client uses it to simulate a device.
+
+#![no_std]
+
+extern crate alloc;
+
+mod host;
+mod transfer;
+
+use alloc::string::{String, ToString};
+
+use derive_more::Display;
+use wasmbed_protocol::{
+    ApplicationId, ClientMessage, ClientMessageRef, Digest, ResourceLimits,
+    ServerMessageRef,
+};
+use wasmbed_protocol_client::Handler;
+use wasmi::{
+    Config, Engine, Linker, Module, Store, StoreLimitsBuilder, TypedFunc,
+    TypedResumableCall, TypedResumableInvocation, Val,
+};
+
+use host::{Call, Host};
+use transfer::Transfer;
+
+/// Version of the runtime, advertised in the device capabilities.
+pub const RUNTIME_VERSION: &str = "wasmi 0.32.3";
+
+/// Size of a Wasm linear memory page.
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// Fuel given to applications deployed without a fuel limit.
+pub const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// Services of the device available to applications through the
+/// `wasmbed` host module.
+pub trait Platform {
+    /// Writes a message of the application to the device log.
+    fn log(&mut self, message: &str);
+
+    /// Milliseconds elapsed since the device booted.
+    fn uptime_ms(&self) -> u64;
+}
+
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display("Invalid module: {_0}")]
+    Compile(wasmi::Error),
+    #[display("Unable to instantiate the module: {_0}")]
+    Instantiate(wasmi::Error),
+    #[display("Entry point {_0} not found")]
+    EntryPoint(String),
+    #[display("Application {_0} is already running")]
+    Busy(ApplicationId),
+    #[display("Trap: {_0}")]
+    Trap(wasmi::Error),
+}
+
+impl core::error::Error for Error {}
+
+/// Interpreter running one application at a time.
+///
+/// Deployed applications do not run while their deployment is handled:
+/// the device calls [`step`](Runtime::step) whenever the application is
+/// due to run, which runs it until its next call to a host function. This
+/// leaves the device free to exchange messages with the gateway between
+/// two steps, e.g. to stop the application. An application computing
+/// without calling the host runs within a single step, bounded by its
+/// fuel: wasmi cannot resume a call once out of fuel.
+///
+/// Each application gets its own store, released once it stops, so that
+/// nothing is left behind by a failed application.
+pub struct Runtime<P> {
+    engine: Engine,
+    platform: P,
+    /// Memory available to applications, in bytes
+    memory: usize,
+    application: Option<Application>,
+    transfer: Option<Transfer>,
+    /// Last reply to the server, borrowed by [`Handler::handle`]
+    reply: Option<ClientMessage>,
+}
+
+/// Application deployed on a [`Runtime`].
+struct Application {
+    id: ApplicationId,
+    store: Store<Host>,
+    entry: TypedFunc<(), ()>,
+    /// Call suspended at a host function, `None` until the entry point is
+    /// called
+    invocation: Option<TypedResumableInvocation<()>>,
+    /// Uptime before which the application does not run
+    wake_at: u64,
+}
+
+impl<P: Platform> Runtime<P> {
+    /// Creates a runtime giving applications at most `memory` bytes, for
+    /// their linear memory and for the modules transferred in chunks.
+    pub fn new(platform: P, memory: usize) -> Self {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Self {
+            engine: Engine::new(&config),
+            platform,
+            memory,
+            application: None,
+            transfer: None,
+            reply: None,
+        }
+    }
+
+    pub fn platform(&self) -> &P {
+        &self.platform
+    }
+
+    /// Memory available to applications, in bytes.
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Uptime from which [`step`](Self::step) runs the application, `None`
+    /// if no application is deployed.
+    pub fn wake_at(&self) -> Option<u64> {
+        self.application
+            .as_ref()
+            .map(|application| application.wake_at)
+    }
+
+    /// Runs the application until it calls a host function, if it is due
+    /// to run. Returns the message to send to the server once the
+    /// application has stopped: `ApplicationStopped` when its entry point
+    /// returns, or `ApplicationFailed` with the trap.
+    pub fn step(&mut self) -> Option<ClientMessage> {
+        let now = self.platform.uptime_ms();
+        let application = self
+            .application
+            .as_mut()
+            .filter(|application| application.wake_at <= now)?;
+
+        let call = match application.invocation.take() {
+            None => {
+                application.entry.call_resumable(&mut application.store, ())
+            },
+            Some(invocation) => {
+                let uptime = Val::I64(i64::try_from(now).unwrap_or(i64::MAX));
+                let results = match invocation.host_error().downcast_ref() {
+                    Some(Call::UptimeMs) => core::slice::from_ref(&uptime),
+                    _ => &[],
+                };
+                invocation.resume(&mut application.store, results)
+            },
+        };
+
+        let failure = match call {
+            Ok(TypedResumableCall::Finished(())) => None,
+            Ok(TypedResumableCall::Resumable(invocation)) => {
+                match invocation.host_error().downcast_ref() {
+                    Some(Call::Log(message)) => {
+                        self.platform.log(message);
+                    },
+                    Some(Call::SleepMs(duration)) => {
+                        application.wake_at =
+                            now.saturating_add(u64::from(*duration));
+                    },
+                    Some(Call::UptimeMs) => {},
+                    // Errors of the host functions, e.g. a message out of
+                    // the memory of the application.
+                    None => {
+                        let reason = invocation.host_error().to_string();
+                        return self.stopped(Some(reason));
+                    },
+                }
+                application.invocation = Some(invocation);
+                return None;
+            },
+            Err(e) => Some(Error::Trap(e).to_string()),
+        };
+        self.stopped(failure)
+    }
+
+    /// Releases the application, which stopped with `failure` if any.
+    fn stopped(&mut self, failure: Option<String>) -> Option<ClientMessage> {
+        let app_id = self.application.take()?.id;
+        Some(match failure {
+            Some(reason) => ClientMessage::ApplicationFailed { app_id, reason },
+            None => ClientMessage::ApplicationStopped { app_id },
+        })
+    }
+
+    /// Instantiates `bytecode`, whose `entry_point` is a function without
+    /// parameters or results called by the next [`step`](Self::step),
+    /// within `limits`. A new deployment of the running application
+    /// restarts it.
+    fn start(
+        &mut self,
+        app_id: &str,
+        bytecode: &[u8],
+        entry_point: &str,
+        limits: &ResourceLimits,
+    ) -> Result<(), Error> {
+        if let Some(application) = &self.application
+            && application.id != app_id
+        {
+            return Err(Error::Busy(application.id.clone()));
+        }
+
+        let module =
+            Module::new(&self.engine, bytecode).map_err(Error::Compile)?;
+
+        let memory = limits.max_memory_pages.map_or(self.memory, |pages| {
+            usize::try_from(pages)
+                .unwrap_or(usize::MAX)
+                .saturating_mul(PAGE_SIZE)
+                .min(self.memory)
+        });
+        let store_limits = StoreLimitsBuilder::new()
+            .instances(1)
+            .memory_size(memory)
+            .build();
+        let mut store = Store::new(&self.engine, Host::new(store_limits));
+        store.limiter(|host| host.limits());
+        store
+            .set_fuel(limits.fuel.unwrap_or(DEFAULT_FUEL))
+            .map_err(|e| Error::Instantiate(e.into()))?;
+
+        let mut linker = Linker::new(&self.engine);
+        host::define(&mut linker).map_err(|e| Error::Instantiate(e.into()))?;
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(Error::Instantiate)?;
+        let entry = instance
+            .get_typed_func::<(), ()>(&store, entry_point)
+            .map_err(|_| Error::EntryPoint(entry_point.into()))?;
+
+        self.application = Some(Application {
+            id: app_id.into(),
+            store,
+            entry,
+            invocation: None,
+            wake_at: self.platform.uptime_ms(),
+        });
+        Ok(())
+    }
+
+    /// Handles a message of the server, returning the reply.
+    fn on_message(
+        &mut self,
+        message: ServerMessageRef<'_>,
+    ) -> Option<ClientMessage> {
+        match message {
+            ServerMessageRef::DeployApplication {
+                app_id,
+                bytecode,
+                entry_point,
+                limits,
+            } => Some(self.deploy(app_id, bytecode, entry_point, &limits)),
+            ServerMessageRef::BeginTransfer {
+                app_id,
+                size,
+                entry_point,
+                limits,
+            } => Some(self.begin_transfer(app_id, size, entry_point, limits)),
+            ServerMessageRef::TransferChunk {
+                app_id,
+                offset,
+                data,
+            } => Some(self.transfer_chunk(app_id, offset, data)),
+            ServerMessageRef::CommitTransfer { app_id, digest } => {
+                Some(self.commit_transfer(app_id, &digest))
+            },
+            ServerMessageRef::StopApplication { app_id } => {
+                Some(self.stop(app_id))
+            },
+            _ => None,
+        }
+    }
+
+    /// Starts the application, replying with `ApplicationDeployed` once it
+    /// is ready to run.
+    fn deploy(
+        &mut self,
+        app_id: &str,
+        bytecode: &[u8],
+        entry_point: &str,
+        limits: &ResourceLimits,
+    ) -> ClientMessage {
+        match self.start(app_id, bytecode, entry_point, limits) {
+            Ok(()) => ClientMessage::ApplicationDeployed {
+                app_id: app_id.into(),
+            },
+            Err(e) => failed(app_id, e),
+        }
+    }
+
+    fn begin_transfer(
+        &mut self,
+        app_id: &str,
+        size: u32,
+        entry_point: &str,
+        limits: ResourceLimits,
+    ) -> ClientMessage {
+        let resumed = self
+            .transfer
+            .as_ref()
+            .filter(|transfer| transfer.is(app_id, size, entry_point, &limits));
+        if let Some(transfer) = resumed {
+            return ClientMessage::TransferReady {
+                app_id: app_id.into(),
+                offset: transfer.offset(),
+            };
+        }
+
+        // Only one module is kept in memory.
+        self.transfer = None;
+        match Transfer::new(app_id, size, entry_point, limits, self.memory) {
+            Ok(transfer) => {
+                self.transfer = Some(transfer);
+                ClientMessage::TransferReady {
+                    app_id: app_id.into(),
+                    offset: 0,
+                }
+            },
+            Err(e) => failed(app_id, e),
+        }
+    }
+
+    fn transfer_chunk(
+        &mut self,
+        app_id: &str,
+        offset: u32,
+        data: &[u8],
+    ) -> ClientMessage {
+        let Some(transfer) = self
+            .transfer
+            .as_mut()
+            .filter(|transfer| transfer.app_id == app_id)
+        else {
+            return failed(app_id, transfer::TransferError::NotInProgress);
+        };
+        match transfer.append(offset, data) {
+            Ok(offset) => ClientMessage::ChunkAck {
+                app_id: app_id.into(),
+                offset,
+            },
+            Err(e) => {
+                self.transfer = None;
+                failed(app_id, e)
+            },
+        }
+    }
+
+    fn commit_transfer(
+        &mut self,
+        app_id: &str,
+        digest: &Digest,
+    ) -> ClientMessage {
+        let Some(transfer) =
+            self.transfer.take_if(|transfer| transfer.app_id == app_id)
+        else {
+            return failed(app_id, transfer::TransferError::NotInProgress);
+        };
+        let entry_point = transfer.entry_point.clone();
+        let limits = transfer.limits;
+        match transfer.commit(digest) {
+            Ok(bytecode) => {
+                self.deploy(app_id, &bytecode, &entry_point, &limits)
+            },
+            Err(e) => failed(app_id, e),
+        }
+    }
+
+    /// Stops the application, interrupting it if it is running.
+    fn stop(&mut self, app_id: &str) -> ClientMessage {
+        self.application
+            .take_if(|application| application.id == app_id);
+        self.transfer.take_if(|transfer| transfer.app_id == app_id);
+        ClientMessage::ApplicationStopped {
+            app_id: app_id.into(),
+        }
+    }
+}
+
+fn failed(app_id: &str, error: impl ToString) -> ClientMessage {
+    ClientMessage::ApplicationFailed {
+        app_id: app_id.into(),
+        reason: error.to_string(),
+    }
+}
+
+/// Deploys and stops the applications requested by the server on a
+/// [`Runtime`], replying `ApplicationDeployed` as soon as the application
+/// is ready to run. Applications then run with [`Runtime::step`].
+impl<P: Platform> Handler for Runtime<P> {
+    async fn handle<'a>(
+        &'a mut self,
+        message: ServerMessageRef<'a>,
+    ) -> Option<ClientMessageRef<'a>> {
+        self.reply = self.on_message(message);
+        self.reply.as_ref().map(ClientMessage::borrowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+    use sha2::{Digest as _, Sha256};
+
+    const HELLO: &str = r#"(module
+        (import "wasmbed" "log" (func $log (param i32 i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 8) "Hello")
+        (func (export "main") (call $log (i32.const 8) (i32.const 5))))"#;
+
+    const SLEEPER: &str = r#"(module
+        (import "wasmbed" "sleep_ms" (func $sleep (param i32)))
+        (func (export "main") (loop (call $sleep (i32.const 1000)) (br 0))))"#;
+
+    #[derive(Default)]
+    struct Recorder {
+        logs: Vec<String>,
+        now: u64,
+    }
+
+    impl Platform for Recorder {
+        fn log(&mut self, message: &str) {
+            self.logs.push(message.into());
+        }
+
+        fn uptime_ms(&self) -> u64 {
+            self.now
+        }
+    }
+
+    fn runtime() -> Runtime<Recorder> {
+        Runtime::new(Recorder::default(), 1024 * 1024)
+    }
+
+    fn deploy(
+        runtime: &mut Runtime<Recorder>,
+        app_id: &str,
+        wat: &str,
+        limits: ResourceLimits,
+    ) -> ClientMessage {
+        let bytecode = wat::parse_str(wat).unwrap();
+        runtime
+            .on_message(ServerMessageRef::DeployApplication {
+                app_id,
+                bytecode: &bytecode,
+                entry_point: "main",
+                limits,
+            })
+            .unwrap()
+    }
+
+    /// Runs the application until it stops, skipping its sleeps.
+    fn run(runtime: &mut Runtime<Recorder>) -> ClientMessage {
+        loop {
+            if let Some(message) = runtime.step() {
+                return message;
+            }
+            runtime.platform.now = runtime.wake_at().unwrap();
+        }
+    }
+
+    /// Deploys and runs the module of `wat` as `app-0`.
+    fn deploy_and_run(
+        wat: &str,
+        limits: ResourceLimits,
+    ) -> (ClientMessage, Recorder) {
+        let mut runtime = runtime();
+        let reply = match deploy(&mut runtime, "app-0", wat, limits) {
+            ClientMessage::ApplicationDeployed { .. } => run(&mut runtime),
+            reply => reply,
+        };
+        (reply, runtime.platform)
+    }
+
+    fn failure(reply: ClientMessage) -> String {
+        match reply {
+            ClientMessage::ApplicationFailed { reason, .. } => reason,
+            reply => panic!("Unexpected reply {reply:?}"),
+        }
+    }
+
+    fn stopped(app_id: &str) -> ClientMessage {
+        ClientMessage::ApplicationStopped {
+            app_id: app_id.into(),
+        }
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let mut runtime = runtime();
+        runtime.platform.now = 42;
+        let reply = deploy(
+            &mut runtime,
+            "app-0",
+            r#"(module
+                (import "wasmbed" "log" (func $log (param i32 i32)))
+                (import "wasmbed" "uptime_ms" (func $uptime (result i64)))
+                (import "wasmbed" "sleep_ms" (func $sleep (param i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 8) "Hello")
+                (func (export "main")
+                    (call $log (i32.const 8) (i32.const 5))
+                    (call $sleep (i32.wrap_i64 (call $uptime)))
+                    (call $sleep (i32.const -1))))"#,
+            ResourceLimits::default(),
+        );
+        // Deployed before running.
+        assert_eq!(
+            reply,
+            ClientMessage::ApplicationDeployed {
+                app_id: "app-0".into()
+            }
+        );
+        assert!(runtime.platform.logs.is_empty());
+
+        assert_eq!(run(&mut runtime), stopped("app-0"));
+        assert_eq!(runtime.platform.logs, ["Hello"]);
+        assert_eq!(runtime.platform.now, 84);
+        assert_eq!(runtime.wake_at(), None);
+    }
+
+    #[test]
+    fn test_out_of_fuel() {
+        let (reply, _) = deploy_and_run(
+            r#"(module (func (export "main") (loop (br 0))))"#,
+            ResourceLimits {
+                max_memory_pages: None,
+                fuel: Some(1000),
+            },
+        );
+        assert!(failure(reply).contains("fuel"));
+    }
+
+    #[test]
+    fn test_trap() {
+        let (reply, _) = deploy_and_run(
+            r#"(module (func (export "main") unreachable))"#,
+            ResourceLimits::default(),
+        );
+        assert!(failure(reply).contains("unreachable"));
+    }
+
+    #[test]
+    fn test_log_out_of_bounds() {
+        let (reply, platform) = deploy_and_run(
+            r#"(module
+                (import "wasmbed" "log" (func $log (param i32 i32)))
+                (memory (export "memory") 1)
+                (func (export "main")
+                    (call $log (i32.const 65530) (i32.const 16))))"#,
+            ResourceLimits::default(),
+        );
+        assert!(failure(reply).contains("out of bounds"));
+        assert!(platform.logs.is_empty());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let (reply, _) = deploy_and_run(
+            r#"(module (memory 2) (func (export "main")))"#,
+            ResourceLimits {
+                max_memory_pages: Some(1),
+                fuel: None,
+            },
+        );
+        assert!(failure(reply).starts_with("Unable to instantiate"));
+
+        // The memory of the device bounds that of every application.
+        let mut runtime = Runtime::new(Recorder::default(), PAGE_SIZE);
+        let reply = deploy(
+            &mut runtime,
+            "app-0",
+            r#"(module (memory 2) (func (export "main")))"#,
+            ResourceLimits::default(),
+        );
+        assert!(failure(reply).starts_with("Unable to instantiate"));
+    }
+
+    #[test]
+    fn test_missing_entry_point() {
+        let (reply, _) = deploy_and_run(
+            r#"(module (func (export "start")))"#,
+            ResourceLimits::default(),
+        );
+        assert_eq!(failure(reply), "Entry point main not found");
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut runtime = runtime();
+        deploy(&mut runtime, "app-0", SLEEPER, ResourceLimits::default());
+        assert_eq!(runtime.step(), None);
+        assert_eq!(runtime.wake_at(), Some(1000));
+
+        let reply = deploy(&mut runtime, "app-1", HELLO, Default::default());
+        assert_eq!(failure(reply), "Application app-0 is already running");
+
+        let stop = ServerMessageRef::StopApplication { app_id: "app-0" };
+        assert_eq!(runtime.on_message(stop), Some(stopped("app-0")));
+        assert_eq!(runtime.wake_at(), None);
+        assert_eq!(runtime.step(), None);
+    }
+
+    #[test]
+    fn test_transfer() {
+        let bytecode = wat::parse_str(HELLO).unwrap();
+        let digest: Digest = Sha256::digest(&bytecode).into();
+        let size = u32::try_from(bytecode.len()).unwrap();
+        let (head, tail) = bytecode.split_at(16);
+        let half = u32::try_from(head.len()).unwrap();
+
+        let mut runtime = runtime();
+        let begin = ServerMessageRef::BeginTransfer {
+            app_id: "app-0",
+            size,
+            entry_point: "main",
+            limits: ResourceLimits::default(),
+        };
+        let chunk = |offset, data| ServerMessageRef::TransferChunk {
+            app_id: "app-0",
+            offset,
+            data,
+        };
+        let ack = |offset| ClientMessage::ChunkAck {
+            app_id: "app-0".into(),
+            offset,
+        };
+        let ready = |offset| ClientMessage::TransferReady {
+            app_id: "app-0".into(),
+            offset,
+        };
+
+        assert_eq!(runtime.on_message(begin.clone()), Some(ready(0)));
+        assert_eq!(runtime.on_message(chunk(0, head)), Some(ack(half)));
+        // Chunks sent again are ignored.
+        assert_eq!(runtime.on_message(chunk(0, head)), Some(ack(half)));
+        // The transfer resumes where it stopped.
+        assert_eq!(runtime.on_message(begin.clone()), Some(ready(half)));
+        assert_eq!(runtime.on_message(chunk(half, tail)), Some(ack(size)));
+
+        let commit = |digest| ServerMessageRef::CommitTransfer {
+            app_id: "app-0",
+            digest,
+        };
+        assert_eq!(
+            runtime.on_message(commit(digest)),
+            Some(ClientMessage::ApplicationDeployed {
+                app_id: "app-0".into()
+            })
+        );
+        assert_eq!(run(&mut runtime), stopped("app-0"));
+        assert_eq!(runtime.platform.logs, ["Hello"]);
+
+        runtime.on_message(begin);
+        runtime.on_message(chunk(0, &bytecode));
+        let reply = runtime.on_message(commit([0; 32])).unwrap();
+        assert_eq!(failure(reply), "Module digest mismatch");
+        let reply = runtime.on_message(chunk(0, &bytecode)).unwrap();
+        assert_eq!(
+            failure(reply),
+            "No transfer of the application in progress"
+        );
+    }
+
+    #[test]
+    fn test_transfer_too_large() {
+        let mut runtime = Runtime::new(Recorder::default(), 16);
+        let reply = runtime
+            .on_message(ServerMessageRef::BeginTransfer {
+                app_id: "app-0",
+                size: 17,
+                entry_point: "main",
+                limits: ResourceLimits::default(),
+            })
+            .unwrap();
+        assert_eq!(
+            failure(reply),
+            "Module of 17 bytes exceeds the memory of the device"
+        );
+    }
+}
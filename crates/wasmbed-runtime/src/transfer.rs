@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Reassembly of the modules transferred in chunks.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use derive_more::Display;
+use sha2::{Digest as _, Sha256};
+use wasmbed_protocol::{ApplicationId, Digest, ResourceLimits};
+
+#[derive(Debug, Display)]
+pub enum TransferError {
+    #[display("Module of {size} bytes exceeds the memory of the device")]
+    TooLarge {
+        size: u32,
+    },
+    #[display("No transfer of the application in progress")]
+    NotInProgress,
+    #[display("Chunk at offset {offset} ends beyond the module")]
+    Overflow {
+        offset: u32,
+    },
+    #[display("Module incomplete, received {received} of {size} bytes")]
+    Incomplete {
+        received: usize,
+        size: usize,
+    },
+    #[display("Module digest mismatch")]
+    DigestMismatch,
+}
+
+/// Module being received by the device.
+pub struct Transfer {
+    pub app_id: ApplicationId,
+    pub entry_point: String,
+    pub limits: ResourceLimits,
+    size: usize,
+    bytecode: Vec<u8>,
+}
+
+impl Transfer {
+    /// Starts receiving a module of `size` bytes, at most `memory` bytes.
+    pub fn new(
+        app_id: &str,
+        size: u32,
+        entry_point: &str,
+        limits: ResourceLimits,
+        memory: usize,
+    ) -> Result<Self, TransferError> {
+        let len = usize::try_from(size)
+            .ok()
+            .filter(|len| *len <= memory)
+            .ok_or(TransferError::TooLarge { size })?;
+        let mut bytecode = Vec::new();
+        bytecode
+            .try_reserve_exact(len)
+            .map_err(|_| TransferError::TooLarge { size })?;
+        Ok(Self {
+            app_id: app_id.into(),
+            entry_point: entry_point.into(),
+            limits,
+            size: len,
+            bytecode,
+        })
+    }
+
+    /// Whether the transfer is the one requested again by `BeginTransfer`,
+    /// in which case it resumes where it stopped.
+    pub fn is(
+        &self,
+        app_id: &str,
+        size: u32,
+        entry_point: &str,
+        limits: &ResourceLimits,
+    ) -> bool {
+        self.app_id == app_id
+            && usize::try_from(size).is_ok_and(|size| size == self.size)
+            && self.entry_point == entry_point
+            && self.limits == *limits
+    }
+
+    /// Number of module bytes received so far.
+    pub fn offset(&self) -> u32 {
+        u32::try_from(self.bytecode.len()).unwrap_or(u32::MAX)
+    }
+
+    /// Appends the chunk `data` starting at `offset`, returning the new
+    /// offset. Chunks not starting at the current offset, e.g. sent again
+    /// by the server, are ignored.
+    pub fn append(
+        &mut self,
+        offset: u32,
+        data: &[u8],
+    ) -> Result<u32, TransferError> {
+        if offset == self.offset() {
+            let remaining = self.size.saturating_sub(self.bytecode.len());
+            if data.len() > remaining {
+                return Err(TransferError::Overflow { offset });
+            }
+            self.bytecode.extend_from_slice(data);
+        }
+        Ok(self.offset())
+    }
+
+    /// Returns the module once complete and matching `digest`.
+    pub fn commit(self, digest: &Digest) -> Result<Vec<u8>, TransferError> {
+        if self.bytecode.len() != self.size {
+            return Err(TransferError::Incomplete {
+                received: self.bytecode.len(),
+                size: self.size,
+            });
+        }
+        if Sha256::digest(&self.bytecode).as_slice() != digest {
+            return Err(TransferError::DigestMismatch);
+        }
+        Ok(self.bytecode)
+    }
+}
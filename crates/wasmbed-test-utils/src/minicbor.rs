@@ -4,6 +4,8 @@
 use minicbor::encode::Encode;
 use minicbor::decode::Decode;
 
+// Panicking is the intended failure mode of a test assertion.
+#[allow(clippy::unwrap_used)]
 pub fn assert_encode_decode<T>(v: &T)
 where
     T: PartialEq + std::fmt::Debug + Encode<()> + for<'b> Decode<'b, ()>,
@@ -0,0 +1,45 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use core::str::FromStr;
+
+/// Resources class of a device, bounding the modules it can run.
+#[derive(
+    Clone, Copy, Debug, Default, Eq, Hash, PartialEq, derive_more::Display,
+)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum DeviceClass {
+    /// Microcontrollers with a few hundred KiB of memory
+    #[default]
+    #[display("small")]
+    Small,
+    /// Microcontrollers with external memory
+    #[display("medium")]
+    Medium,
+    /// Application processors
+    #[display("large")]
+    Large,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, derive_more::Display)]
+#[display("Unknown device class")]
+pub struct UnknownDeviceClass;
+
+impl core::error::Error for UnknownDeviceClass {}
+
+impl FromStr for DeviceClass {
+    type Err = UnknownDeviceClass;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "small" => Ok(Self::Small),
+            "medium" => Ok(Self::Medium),
+            "large" => Ok(Self::Large),
+            _ => Err(UnknownDeviceClass),
+        }
+    }
+}
@@ -2,24 +2,37 @@
 // Copyright © 2025 Wasmbed contributors
 
 use alloc::borrow::ToOwned;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 
 use k8s_openapi::api::core::v1::TypedObjectReference;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
-pub struct GatewayReference(
-    pub k8s_openapi::api::core::v1::TypedObjectReference,
-);
+pub struct GatewayReference {
+    #[serde(flatten)]
+    pub pod: TypedObjectReference,
+    /// UID of the gateway Pod, telling it apart from a Pod later created
+    /// with the same name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
+}
 
 impl GatewayReference {
     pub fn new(namespace: &str, name: &str) -> Self {
-        Self(TypedObjectReference {
-            api_group: None,
-            kind: "Pod".to_string(),
-            name: name.to_owned(),
-            namespace: Some(namespace.to_owned()),
-        })
+        Self {
+            pod: TypedObjectReference {
+                api_group: None,
+                kind: "Pod".to_string(),
+                name: name.to_owned(),
+                namespace: Some(namespace.to_owned()),
+            },
+            uid: None,
+        }
+    }
+
+    pub fn with_uid(mut self, uid: &str) -> Self {
+        self.uid = Some(uid.to_owned());
+        self
     }
 }
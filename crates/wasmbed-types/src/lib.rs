@@ -12,11 +12,15 @@ extern crate std;
 #[cfg(feature = "cert")]
 mod cert;
 
+mod device_class;
+
 #[cfg(feature = "k8s")]
 mod k8s;
 
 #[cfg(feature = "cert")]
 pub use cert::PublicKey;
 
+pub use device_class::{DeviceClass, UnknownDeviceClass};
+
 #[cfg(feature = "k8s")]
 pub use k8s::GatewayReference;
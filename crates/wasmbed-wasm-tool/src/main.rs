@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+
+use wasmbed_types::DeviceClass;
+use wasmbed_wasm::{Policy, validate};
+
+#[derive(Parser)]
+#[command(disable_help_subcommand = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check that a Wasm module can be deployed on a class of devices.
+    Validate {
+        /// Path to the Wasm module.
+        module: PathBuf,
+        /// Class of the target devices: small, medium or large.
+        #[arg(long, default_value_t = DeviceClass::default())]
+        device_class: DeviceClass,
+        /// Name of the exported function invoked to start the application.
+        #[arg(long, default_value = "_start")]
+        entry_point: String,
+        /// Maximum number of 64 KiB linear memory pages.
+        #[arg(long)]
+        max_memory_pages: Option<u32>,
+    },
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    match args.command {
+        Command::Validate {
+            module,
+            device_class,
+            entry_point,
+            max_memory_pages,
+        } => {
+            let bytecode = std::fs::read(&module).with_context(|| {
+                format!("Failed to read Wasm module from {}", module.display())
+            })?;
+            let policy = Policy::for_class(device_class)
+                .limit_memory_pages(max_memory_pages);
+            let info = validate(&bytecode, &entry_point, &policy)?;
+
+            println!("Memory pages: {}", info.memory_pages);
+            println!("Code size: {} bytes", info.code_size);
+            println!("Imports: {}", info.imports.join(", "));
+            println!("Exports: {}", info.exports.join(", "));
+        },
+    }
+
+    Ok(())
+}
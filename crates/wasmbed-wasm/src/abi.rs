@@ -0,0 +1,113 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use std::fmt;
+
+/// Module from which applications import the host functions.
+pub const HOST_MODULE: &str = "wasmbed";
+
+/// Host functions provided by the device firmware.
+pub const HOST_ABI: &[HostFunction] = &[
+    // Writes the UTF-8 string at `ptr` of `len` bytes to the device log.
+    HostFunction {
+        module: HOST_MODULE,
+        name: "log",
+        params: &[ValueType::I32, ValueType::I32],
+        results: &[],
+    },
+    // Milliseconds elapsed since the device booted.
+    HostFunction {
+        module: HOST_MODULE,
+        name: "uptime_ms",
+        params: &[],
+        results: &[ValueType::I64],
+    },
+    // Suspends the application for the given number of milliseconds.
+    HostFunction {
+        module: HOST_MODULE,
+        name: "sleep_ms",
+        params: &[ValueType::I32],
+        results: &[],
+    },
+];
+
+/// Value types of the host function signatures
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValueType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValueType {
+    pub(crate) fn from_wasm(value: wasmparser::ValType) -> Option<Self> {
+        match value {
+            wasmparser::ValType::I32 => Some(Self::I32),
+            wasmparser::ValType::I64 => Some(Self::I64),
+            wasmparser::ValType::F32 => Some(Self::F32),
+            wasmparser::ValType::F64 => Some(Self::F64),
+            wasmparser::ValType::V128 | wasmparser::ValType::Ref(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        })
+    }
+}
+
+/// A function applications can import from the device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HostFunction {
+    pub module: &'static str,
+    pub name: &'static str,
+    pub params: &'static [ValueType],
+    pub results: &'static [ValueType],
+}
+
+impl HostFunction {
+    /// Whether a function of type `ty` can be bound to this function.
+    pub(crate) fn matches(&self, ty: &wasmparser::FuncType) -> bool {
+        fn same(
+            expected: &[ValueType],
+            actual: &[wasmparser::ValType],
+        ) -> bool {
+            expected.len() == actual.len()
+                && expected.iter().zip(actual).all(|(expected, actual)| {
+                    ValueType::from_wasm(*actual) == Some(*expected)
+                })
+        }
+
+        same(self.params, ty.params()) && same(self.results, ty.results())
+    }
+}
+
+impl fmt::Display for HostFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(
+            f: &mut fmt::Formatter<'_>,
+            types: &[ValueType],
+        ) -> fmt::Result {
+            f.write_str("(")?;
+            for (i, ty) in types.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{ty}")?;
+            }
+            f.write_str(")")
+        }
+
+        write!(f, "{}.{}", self.module, self.name)?;
+        list(f, self.params)?;
+        f.write_str(" -> ")?;
+        list(f, self.results)
+    }
+}
@@ -0,0 +1,13 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+//! Validation of the Wasm modules deployed on devices, against the
+//! proposals, host functions and resources the devices support.
+
+mod abi;
+mod policy;
+mod validate;
+
+pub use abi::{HOST_ABI, HOST_MODULE, HostFunction, ValueType};
+pub use policy::Policy;
+pub use validate::{ModuleInfo, ValidationError, host_modules, validate};
@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use wasmbed_types::DeviceClass;
+
+use crate::abi::{HOST_ABI, HostFunction};
+
+/// What a device accepts to run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    /// Largest initial linear memory, in 64 KiB pages
+    pub max_memory_pages: u32,
+    /// Largest code section, in bytes
+    pub max_code_size: u32,
+    /// Functions modules can import
+    pub host_functions: Vec<HostFunction>,
+}
+
+impl Policy {
+    /// Default policy of the devices of `class`, providing the whole
+    /// [`HOST_ABI`].
+    pub fn for_class(class: DeviceClass) -> Self {
+        let (max_memory_pages, max_code_size) = match class {
+            DeviceClass::Small => (1, 64 * 1024),
+            DeviceClass::Medium => (16, 512 * 1024),
+            DeviceClass::Large => (256, 8 * 1024 * 1024),
+        };
+
+        Self {
+            max_memory_pages,
+            max_code_size,
+            host_functions: HOST_ABI.to_vec(),
+        }
+    }
+
+    /// Restricts the host functions to those of `modules`, if set.
+    pub fn restrict_host_modules(mut self, modules: Option<&[String]>) -> Self {
+        if let Some(modules) = modules {
+            self.host_functions
+                .retain(|f| modules.iter().any(|module| module == f.module));
+        }
+        self
+    }
+
+    /// Lowers the memory limit to `pages`, if set and lower.
+    pub fn limit_memory_pages(mut self, pages: Option<u32>) -> Self {
+        if let Some(pages) = pages {
+            self.max_memory_pages = self.max_memory_pages.min(pages);
+        }
+        self
+    }
+}
@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: AGPL-3.0
+// Copyright © 2025 Wasmbed contributors
+
+use derive_more::{Display, Error};
+use wasmparser::{
+    BinaryReaderError, ExternalKind, FuncType, Parser, Payload, TypeRef,
+    Validator, WasmFeatures,
+};
+
+use crate::abi::HostFunction;
+use crate::policy::Policy;
+
+/// Proposals the device runtimes support: WebAssembly 2.0 without SIMD.
+/// Threads, GC and the other later proposals are rejected too.
+const FEATURES: WasmFeatures = WasmFeatures::WASM2
+    .difference(WasmFeatures::SIMD)
+    .difference(WasmFeatures::RELAXED_SIMD);
+
+#[derive(Debug, Display, Error, PartialEq)]
+pub enum ValidationError {
+    #[display("Invalid module: {_0}")]
+    Invalid(#[error(not(source))] String),
+    #[display("Import {module}.{name} is not provided by the device")]
+    UnsupportedImport {
+        module: String,
+        name: String,
+    },
+    #[display("Import {module}.{name} is not a function")]
+    NotAFunction {
+        module: String,
+        name: String,
+    },
+    #[display("Import {module}.{name} does not match {expected}")]
+    ImportSignature {
+        module: String,
+        name: String,
+        expected: HostFunction,
+    },
+    #[display("Module requires {pages} memory pages, the limit is {limit}")]
+    TooManyMemoryPages {
+        pages: u64,
+        limit: u32,
+    },
+    #[display("Code size is {size} bytes, the limit is {limit}")]
+    CodeTooLarge {
+        size: u32,
+        limit: u32,
+    },
+    #[display("Entry point {_0} is not an exported function")]
+    MissingEntryPoint(#[error(not(source))] String),
+    #[display("Entry point {_0} must take no parameters and return nothing")]
+    EntryPointSignature(#[error(not(source))] String),
+}
+
+impl From<BinaryReaderError> for ValidationError {
+    fn from(error: BinaryReaderError) -> Self {
+        Self::Invalid(error.to_string())
+    }
+}
+
+/// What a valid module requires from the device.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModuleInfo {
+    /// Initial size of the linear memory, in 64 KiB pages
+    pub memory_pages: u64,
+    /// Size of the code section, in bytes
+    pub code_size: u32,
+    /// Imported host functions, as `module.name`
+    pub imports: Vec<String>,
+    /// Exported functions
+    pub exports: Vec<String>,
+}
+
+/// Checks that `bytecode` is a module the devices `policy` applies to can
+/// run, starting from `entry_point`.
+pub fn validate(
+    bytecode: &[u8],
+    entry_point: &str,
+    policy: &Policy,
+) -> Result<ModuleInfo, ValidationError> {
+    Validator::new_with_features(FEATURES).validate_all(bytecode)?;
+
+    let mut info = ModuleInfo::default();
+    let mut types: Vec<FuncType> = Vec::new();
+    // Type index of each function, imported ones first
+    let mut functions: Vec<u32> = Vec::new();
+    let mut entry = None;
+
+    for payload in Parser::new(0).parse_all(bytecode) {
+        match payload? {
+            Payload::TypeSection(reader) => {
+                for ty in reader.into_iter_err_on_gc_types() {
+                    types.push(ty?);
+                }
+            },
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    let import = import?;
+                    let (TypeRef::Func(index) | TypeRef::FuncExact(index)) =
+                        import.ty
+                    else {
+                        return Err(ValidationError::NotAFunction {
+                            module: import.module.to_string(),
+                            name: import.name.to_string(),
+                        });
+                    };
+
+                    let host = policy
+                        .host_functions
+                        .iter()
+                        .find(|f| {
+                            f.module == import.module && f.name == import.name
+                        })
+                        .ok_or_else(|| ValidationError::UnsupportedImport {
+                            module: import.module.to_string(),
+                            name: import.name.to_string(),
+                        })?;
+                    if !func_type(&types, index)
+                        .is_some_and(|ty| host.matches(ty))
+                    {
+                        return Err(ValidationError::ImportSignature {
+                            module: import.module.to_string(),
+                            name: import.name.to_string(),
+                            expected: *host,
+                        });
+                    }
+
+                    functions.push(index);
+                    info.imports
+                        .push(format!("{}.{}", import.module, import.name));
+                }
+            },
+            Payload::FunctionSection(reader) => {
+                for index in reader {
+                    functions.push(index?);
+                }
+            },
+            Payload::MemorySection(reader) => {
+                for memory in reader {
+                    info.memory_pages = info.memory_pages.max(memory?.initial);
+                }
+            },
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export?;
+                    if export.kind != ExternalKind::Func {
+                        continue;
+                    }
+                    if export.name == entry_point {
+                        entry = Some(export.index);
+                    }
+                    info.exports.push(export.name.to_string());
+                }
+            },
+            Payload::CodeSectionStart { size, .. } => info.code_size = size,
+            _ => {},
+        }
+    }
+
+    if info.memory_pages > u64::from(policy.max_memory_pages) {
+        return Err(ValidationError::TooManyMemoryPages {
+            pages: info.memory_pages,
+            limit: policy.max_memory_pages,
+        });
+    }
+    if info.code_size > policy.max_code_size {
+        return Err(ValidationError::CodeTooLarge {
+            size: info.code_size,
+            limit: policy.max_code_size,
+        });
+    }
+
+    let entry = entry.ok_or_else(|| {
+        ValidationError::MissingEntryPoint(entry_point.to_string())
+    })?;
+    let entry_type = usize::try_from(entry)
+        .ok()
+        .and_then(|entry| functions.get(entry))
+        .and_then(|index| func_type(&types, *index));
+    if !entry_type
+        .is_some_and(|ty| ty.params().is_empty() && ty.results().is_empty())
+    {
+        return Err(ValidationError::EntryPointSignature(
+            entry_point.to_string(),
+        ));
+    }
+
+    Ok(info)
+}
+
+/// Modules from which `bytecode` imports, without validating it otherwise.
+pub fn host_modules(bytecode: &[u8]) -> Result<Vec<String>, ValidationError> {
+    let mut modules: Vec<String> = Vec::new();
+    for payload in Parser::new(0).parse_all(bytecode) {
+        if let Payload::ImportSection(reader) = payload? {
+            for import in reader {
+                let import = import?;
+                if !modules.iter().any(|module| module == import.module) {
+                    modules.push(import.module.to_string());
+                }
+            }
+        }
+    }
+    Ok(modules)
+}
+
+fn func_type(types: &[FuncType], index: u32) -> Option<&FuncType> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| types.get(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmbed_types::DeviceClass;
+
+    fn check(source: &str) -> Result<ModuleInfo, ValidationError> {
+        let bytecode = wat::parse_str(source).unwrap();
+        validate(&bytecode, "_start", &Policy::for_class(DeviceClass::Small))
+    }
+
+    #[test]
+    fn test_valid_module() {
+        let info = check(
+            r#"(module
+                (import "wasmbed" "log" (func $log (param i32 i32)))
+                (memory 1)
+                (data (i32.const 0) "hello")
+                (func (export "_start")
+                    (call $log (i32.const 0) (i32.const 5))))"#,
+        )
+        .unwrap();
+        assert_eq!(info.memory_pages, 1);
+        assert_eq!(info.imports, ["wasmbed.log"]);
+        assert_eq!(info.exports, ["_start"]);
+    }
+
+    #[test]
+    fn test_host_modules() {
+        let bytecode = wat::parse_str(
+            r#"(module
+                (import "wasmbed" "log" (func (param i32 i32)))
+                (import "env" "f" (func))
+                (import "wasmbed" "uptime_ms" (func (result i64))))"#,
+        )
+        .unwrap();
+        assert_eq!(host_modules(&bytecode).unwrap(), ["wasmbed", "env"]);
+    }
+
+    #[test]
+    fn test_rejects_unsupported_proposals() {
+        let simd = check(
+            r#"(module
+                (func (export "_start")
+                    (drop (v128.const i64x2 0 0))))"#,
+        );
+        assert!(matches!(simd, Err(ValidationError::Invalid(_))));
+
+        let threads = check(r#"(module (memory 1 1 shared))"#);
+        assert!(matches!(threads, Err(ValidationError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_rejects_unknown_imports() {
+        assert_eq!(
+            check(
+                r#"(module
+                    (import "wasi_snapshot_preview1" "fd_write"
+                        (func (param i32 i32 i32 i32) (result i32)))
+                    (func (export "_start")))"#
+            ),
+            Err(ValidationError::UnsupportedImport {
+                module: "wasi_snapshot_preview1".into(),
+                name: "fd_write".into(),
+            })
+        );
+
+        let signature = check(
+            r#"(module
+                (import "wasmbed" "log" (func (param i64)))
+                (func (export "_start")))"#,
+        );
+        assert!(matches!(
+            signature,
+            Err(ValidationError::ImportSignature { .. })
+        ));
+
+        let bytecode = wat::parse_str(
+            r#"(module
+                (import "wasmbed" "uptime_ms" (func (result i64)))
+                (func (export "_start")))"#,
+        )
+        .unwrap();
+        let policy = Policy::for_class(DeviceClass::Small)
+            .restrict_host_modules(Some(&[]));
+        assert!(matches!(
+            validate(&bytecode, "_start", &policy),
+            Err(ValidationError::UnsupportedImport { .. })
+        ));
+    }
+
+    #[test]
+    fn test_enforces_limits() {
+        assert_eq!(
+            check(r#"(module (memory 2) (func (export "_start")))"#),
+            Err(ValidationError::TooManyMemoryPages { pages: 2, limit: 1 })
+        );
+
+        let bytecode =
+            wat::parse_str(r#"(module (memory 1) (func (export "_start")))"#)
+                .unwrap();
+        let policy =
+            Policy::for_class(DeviceClass::Large).limit_memory_pages(Some(0));
+        assert!(matches!(
+            validate(&bytecode, "_start", &policy),
+            Err(ValidationError::TooManyMemoryPages { .. })
+        ));
+    }
+
+    #[test]
+    fn test_checks_entry_point() {
+        assert_eq!(
+            check(r#"(module (func (export "main")))"#),
+            Err(ValidationError::MissingEntryPoint("_start".into()))
+        );
+        assert_eq!(
+            check(r#"(module (func (export "_start") (param i32)))"#),
+            Err(ValidationError::EntryPointSignature("_start".into()))
+        );
+    }
+}
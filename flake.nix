@@ -131,6 +131,10 @@
         pkgs.qemu
         pkgs.socat
         self.packages.${system}.defmt-print
+      ] ++ lib.optionals pkgs.stdenv.isLinux [
+        # SLIP link between the QEMU firmware and the host
+        pkgs.iproute2
+        pkgs.nettools
       ];
     };
 
@@ -142,5 +146,14 @@
         ];
       };
     };
+
+    dockerImages.wasmbed-controller = pkgs.dockerTools.buildLayeredImage {
+      name = "wasmbed-controller";
+      config = {
+        Cmd = [
+          (lib.meta.getExe self.packages.${system}.wasmbed-k8s-controller)
+        ];
+      };
+    };
   });
 }